@@ -0,0 +1,444 @@
+#![allow(dead_code)]
+
+// A B+tree map laid out for the cache.
+//
+// Nodes are not separate allocations: they are slots in a few flat arrays,
+// one per field, and refer to each other by index. The keys of a node are
+// contiguous, apart from its child indexes or values, so a search inside a
+// node only touches keys. Inner nodes and leaves live in arrays of their
+// own, the height of the tree tells which one a child index is for.
+//
+//   inner node i: keys     inner_keys[i * MAX_KEYS..][..inner_lens[i]]
+//                 children inner_children[i * (MAX_KEYS + 1)..]
+//   leaf i:       keys     leaf_keys[i * MAX_KEYS..][..leaf_lens[i]]
+//                 values   leaf_vals[i * MAX_KEYS..]
+//                 leaf_next[i], the leaf to the right, for scans
+//
+// children[j] holds the keys in [keys[j - 1], keys[j]). Keys are found
+// with a branchless binary search. Nodes are never merged or freed, a
+// remove just takes the key out of its leaf, like in `blink`.
+
+use std::ops::{Bound, RangeBounds};
+
+const MAX_KEYS: usize = 32;
+const NIL: u32 = u32::MAX;
+
+// the number of keys less than `key`, or not greater if `inclusive`.
+// The loop has a fixed number of rounds for a given length and the
+// comparison only selects the next base, which compiles to a conditional
+// move instead of a hard to predict branch.
+fn search<K: Ord>(keys: &[K], key: &K, inclusive: bool) -> usize {
+    if keys.is_empty() {
+        return 0;
+    }
+    let below = |k: &K| if inclusive { k <= key } else { k < key };
+    let mut base = 0;
+    let mut size = keys.len();
+    while size > 1 {
+        let half = size / 2;
+        base = if below(&keys[base + half]) { base + half } else { base };
+        size -= half;
+    }
+    base + below(&keys[base]) as usize
+}
+
+#[derive(Debug, Clone)]
+pub struct ArenaTree<K, V> {
+    inner_keys: Vec<K>,
+    inner_children: Vec<u32>,
+    inner_lens: Vec<u16>,
+    leaf_keys: Vec<K>,
+    leaf_vals: Vec<V>,
+    leaf_lens: Vec<u16>,
+    leaf_next: Vec<u32>,
+    root: u32,
+    // the number of inner levels, 0 while the root is a leaf
+    height: usize,
+    len: usize,
+}
+
+impl<K: Ord + Clone + Default, V: Clone + Default> Default for ArenaTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone + Default, V: Clone + Default> ArenaTree<K, V> {
+    pub fn new() -> Self {
+        let mut tree = Self {
+            inner_keys: Vec::new(),
+            inner_children: Vec::new(),
+            inner_lens: Vec::new(),
+            leaf_keys: Vec::new(),
+            leaf_vals: Vec::new(),
+            leaf_lens: Vec::new(),
+            leaf_next: Vec::new(),
+            root: 0,
+            height: 0,
+            len: 0,
+        };
+        tree.root = tree.new_leaf();
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn new_leaf(&mut self) -> u32 {
+        let id = self.leaf_lens.len();
+        self.leaf_keys.resize((id + 1) * MAX_KEYS, K::default());
+        self.leaf_vals.resize((id + 1) * MAX_KEYS, V::default());
+        self.leaf_lens.push(0);
+        self.leaf_next.push(NIL);
+        id as u32
+    }
+
+    fn new_inner(&mut self) -> u32 {
+        let id = self.inner_lens.len();
+        self.inner_keys.resize((id + 1) * MAX_KEYS, K::default());
+        self.inner_children.resize((id + 1) * (MAX_KEYS + 1), NIL);
+        self.inner_lens.push(0);
+        id as u32
+    }
+
+    fn inner_keys(&self, node: u32) -> &[K] {
+        let base = node as usize * MAX_KEYS;
+        &self.inner_keys[base..base + self.inner_lens[node as usize] as usize]
+    }
+
+    fn child(&self, node: u32, i: usize) -> u32 {
+        self.inner_children[node as usize * (MAX_KEYS + 1) + i]
+    }
+
+    fn leaf_keys(&self, leaf: u32) -> &[K] {
+        let base = leaf as usize * MAX_KEYS;
+        &self.leaf_keys[base..base + self.leaf_lens[leaf as usize] as usize]
+    }
+
+    // the leaf that holds `key` if anything does, and the inner nodes on
+    // the way down with the child taken in each, when `path` is given
+    fn descend(&self, key: &K, mut path: Option<&mut Vec<(u32, usize)>>) -> u32 {
+        let mut node = self.root;
+        for _ in 0..self.height {
+            let i = search(self.inner_keys(node), key, true);
+            if let Some(path) = path.as_mut() {
+                path.push((node, i));
+            }
+            node = self.child(node, i);
+        }
+        node
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let leaf = self.descend(key, None);
+        let keys = self.leaf_keys(leaf);
+        let i = search(keys, key, false);
+        match i < keys.len() && keys[i] == *key {
+            true => Some(&self.leaf_vals[leaf as usize * MAX_KEYS + i]),
+            false => None,
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    // insert or replace, returns the value replaced
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let mut path = Vec::with_capacity(self.height);
+        let mut leaf = self.descend(&key, Some(&mut path));
+        let mut i = search(self.leaf_keys(leaf), &key, false);
+        if i < self.leaf_lens[leaf as usize] as usize && self.leaf_keys(leaf)[i] == key {
+            let slot = &mut self.leaf_vals[leaf as usize * MAX_KEYS + i];
+            return Some(std::mem::replace(slot, val));
+        }
+        if self.leaf_lens[leaf as usize] as usize == MAX_KEYS {
+            let right = self.split_leaf(leaf);
+            let sep = self.leaf_keys(right)[0].clone();
+            if key >= sep {
+                leaf = right;
+                i -= MAX_KEYS / 2;
+            }
+            self.insert_into_parent(path, sep, right);
+        }
+
+        let (base, n) = (leaf as usize * MAX_KEYS, self.leaf_lens[leaf as usize] as usize);
+        self.leaf_keys[base + i..=base + n].rotate_right(1);
+        self.leaf_vals[base + i..=base + n].rotate_right(1);
+        self.leaf_keys[base + i] = key;
+        self.leaf_vals[base + i] = val;
+        self.leaf_lens[leaf as usize] += 1;
+        self.len += 1;
+        None
+    }
+
+    // move the upper half of a full leaf into a new leaf to its right
+    fn split_leaf(&mut self, leaf: u32) -> u32 {
+        let right = self.new_leaf();
+        let (from, to) = (leaf as usize * MAX_KEYS, right as usize * MAX_KEYS);
+        let half = MAX_KEYS / 2;
+        for j in half..MAX_KEYS {
+            self.leaf_keys[to + j - half] = std::mem::take(&mut self.leaf_keys[from + j]);
+            self.leaf_vals[to + j - half] = std::mem::take(&mut self.leaf_vals[from + j]);
+        }
+        self.leaf_lens[leaf as usize] = half as u16;
+        self.leaf_lens[right as usize] = (MAX_KEYS - half) as u16;
+        self.leaf_next[right as usize] = self.leaf_next[leaf as usize];
+        self.leaf_next[leaf as usize] = right;
+        right
+    }
+
+    // add `sep` and the new node `right` to the right of the child taken
+    // last on `path`, splitting inner nodes up to a new root as needed
+    fn insert_into_parent(&mut self, mut path: Vec<(u32, usize)>, mut sep: K, mut right: u32) {
+        while let Some((node, i)) = path.pop() {
+            let n = self.inner_lens[node as usize] as usize;
+            let kbase = node as usize * MAX_KEYS;
+            let cbase = node as usize * (MAX_KEYS + 1);
+            if n < MAX_KEYS {
+                self.inner_keys[kbase + i..=kbase + n].rotate_right(1);
+                self.inner_keys[kbase + i] = sep;
+                self.inner_children[cbase + i + 1..=cbase + n + 1].rotate_right(1);
+                self.inner_children[cbase + i + 1] = right;
+                self.inner_lens[node as usize] += 1;
+                return;
+            }
+
+            // a full node: lay out all keys and children with the new ones,
+            // keep the lower half, move the upper half to a new node and the
+            // key in the middle up
+            let mut keys: Vec<K> = self.inner_keys[kbase..kbase + n].to_vec();
+            let mut children: Vec<u32> = self.inner_children[cbase..=cbase + n].to_vec();
+            keys.insert(i, sep);
+            children.insert(i + 1, right);
+            let half = keys.len() / 2;
+            let new = self.new_inner();
+            let (nk, nc) = (new as usize * MAX_KEYS, new as usize * (MAX_KEYS + 1));
+            for (j, key) in keys.drain(half + 1..).enumerate() {
+                self.inner_keys[nk + j] = key;
+            }
+            for (j, child) in children.drain(half + 1..).enumerate() {
+                self.inner_children[nc + j] = child;
+            }
+            self.inner_lens[new as usize] = (MAX_KEYS - half) as u16;
+            sep = keys.pop().unwrap();
+            for (j, key) in keys.into_iter().enumerate() {
+                self.inner_keys[kbase + j] = key;
+            }
+            for (j, child) in children.into_iter().enumerate() {
+                self.inner_children[cbase + j] = child;
+            }
+            self.inner_lens[node as usize] = half as u16;
+            right = new;
+        }
+
+        let root = self.new_inner();
+        self.inner_keys[root as usize * MAX_KEYS] = sep;
+        self.inner_children[root as usize * (MAX_KEYS + 1)] = self.root;
+        self.inner_children[root as usize * (MAX_KEYS + 1) + 1] = right;
+        self.inner_lens[root as usize] = 1;
+        self.root = root;
+        self.height += 1;
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let leaf = self.descend(key, None);
+        let keys = self.leaf_keys(leaf);
+        let i = search(keys, key, false);
+        if i == keys.len() || keys[i] != *key {
+            return None;
+        }
+        let (base, n) = (leaf as usize * MAX_KEYS, keys.len());
+        self.leaf_keys[base + i..base + n].rotate_left(1);
+        self.leaf_vals[base + i..base + n].rotate_left(1);
+        self.leaf_lens[leaf as usize] -= 1;
+        self.len -= 1;
+        Some(std::mem::take(&mut self.leaf_vals[base + n - 1]))
+    }
+
+    // the entries within `range` in key order, walking the leaves
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        let (leaf, pos) = match range.start_bound() {
+            Bound::Included(key) => {
+                let leaf = self.descend(key, None);
+                (leaf, search(self.leaf_keys(leaf), key, false))
+            }
+            Bound::Excluded(key) => {
+                let leaf = self.descend(key, None);
+                (leaf, search(self.leaf_keys(leaf), key, true))
+            }
+            Bound::Unbounded => {
+                let mut node = self.root;
+                for _ in 0..self.height {
+                    node = self.child(node, 0);
+                }
+                (node, 0)
+            }
+        };
+        Range {
+            tree: self,
+            leaf,
+            pos,
+            end: match range.end_bound() {
+                Bound::Included(key) => Bound::Included(key.clone()),
+                Bound::Excluded(key) => Bound::Excluded(key.clone()),
+                Bound::Unbounded => Bound::Unbounded,
+            },
+        }
+    }
+
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range(..)
+    }
+}
+
+pub struct Range<'a, K, V> {
+    tree: &'a ArenaTree<K, V>,
+    leaf: u32,
+    pos: usize,
+    end: Bound<K>,
+}
+
+impl<'a, K: Ord + Clone + Default, V: Clone + Default> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.tree;
+        while self.leaf != NIL {
+            let keys = tree.leaf_keys(self.leaf);
+            if self.pos < keys.len() {
+                let key = &keys[self.pos];
+                let past = match &self.end {
+                    Bound::Included(end) => key > end,
+                    Bound::Excluded(end) => key >= end,
+                    Bound::Unbounded => false,
+                };
+                if past {
+                    self.leaf = NIL;
+                    return None;
+                }
+                let val = &tree.leaf_vals[self.leaf as usize * MAX_KEYS + self.pos];
+                self.pos += 1;
+                return Some((key, val));
+            }
+            self.leaf = tree.leaf_next[self.leaf as usize];
+            self.pos = 0;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::index::conformance::rng;
+    use std::collections::BTreeMap;
+    use std::time::Instant;
+
+    // keys are ordered and within the bounds their parents set, and every
+    // leaf is at the same depth
+    fn check(tree: &ArenaTree<u64, u64>, node: u32, depth: usize, low: Option<u64>, high: Option<u64>) {
+        let keys = match depth == tree.height {
+            true => tree.leaf_keys(node),
+            false => tree.inner_keys(node),
+        };
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(keys.iter().all(|&k| low.is_none_or(|l| k >= l) && high.is_none_or(|h| k < h)));
+        if depth < tree.height {
+            assert!(!keys.is_empty());
+            for i in 0..=keys.len() {
+                let low = if i == 0 { low } else { Some(keys[i - 1]) };
+                let high = if i == keys.len() { high } else { Some(keys[i]) };
+                check(tree, tree.child(node, i), depth + 1, low, high);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let keys = [1, 3, 3, 5, 7];
+        for key in 0..9 {
+            assert_eq!(search(&keys, &key, false), keys.partition_point(|&k| k < key));
+            assert_eq!(search(&keys, &key, true), keys.partition_point(|&k| k <= key));
+        }
+        assert_eq!(search(&[] as &[u64], &1, false), 0);
+    }
+
+    #[test]
+    fn test_random() {
+        let mut tree = ArenaTree::new();
+        let mut expected = BTreeMap::new();
+        let mut next = rng(21);
+        for step in 0..50_000u64 {
+            let key = next() % 5000;
+            if next().is_multiple_of(4) {
+                assert_eq!(tree.remove(&key), expected.remove(&key));
+            } else {
+                assert_eq!(tree.insert(key, step), expected.insert(key, step));
+            }
+        }
+        check(&tree, tree.root, 0, None, None);
+        assert!(tree.height >= 2);
+        assert_eq!(tree.len(), expected.len());
+        assert!(tree.iter().eq(expected.iter()));
+        for key in 0..5000 {
+            assert_eq!(tree.get(&key), expected.get(&key));
+        }
+        assert!(tree.range(1000..2000).eq(expected.range(1000..2000)));
+        assert!(tree.range(..=17).eq(expected.range(..=17)));
+        let from = (Bound::Excluded(4000), Bound::Unbounded);
+        assert!(tree.range(from).eq(expected.range(from)));
+    }
+
+    // against `BTreeMap`, run with
+    //   cargo test --release bench_vs_btreemap -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_vs_btreemap() {
+        const N: u64 = 1_000_000;
+        let mut next = rng(77);
+        let keys: Vec<u64> = (0..N).map(|_| next()).collect();
+
+        let start = Instant::now();
+        let mut tree = ArenaTree::new();
+        for &key in &keys {
+            tree.insert(key, key);
+        }
+        let tree_insert = start.elapsed();
+        let start = Instant::now();
+        let mut map = BTreeMap::new();
+        for &key in &keys {
+            map.insert(key, key);
+        }
+        let map_insert = start.elapsed();
+
+        let start = Instant::now();
+        for &key in &keys {
+            assert!(std::hint::black_box(tree.get(&key)).is_some());
+        }
+        let tree_get = start.elapsed();
+        let start = Instant::now();
+        for &key in &keys {
+            assert!(std::hint::black_box(map.get(&key)).is_some());
+        }
+        let map_get = start.elapsed();
+
+        let start = Instant::now();
+        let tree_sum = tree.iter().fold(0u64, |s, (_, v)| s.wrapping_add(*v));
+        let tree_scan = start.elapsed();
+        let start = Instant::now();
+        let map_sum = map.iter().fold(0u64, |s, (_, v)| s.wrapping_add(*v));
+        let map_scan = start.elapsed();
+        assert_eq!(tree_sum, map_sum);
+
+        println!("{} random u64 keys   ArenaTree   BTreeMap", N);
+        for (name, a, b) in [("insert", tree_insert, map_insert), ("get", tree_get, map_get), ("scan", tree_scan, map_scan)] {
+            println!("{:<20} {:>9.1?} {:>10.1?}", name, a, b);
+        }
+    }
+}
@@ -0,0 +1,489 @@
+#![allow(dead_code)]
+
+// A concurrent B+tree following Lehman and Yao, "Efficient Locking for
+// Concurrent Operations on B-Trees"(1981), the design of PostgreSQL's nbtree.
+//
+// Every node has a high key, the upper bound of the keys below it, and a
+// link to its right sibling on the same level. A split moves the upper
+// half of a node to a new right sibling and links it in before the parent
+// learns about it. So a reader that reaches a node after the key it looks
+// for moved away sees the key above the high key and follows the right
+// link, no latch is held on more than one node at a time:
+//
+//   search: descend from the root with shared latches, moving right while
+//     the key is above the high key, one latch at a time
+//   insert: descend like search remembering the nodes passed on each level,
+//     latch the leaf exclusively(moving right if needed) and insert, if it
+//     overflows split it and insert the separator into the remembered
+//     parent(moving right from it, the parent may have split as well)
+//   delete: latch the leaf exclusively and remove the key. Nodes are never
+//     merged, a node emptied by deletes stays in the tree like an empty
+//     nbtree page until VACUUM(not done here).
+
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+const DEFAULT_MAX_KEYS: usize = 64;
+
+type NodeRef<K> = Arc<RwLock<Node<K>>>;
+
+#[derive(Debug)]
+struct Node<K> {
+    // 0 for leaves
+    level: usize,
+    // the keys of a leaf, or the separators of an internal node:
+    // children[i] holds the keys in (keys[i - 1], keys[i]], the last child
+    // the keys in (keys.last(), high_key]
+    keys: Vec<K>,
+    children: Vec<NodeRef<K>>,
+    // every key of the node and its subtree is <= high_key,
+    // `None` for the rightmost node of a level
+    high_key: Option<K>,
+    right: Option<NodeRef<K>>,
+}
+
+impl<K: Ord> Node<K> {
+    // whether `key` is beyond this node and has to be looked for on the right
+    fn beyond(&self, key: &K) -> bool {
+        self.high_key.as_ref().is_some_and(|h| key > h)
+    }
+
+    fn child_for(&self, key: &K) -> NodeRef<K> {
+        let i = self.keys.partition_point(|k| k < key);
+        self.children[i].clone()
+    }
+}
+
+#[derive(Debug)]
+pub struct BlinkTree<K> {
+    // replaced when the root splits
+    root: RwLock<NodeRef<K>>,
+    max_keys: usize,
+    // the number of keys, changed under the latch of the leaf changed
+    len: AtomicUsize,
+}
+
+impl<K: Ord + Clone> Default for BlinkTree<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone> BlinkTree<K> {
+    pub fn new() -> Self {
+        Self::with_max_keys(DEFAULT_MAX_KEYS)
+    }
+
+    // a tree whose nodes split when they get more than `max_keys` keys
+    pub fn with_max_keys(max_keys: usize) -> Self {
+        assert!(max_keys >= 3);
+        let leaf = Node {
+            level: 0,
+            keys: Vec::new(),
+            children: Vec::new(),
+            high_key: None,
+            right: None,
+        };
+        Self {
+            root: RwLock::new(Arc::new(RwLock::new(leaf))),
+            max_keys,
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    fn root(&self) -> NodeRef<K> {
+        self.root.read().unwrap().clone()
+    }
+
+    // descend from `node` to the node of `level` that may hold `key`,
+    // pushing the node passed on every level to `stack` if given
+    fn descend(&self, mut node: NodeRef<K>, key: &K, level: usize, mut stack: Option<&mut Vec<NodeRef<K>>>) -> NodeRef<K> {
+        loop {
+            let next = {
+                let guard = node.read().unwrap();
+                if guard.beyond(key) {
+                    guard.right.clone().unwrap()
+                } else if guard.level == level {
+                    break;
+                } else {
+                    let child = guard.child_for(key);
+                    drop(guard);
+                    if let Some(stack) = stack.as_mut() {
+                        stack.push(node.clone());
+                    }
+                    child
+                }
+            };
+            node = next;
+        }
+        node
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        let leaf = self.descend(self.root(), key, 0, None);
+        let mut node = leaf;
+        loop {
+            let next = {
+                let guard = node.read().unwrap();
+                if !guard.beyond(key) {
+                    return guard.keys.binary_search(key).is_ok();
+                }
+                guard.right.clone().unwrap()
+            };
+            node = next;
+        }
+    }
+
+    // insert `key`, false if it is already there
+    pub fn insert(&self, key: K) -> bool {
+        let mut stack = Vec::new();
+        let mut node = self.descend(self.root(), &key, 0, Some(&mut stack));
+        // the key to insert into the current level and, above the leaves,
+        // the new child right of it
+        let mut pending: (K, Option<NodeRef<K>>) = (key, None);
+        loop {
+            let split = {
+                let mut guard = node.write().unwrap();
+                if guard.beyond(&pending.0) {
+                    let right = guard.right.clone().unwrap();
+                    drop(guard);
+                    node = right;
+                    continue;
+                }
+                let i = guard.keys.partition_point(|k| *k < pending.0);
+                match pending.1.take() {
+                    None => {
+                        if guard.keys.get(i) == Some(&pending.0) {
+                            return false;
+                        }
+                        guard.keys.insert(i, pending.0.clone());
+                        self.len.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Some(child) => {
+                        guard.keys.insert(i, pending.0.clone());
+                        guard.children.insert(i + 1, child);
+                    }
+                }
+                if guard.keys.len() <= self.max_keys {
+                    return true;
+                }
+                self.split(&mut guard)
+            };
+            // the node is unlatched, readers find the new sibling through
+            // its right link until the parent knows about it
+            node = match stack.pop() {
+                Some(parent) => parent,
+                None => match self.new_root(&node, &split) {
+                    Some(parent) => parent,
+                    None => return true,
+                },
+            };
+            pending = (split.0, Some(split.1));
+        }
+    }
+
+    // move the upper half of `node` to a new right sibling,
+    // returns the separator and the sibling
+    fn split(&self, node: &mut Node<K>) -> (K, NodeRef<K>) {
+        let mid = node.keys.len() / 2;
+        let (separator, keys, children) = if node.level == 0 {
+            let keys = node.keys.split_off(mid);
+            (node.keys.last().unwrap().clone(), keys, Vec::new())
+        } else {
+            // the middle separator becomes the high key of the left half
+            let keys = node.keys.split_off(mid + 1);
+            let separator = node.keys.pop().unwrap();
+            (separator, keys, node.children.split_off(mid + 1))
+        };
+        let sibling = Arc::new(RwLock::new(Node {
+            level: node.level,
+            keys,
+            children,
+            high_key: node.high_key.take(),
+            right: node.right.take(),
+        }));
+        node.high_key = Some(separator.clone());
+        node.right = Some(sibling.clone());
+        (separator, sibling)
+    }
+
+    // `node` split and had no parent on the stack: grow the tree if it is
+    // still the root, otherwise another split grew it meanwhile and the
+    // parent is found by descending from the new root
+    fn new_root(&self, node: &NodeRef<K>, split: &(K, NodeRef<K>)) -> Option<NodeRef<K>> {
+        let level = node.read().unwrap().level;
+        loop {
+            let mut root = self.root.write().unwrap();
+            if Arc::ptr_eq(&root, node) {
+                *root = Arc::new(RwLock::new(Node {
+                    level: level + 1,
+                    keys: vec![split.0.clone()],
+                    children: vec![node.clone(), split.1.clone()],
+                    high_key: None,
+                    right: None,
+                }));
+                return None;
+            }
+            let top = root.clone();
+            drop(root);
+            // `node` was reached through the right link of a root that
+            // split, wait until that split has made the new root
+            if top.read().unwrap().level > level {
+                return Some(self.descend(top, &split.0, level + 1, None));
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    // delete `key`, false if it was not there
+    pub fn delete(&self, key: &K) -> bool {
+        let mut node = self.descend(self.root(), key, 0, None);
+        loop {
+            let next = {
+                let mut guard = node.write().unwrap();
+                if !guard.beyond(key) {
+                    return match guard.keys.binary_search(key) {
+                        Ok(i) => {
+                            guard.keys.remove(i);
+                            self.len.fetch_sub(1, Ordering::Relaxed);
+                            true
+                        }
+                        Err(_) => false,
+                    };
+                }
+                guard.right.clone().unwrap()
+            };
+            node = next;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // all keys in order, following the right links of the leaves
+    pub fn keys(&self) -> Vec<K> {
+        self.range(..)
+    }
+
+    // the keys within `range` in order: from the leaf its start falls in
+    // along the right links until a key lies past its end. One leaf is
+    // latched at a time, so a scan racing writers sees each leaf as it was
+    // when it got there.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<K> {
+        let mut node = match range.start_bound() {
+            Bound::Included(start) | Bound::Excluded(start) => self.descend(self.root(), start, 0, None),
+            Bound::Unbounded => {
+                let mut node = self.root();
+                loop {
+                    let next = {
+                        let guard = node.read().unwrap();
+                        if guard.level == 0 {
+                            break;
+                        }
+                        guard.children[0].clone()
+                    };
+                    node = next;
+                }
+                node
+            }
+        };
+        let past_end = |key: &K| match range.end_bound() {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        };
+        let mut keys = Vec::new();
+        loop {
+            let next = {
+                let guard = node.read().unwrap();
+                keys.extend(guard.keys.iter().filter(|k| range.contains(k)).cloned());
+                if guard.keys.last().is_some_and(past_end) {
+                    return keys;
+                }
+                guard.right.clone()
+            };
+            match next {
+                Some(next) => node = next,
+                None => return keys,
+            }
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.root().read().unwrap().level + 1
+    }
+
+    // Verify the structure of a quiescent tree: keys are ordered within
+    // and across nodes, every node is linked to its right neighbour on the
+    // same level and its high key is the separator its parent has for it.
+    pub fn check(&self) -> Result<(), String> {
+        let root = self.root();
+        if root.read().unwrap().high_key.is_some() {
+            return Err("the root has a high key".into());
+        }
+        let mut first = root;
+        loop {
+            let level = first.read().unwrap().level;
+            // the children the nodes of this level point to, in order, and
+            // the high keys they must have
+            let mut children: Vec<(NodeRef<K>, Option<K>)> = Vec::new();
+            let mut node = first.clone();
+            let mut prev_high: Option<K> = None;
+            loop {
+                let next = {
+                    let guard = node.read().unwrap();
+                    if guard.level != level {
+                        return Err(format!("a node of level {} is linked on level {}", guard.level, level));
+                    }
+                    if !guard.keys.windows(2).all(|w| w[0] < w[1]) {
+                        return Err(format!("the keys of a node on level {} are out of order", level));
+                    }
+                    let above_prev = |k: &K| prev_high.as_ref().is_none_or(|p| k > p);
+                    let below_high = |k: &K| guard.high_key.as_ref().is_none_or(|h| k <= h);
+                    if !guard.keys.iter().all(|k| above_prev(k) && below_high(k)) {
+                        return Err(format!("a key on level {} is outside of its node's range", level));
+                    }
+                    if guard.high_key.is_some() != guard.right.is_some() {
+                        return Err(format!("a node on level {} has a high key but no right link", level));
+                    }
+                    if level > 0 {
+                        if guard.children.len() != guard.keys.len() + 1 {
+                            return Err(format!("a node on level {} has the wrong number of children", level));
+                        }
+                        for (i, child) in guard.children.iter().enumerate() {
+                            let high = guard.keys.get(i).or(guard.high_key.as_ref()).cloned();
+                            children.push((child.clone(), high));
+                        }
+                    }
+                    prev_high = guard.high_key.clone();
+                    guard.right.clone()
+                };
+                match next {
+                    Some(next) => node = next,
+                    None => break,
+                }
+            }
+            if level == 0 {
+                return Ok(());
+            }
+            // the children are exactly the next level, in order
+            for (i, (child, high)) in children.iter().enumerate() {
+                let guard = child.read().unwrap();
+                if guard.high_key != *high {
+                    return Err(format!("a node on level {} has a high key other than its separator", level - 1));
+                }
+                let expected = children.get(i + 1).map(|(c, _)| c);
+                if guard.right.as_ref().map(Arc::as_ptr) != expected.map(Arc::as_ptr) {
+                    return Err(format!("a node on level {} is not linked to its right neighbour", level - 1));
+                }
+            }
+            first = children[0].0.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::index::conformance::rng;
+    use std::collections::BTreeSet;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_single_thread() {
+        let tree = BlinkTree::with_max_keys(4);
+        let mut expected = BTreeSet::new();
+        let mut next = rng(7);
+        for _ in 0..5000 {
+            let key = next() % 500;
+            if next().is_multiple_of(3) {
+                assert_eq!(tree.delete(&key), expected.remove(&key));
+            } else {
+                assert_eq!(tree.insert(key), expected.insert(key));
+            }
+        }
+        tree.check().unwrap();
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.keys(), expected.iter().copied().collect::<Vec<_>>());
+        assert_eq!(tree.range(100..=200), expected.range(100..=200).copied().collect::<Vec<_>>());
+        assert_eq!(tree.range((Bound::Excluded(100), Bound::Excluded(200))), expected.range(101..200).copied().collect::<Vec<_>>());
+        for key in 0..500 {
+            assert_eq!(tree.contains(&key), expected.contains(&key));
+        }
+        assert!(tree.height() > 2);
+    }
+
+    // Writers insert and delete keys of their own residue class, so every
+    // writer knows the final state of its keys. Readers meanwhile look up
+    // keys inserted up front that are never deleted, a lookup missing one
+    // of them means a split hid it.
+    #[test]
+    fn test_concurrent_stress() {
+        const WRITERS: u64 = 6;
+        const READERS: u64 = 2;
+        const KEYS: u64 = 3000;
+        const STABLE: u64 = 1_000_000;
+
+        let tree = Arc::new(BlinkTree::with_max_keys(4));
+        for key in (STABLE..STABLE + 2000).step_by(2) {
+            tree.insert(key);
+        }
+        let done = Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..READERS)
+            .map(|r| {
+                let tree = tree.clone();
+                let done = done.clone();
+                std::thread::spawn(move || {
+                    let mut next = rng(r + 100);
+                    let mut lookups = 0;
+                    while !done.load(Ordering::Relaxed) || lookups < 1000 {
+                        let key = STABLE + next() % 1000 * 2;
+                        assert!(tree.contains(&key), "stable key {} not found", key);
+                        assert!(!tree.contains(&(key + 1)));
+                        lookups += 1;
+                    }
+                })
+            })
+            .collect();
+
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let tree = tree.clone();
+                std::thread::spawn(move || {
+                    let mut next = rng(w + 1);
+                    let mut mine = BTreeSet::new();
+                    for _ in 0..20_000 {
+                        let key = next() % KEYS * WRITERS + w;
+                        match next() % 4 {
+                            0 => assert_eq!(tree.delete(&key), mine.remove(&key)),
+                            1 => assert_eq!(tree.contains(&key), mine.contains(&key)),
+                            _ => assert_eq!(tree.insert(key), mine.insert(key)),
+                        }
+                    }
+                    mine
+                })
+            })
+            .collect();
+
+        let mut expected = BTreeSet::new();
+        for writer in writers {
+            expected.extend(writer.join().unwrap());
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        expected.extend((STABLE..STABLE + 2000).step_by(2));
+
+        tree.check().unwrap();
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.keys(), expected.into_iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,1188 @@
+#![allow(dead_code)]
+
+// A B+tree of byte-string keys in slotted pages, with suffix truncation and
+// prefix compression.
+//
+// `v2` is a classic btree: the key that moves up on a split is a real key
+// and can't be shortened. Here all keys live in the leaves and inner nodes
+// only hold separators, children[i] holding the keys in
+// [keys[i - 1], keys[i]). So a leaf split is free to pick the shortest
+// separator that still divides its halves, the shortest prefix of the
+// first key on the right that is greater than the last key on the left,
+// like nbtree does since PostgreSQL 12:
+//
+//   left ends with  "https://example.com/users/0041/profile"
+//   right starts    "https://example.com/users/0042/avatar"
+//   separator       "https://example.com/users/0042"
+//
+// A leaf stores the prefix all its keys share once and only the rest of
+// every key, the leading bytes that sorted neighbours nearly always share.
+//
+// Every node is a page of `PAGE` bytes laid out like a PostgreSQL page:
+//
+//   | n | upper | prefix offset | prefix len | slot 0 | slot 1 | ...
+//   ...            free            | key 1 | key 0 | prefix |
+//
+// A slot is the offset and length of its key, slots grow up from the
+// header and key bytes grow down from the end. So a node holds as many keys
+// as fit in its page, rather than a fixed number: nodes split once their
+// keys take more than a page and are fixed up once they take less than a
+// quarter of one, `split_node` and `fill_child` deciding by bytes. A key
+// may take up to an eighth of a page.
+//
+// Splits, merges and borrows otherwise follow `v2`.
+//
+// `KeyTree` holds the `CompositeKey`s of a multi-column index in one, by
+// their order preserving encodings, which `v2` can't truncate: a separator
+// there is a key of the index. Here the separators of an index on
+// `(name, row id)` end within the first name where the halves differ. The
+// btree indexes of `storage` are kept in one.
+
+use std::fmt::Display;
+use std::ops::{Bound, RangeBounds};
+
+use super::key::{CompositeKey, KeyRange, KeySchema};
+use super::v2::{escape_json, escape_record};
+
+const HEADER: usize = 8;
+const SLOT: usize = 4;
+
+#[derive(Debug)]
+struct Node<const PAGE: usize> {
+    // the keys of a leaf, less the prefix, or the separators of an inner
+    // node, as laid out above. A page may grow past `PAGE` bytes for a
+    // moment, until the node is split.
+    page: Vec<u8>,
+    // empty for a leaf
+    children: Vec<Node<PAGE>>,
+}
+
+impl<const PAGE: usize> Default for Node<PAGE> {
+    fn default() -> Self {
+        let mut node = Self {
+            page: Vec::new(),
+            children: Vec::new(),
+        };
+        node.set(&[], Vec::new());
+        node
+    }
+}
+
+// the length of the longest common prefix
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+// the shortest key that is greater than `left` and not greater than `right`
+fn separator(left: &[u8], right: &[u8]) -> Vec<u8> {
+    debug_assert!(left < right);
+    right[..common_prefix(left, right) + 1].to_vec()
+}
+
+// the bytes a leaf holding the sorted `keys` takes
+fn leaf_bytes(keys: &[Vec<u8>]) -> usize {
+    let prefix = match (keys.first(), keys.last()) {
+        (Some(first), Some(last)) => common_prefix(first, last),
+        _ => 0,
+    };
+    HEADER + prefix + keys.iter().map(|k| SLOT + k.len() - prefix).sum::<usize>()
+}
+
+// where to split keys of `sizes` bytes so that both halves take about as
+// many bytes, at least `lo` keys go left and at most `hi`
+fn split_point(sizes: &[usize], lo: usize, hi: usize) -> usize {
+    let total: usize = sizes.iter().sum();
+    let mut left = 0;
+    for (m, size) in sizes.iter().enumerate() {
+        if m >= lo && left * 2 >= total {
+            return m.min(hi);
+        }
+        left += size;
+    }
+    hi
+}
+
+impl<const PAGE: usize> Node<PAGE> {
+    // the fewest bytes a node other than the root should take
+    const MIN_BYTES: usize = PAGE / 4;
+
+    fn get(&self, at: usize) -> usize {
+        u16::from_le_bytes([self.page[at], self.page[at + 1]]) as usize
+    }
+
+    fn put(&mut self, at: usize, value: usize) {
+        self.page[at..at + 2].copy_from_slice(&(value as u16).to_le_bytes());
+    }
+
+    // the number of keys
+    fn n(&self) -> usize {
+        self.get(0)
+    }
+
+    // where the key bytes start
+    fn upper(&self) -> usize {
+        self.get(2)
+    }
+
+    fn prefix(&self) -> &[u8] {
+        let (off, len) = (self.get(4), self.get(6));
+        &self.page[off..off + len]
+    }
+
+    // the i-th key as stored, without the prefix
+    fn key(&self, i: usize) -> &[u8] {
+        let slot = HEADER + SLOT * i;
+        let (off, len) = (self.get(slot), self.get(slot + 2));
+        &self.page[off..off + len]
+    }
+
+    fn keys(&self) -> Vec<Vec<u8>> {
+        (0..self.n()).map(|i| self.key(i).to_vec()).collect()
+    }
+
+    // the bytes in use, not counting holes left by removed keys
+    fn bytes(&self) -> usize {
+        let n = self.n();
+        HEADER + self.prefix().len() + (0..n).map(|i| SLOT + self.key(i).len()).sum::<usize>()
+    }
+
+    // lay out a fresh page, without holes
+    fn set(&mut self, prefix: &[u8], keys: Vec<Vec<u8>>) {
+        let need = HEADER + prefix.len() + keys.iter().map(|k| SLOT + k.len()).sum::<usize>();
+        self.page = vec![0; need.max(PAGE)];
+        let mut upper = self.page.len() - prefix.len();
+        self.page[upper..upper + prefix.len()].copy_from_slice(prefix);
+        self.put(4, upper);
+        self.put(6, prefix.len());
+        for (i, key) in keys.iter().enumerate() {
+            upper -= key.len();
+            self.page[upper..upper + key.len()].copy_from_slice(key);
+            self.put(HEADER + SLOT * i, upper);
+            self.put(HEADER + SLOT * i + 2, key.len());
+        }
+        self.put(0, keys.len());
+        self.put(2, upper);
+    }
+
+    // store `key` as the i-th key, compacting the page if the free space in
+    // the middle is too small
+    fn insert_slot(&mut self, i: usize, key: &[u8]) {
+        let n = self.n();
+        let lower = HEADER + SLOT * n;
+        if self.upper() - lower < SLOT + key.len() {
+            let mut keys = self.keys();
+            keys.insert(i, key.to_vec());
+            let prefix = self.prefix().to_vec();
+            self.set(&prefix, keys);
+            return;
+        }
+        let upper = self.upper() - key.len();
+        self.page[upper..upper + key.len()].copy_from_slice(key);
+        self.page.copy_within(HEADER + SLOT * i..lower, HEADER + SLOT * (i + 1));
+        self.put(HEADER + SLOT * i, upper);
+        self.put(HEADER + SLOT * i + 2, key.len());
+        self.put(0, n + 1);
+        self.put(2, upper);
+    }
+
+    // remove the i-th key, its bytes stay behind as a hole until the page
+    // is compacted
+    fn remove_slot(&mut self, i: usize) -> Vec<u8> {
+        let n = self.n();
+        let key = self.key(i).to_vec();
+        self.page.copy_within(HEADER + SLOT * (i + 1)..HEADER + SLOT * n, HEADER + SLOT * i);
+        self.put(0, n - 1);
+        key
+    }
+
+    fn replace_slot(&mut self, i: usize, key: &[u8]) {
+        self.remove_slot(i);
+        self.insert_slot(i, key);
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn full_key(&self, i: usize) -> Vec<u8> {
+        [self.prefix(), self.key(i)].concat()
+    }
+
+    // the keys of a leaf, uncompressed, leaving it empty
+    fn take_keys(&mut self) -> Vec<Vec<u8>> {
+        let keys = (0..self.n()).map(|i| self.full_key(i)).collect();
+        self.set(&[], Vec::new());
+        keys
+    }
+
+    // store sorted `keys` in a leaf, with the longest prefix they share
+    fn set_keys(&mut self, keys: Vec<Vec<u8>>) {
+        let len = match (keys.first(), keys.last()) {
+            (Some(first), Some(last)) => common_prefix(first, last),
+            _ => 0,
+        };
+        let prefix = keys.first().map_or(Vec::new(), |k| k[..len].to_vec());
+        self.set(&prefix, keys.into_iter().map(|k| k[len..].to_vec()).collect());
+    }
+
+    // where `key` is or would go in a leaf
+    fn leaf_pos(&self, key: &[u8]) -> Result<usize, usize> {
+        let Some(rest) = key.strip_prefix(self.prefix()) else {
+            // sorts before or after every key of the leaf
+            return Err(if key < self.prefix() { 0 } else { self.n() });
+        };
+        let (mut lo, mut hi) = (0, self.n());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.key(mid).cmp(rest) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    // the child of an inner node that holds `key` if anything does
+    fn child_pos(&self, key: &[u8]) -> usize {
+        let (mut lo, mut hi) = (0, self.n());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.key(mid) <= key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        match self.is_leaf() {
+            true => self.leaf_pos(key).is_ok(),
+            false => self.children[self.child_pos(key)].contains(key),
+        }
+    }
+
+    // returns whether the key was not there yet
+    fn insert_down_to_leaf(&mut self, key: &[u8]) -> bool {
+        if self.is_leaf() {
+            let Err(i) = self.leaf_pos(key) else {
+                return false;
+            };
+            match key.strip_prefix(self.prefix()) {
+                Some(rest) => {
+                    let rest = rest.to_vec();
+                    self.insert_slot(i, &rest);
+                }
+                // the key doesn't share the prefix, recompress
+                None => {
+                    let mut keys = self.take_keys();
+                    keys.insert(i, key.to_vec());
+                    self.set_keys(keys);
+                }
+            }
+            return true;
+        }
+        let i = self.child_pos(key);
+        let inserted = self.children[i].insert_down_to_leaf(key);
+        if self.children[i].bytes() > PAGE {
+            self.split_node(i);
+        }
+        inserted
+    }
+
+    // split children[i], which takes more than a page, into halves of about
+    // as many bytes: a leaf gets the shortest separator between its halves,
+    // an inner node moves a separator from the middle up
+    fn split_node(&mut self, i: usize) {
+        let child = &mut self.children[i];
+        let mut right = Node::default();
+        let sep = if child.is_leaf() {
+            // sized uncompressed, the halves compress about alike
+            let mut keys = child.take_keys();
+            let sizes: Vec<usize> = keys.iter().map(|k| SLOT + k.len()).collect();
+            let upper = keys.split_off(split_point(&sizes, 1, keys.len() - 1));
+            let sep = separator(keys.last().unwrap(), &upper[0]);
+            child.set_keys(keys);
+            right.set_keys(upper);
+            sep
+        } else {
+            let mut keys = child.keys();
+            let sizes: Vec<usize> = keys.iter().map(|k| SLOT + k.len()).collect();
+            let m = split_point(&sizes, 1, keys.len() - 2);
+            let upper = keys.split_off(m + 1);
+            let sep = keys.pop().unwrap();
+            child.set(&[], keys);
+            right.set(&[], upper);
+            right.children = child.children.split_off(m + 1);
+            sep
+        };
+        self.insert_slot(i, &sep);
+        self.children.insert(i + 1, right);
+    }
+
+    // returns whether the key was there
+    fn delete_from(&mut self, key: &[u8]) -> bool {
+        if self.is_leaf() {
+            let Ok(i) = self.leaf_pos(key) else {
+                return false;
+            };
+            // the prefix stays a common one, if maybe not the longest
+            self.remove_slot(i);
+            return true;
+        }
+        let i = self.child_pos(key);
+        let deleted = self.children[i].delete_from(key);
+        if deleted {
+            // a longer separator from below may have overfilled the child
+            if self.children[i].bytes() > PAGE {
+                self.split_node(i);
+            } else {
+                self.fill_child(i);
+            }
+        }
+        deleted
+    }
+
+    // the bytes children[i] and children[i + 1] would take merged
+    fn merged_bytes(&self, i: usize) -> usize {
+        let (left, right) = (&self.children[i], &self.children[i + 1]);
+        if left.is_leaf() {
+            let keys: Vec<Vec<u8>> = (0..left.n())
+                .map(|j| left.full_key(j))
+                .chain((0..right.n()).map(|j| right.full_key(j)))
+                .collect();
+            return leaf_bytes(&keys);
+        }
+        left.bytes() + right.bytes() - HEADER + SLOT + self.key(i).len()
+    }
+
+    // fix up children[i] after a key was deleted below it, if it takes less
+    // than a quarter of a page:
+    //   merge it with a sibling if both fit in one page,
+    //   otherwise borrow keys from that sibling until it is filled enough
+    fn fill_child(&mut self, i: usize) {
+        if self.children[i].bytes() >= Self::MIN_BYTES {
+            return;
+        }
+        let j = if i > 0 { i - 1 } else { i + 1 };
+        if self.merged_bytes(i.min(j)) <= PAGE {
+            self.merge(i.max(j));
+            return;
+        }
+        let lend = |node: &Self| {
+            let (child, sibling) = (&node.children[i], &node.children[j]);
+            child.bytes() < Self::MIN_BYTES && sibling.n() > 1 && sibling.bytes() > child.bytes()
+        };
+        while lend(self) {
+            match j < i {
+                true => self.borrow_from_left(i),
+                false => self.borrow_from_right(i),
+            }
+        }
+    }
+
+    fn borrow_from_left(&mut self, i: usize) {
+        let down = self.key(i - 1).to_vec();
+        let (left, child) = self.children.split_at_mut(i);
+        let (left, child) = (&mut left[i - 1], &mut child[0]);
+        let sep = if child.is_leaf() {
+            let mut keys = left.take_keys();
+            let mut moved = vec![keys.pop().unwrap()];
+            left.set_keys(keys);
+            moved.extend(child.take_keys());
+            child.set_keys(moved);
+            separator(&left.full_key(left.n() - 1), &child.full_key(0))
+        } else {
+            let up = left.remove_slot(left.n() - 1);
+            child.insert_slot(0, &down);
+            child.children.insert(0, left.children.pop().unwrap());
+            up
+        };
+        self.replace_slot(i - 1, &sep);
+    }
+
+    fn borrow_from_right(&mut self, i: usize) {
+        let down = self.key(i).to_vec();
+        let (child, right) = self.children.split_at_mut(i + 1);
+        let (child, right) = (&mut child[i], &mut right[0]);
+        let sep = if child.is_leaf() {
+            let mut keys = right.take_keys();
+            let moved = keys.remove(0);
+            right.set_keys(keys);
+            let mut keys = child.take_keys();
+            keys.push(moved);
+            child.set_keys(keys);
+            separator(&child.full_key(child.n() - 1), &right.full_key(0))
+        } else {
+            let up = right.remove_slot(0);
+            child.insert_slot(child.n(), &down);
+            child.children.push(right.children.remove(0));
+            up
+        };
+        self.replace_slot(i, &sep);
+    }
+
+    // merge children[i] into children[i - 1]
+    fn merge(&mut self, i: usize) {
+        let mut right = self.children.remove(i);
+        let sep = self.remove_slot(i - 1);
+        let left = &mut self.children[i - 1];
+        if left.is_leaf() {
+            let mut keys = left.take_keys();
+            keys.extend(right.take_keys());
+            left.set_keys(keys);
+        } else {
+            let mut keys = left.keys();
+            keys.push(sep);
+            keys.extend(right.keys());
+            left.set(&[], keys);
+            left.children.append(&mut right.children);
+        }
+    }
+
+    // pass the keys not less than `start` to `visit` in order, until it
+    // returns false, returns false once it did
+    fn scan_from(&self, start: &[u8], visit: &mut impl FnMut(&[u8]) -> bool) -> bool {
+        if self.is_leaf() {
+            let (Ok(i) | Err(i)) = self.leaf_pos(start);
+            return (i..self.n()).all(|j| visit(&self.full_key(j)));
+        }
+        let i = self.child_pos(start);
+        self.children[i].scan_from(start, visit) && self.children[i + 1..].iter().all(|c| c.scan_from(&[], visit))
+    }
+
+    fn collect(&self, out: &mut Vec<Vec<u8>>) {
+        if self.is_leaf() {
+            out.extend((0..self.n()).map(|i| self.full_key(i)));
+        }
+        for child in &self.children {
+            child.collect(out);
+        }
+    }
+
+    fn key_bytes(&self) -> usize {
+        let own = self.prefix().len() + (0..self.n()).map(|i| self.key(i).len()).sum::<usize>();
+        own + self.children.iter().map(|c| c.key_bytes()).sum::<usize>()
+    }
+
+    // number the nodes in preorder into `out`, each with its level, the
+    // root's being 0, and the ids of its children. Returns this node's id.
+    fn preorder<'a>(&'a self, level: usize, out: &mut Vec<(usize, &'a Node<PAGE>, Vec<usize>)>) -> usize {
+        let id = out.len();
+        out.push((level, self, Vec::new()));
+        let children = self.children.iter().map(|c| c.preorder(level + 1, out)).collect();
+        out[id].2 = children;
+        id
+    }
+}
+
+// The shape of a tree, like pgstatindex reports it for an index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub height: usize,
+    // the number of nodes on every level, the root's first
+    pub level_nodes: Vec<usize>,
+    pub leaf_nodes: usize,
+    pub internal_nodes: usize,
+    pub keys: usize,
+    // the percentage of leaf page bytes in use, pgstatindex's
+    // avg_leaf_density
+    pub avg_leaf_density: f64,
+    // nodes other than the root that take less than a quarter of a page
+    pub underfull_nodes: usize,
+    // the bytes spent on keys, prefixes and separators
+    pub key_bytes: usize,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "height            {}", self.height)?;
+        writeln!(f, "nodes per level   {:?}", self.level_nodes)?;
+        writeln!(f, "internal nodes    {}", self.internal_nodes)?;
+        writeln!(f, "leaf nodes        {}", self.leaf_nodes)?;
+        writeln!(f, "keys              {}", self.keys)?;
+        writeln!(f, "avg leaf density  {:.2}", self.avg_leaf_density)?;
+        writeln!(f, "underfull nodes   {}", self.underfull_nodes)?;
+        write!(f, "key bytes         {}", self.key_bytes)
+    }
+}
+
+// `PAGE` is the size of a node in bytes, at most 32kB like PostgreSQL's
+// largest block size.
+#[derive(Debug, Default)]
+pub struct BytesTree<const PAGE: usize = 8192> {
+    root: Node<PAGE>,
+    len: usize,
+}
+
+impl<const PAGE: usize> BytesTree<PAGE> {
+    // the longest key
+    pub const MAX_KEY: usize = PAGE / 8;
+
+    pub fn new() -> Self {
+        assert!((64..=1 << 15).contains(&PAGE), "pages take 64 bytes to 32kB");
+        Self {
+            root: Node::default(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.root.contains(key)
+    }
+
+    // the root takes more than a page, split it below a new root
+    fn split_root(&mut self) {
+        if self.root.bytes() > PAGE {
+            let old = std::mem::take(&mut self.root);
+            self.root.children.push(old);
+            self.root.split_node(0);
+        }
+    }
+
+    // an error for a key longer than `MAX_KEY`, like nbtree's for an index
+    // row too large for a page
+    pub fn check(key: &[u8]) -> Result<(), String> {
+        match key.len() > Self::MAX_KEY {
+            true => Err(format!("index row size {} exceeds maximum {}", key.len(), Self::MAX_KEY)),
+            false => Ok(()),
+        }
+    }
+
+    // insert `key`, false if it is already there
+    pub fn insert(&mut self, key: &[u8]) -> Result<bool, String> {
+        Self::check(key)?;
+        if !self.root.insert_down_to_leaf(key) {
+            return Ok(false);
+        }
+        self.split_root();
+        self.len += 1;
+        Ok(true)
+    }
+
+    // delete `key`, false if it was not there
+    pub fn delete(&mut self, key: &[u8]) -> bool {
+        if !self.root.delete_from(key) {
+            return false;
+        }
+        self.split_root();
+        // the root lost its last separator, its only child is the new root
+        if !self.root.is_leaf() && self.root.n() == 0 {
+            self.root = self.root.children.pop().unwrap();
+        }
+        self.len -= 1;
+        true
+    }
+
+    // all keys in order
+    pub fn keys(&self) -> Vec<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.len);
+        self.root.collect(&mut out);
+        out
+    }
+
+    // the keys not less than `start` in order, as long as `visit` returns
+    // true for them
+    pub fn scan_from(&self, start: &[u8], mut visit: impl FnMut(&[u8]) -> bool) {
+        self.root.scan_from(start, &mut visit);
+    }
+
+    // the keys within `range` in order, from the range's start on until a
+    // key lies past it
+    pub fn range<R: RangeBounds<Vec<u8>>>(&self, range: R) -> Vec<Vec<u8>> {
+        let start = match range.start_bound() {
+            Bound::Included(key) | Bound::Excluded(key) => &key[..],
+            Bound::Unbounded => &[],
+        };
+        let mut out = Vec::new();
+        self.scan_from(start, |key| {
+            let key = key.to_vec();
+            let past_end = match range.end_bound() {
+                Bound::Included(end) => key > *end,
+                Bound::Excluded(end) => key >= *end,
+                Bound::Unbounded => false,
+            };
+            if !past_end && range.contains(&key) {
+                out.push(key);
+            }
+            !past_end
+        });
+        out
+    }
+
+    // the bytes spent on keys and separators in all nodes
+    pub fn key_bytes(&self) -> usize {
+        self.root.key_bytes()
+    }
+
+    // a tree of sorted, distinct `keys` with every node as full as it goes
+    // but the last of a level, which takes keys from the one before until
+    // it holds a quarter of a page, like CREATE INDEX builds a btree
+    pub fn from_sorted(keys: Vec<Vec<u8>>) -> Self {
+        assert!(keys.windows(2).all(|w| w[0] < w[1]), "keys must be sorted and distinct");
+        assert!(keys.iter().all(|k| Self::check(k).is_ok()), "keys must fit in a page");
+        let len = keys.len();
+
+        // the leaves, a key goes to the last one while it fits with the
+        // prefix the leaf's first key and it share
+        let mut leaves: Vec<Vec<Vec<u8>>> = Vec::new();
+        let mut stored = 0;
+        for key in keys {
+            let fits = leaves.last().is_some_and(|leaf| {
+                let prefix = common_prefix(&leaf[0], &key);
+                HEADER + prefix + stored + SLOT + key.len() - (leaf.len() + 1) * prefix <= PAGE
+            });
+            if !fits {
+                leaves.push(Vec::new());
+                stored = 0;
+            }
+            stored += SLOT + key.len();
+            leaves.last_mut().unwrap().push(key);
+        }
+        if let [.., before, last] = &mut leaves[..] {
+            while leaf_bytes(last) < Node::<PAGE>::MIN_BYTES && before.len() > 1 {
+                last.insert(0, before.pop().unwrap());
+                // a shorter prefix may take more than the key brings
+                if leaf_bytes(last) > PAGE {
+                    before.push(last.remove(0));
+                    break;
+                }
+            }
+        }
+        let mut seps: Vec<Vec<u8>> = leaves.windows(2).map(|w| separator(w[0].last().unwrap(), &w[1][0])).collect();
+        let mut level: Vec<Node<PAGE>> = leaves
+            .into_iter()
+            .map(|keys| {
+                let mut leaf = Node::default();
+                leaf.set_keys(keys);
+                leaf
+            })
+            .collect();
+
+        // the inner levels, the separator between two nodes goes up when
+        // they end up under different parents
+        while level.len() > 1 {
+            let mut parents: Vec<(Vec<Vec<u8>>, Vec<Node<PAGE>>)> = Vec::new();
+            let mut ups = Vec::new();
+            let mut bytes = 0;
+            let mut seps_left = seps.into_iter();
+            for node in level {
+                let Some((keys, children)) = parents.last_mut() else {
+                    parents.push((Vec::new(), vec![node]));
+                    bytes = HEADER;
+                    continue;
+                };
+                let sep = seps_left.next().unwrap();
+                if bytes + SLOT + sep.len() <= PAGE {
+                    bytes += SLOT + sep.len();
+                    keys.push(sep);
+                    children.push(node);
+                } else {
+                    ups.push(sep);
+                    parents.push((Vec::new(), vec![node]));
+                    bytes = HEADER;
+                }
+            }
+            if let [.., (keys, children), (last_keys, last_children)] = &mut parents[..] {
+                let bytes = |keys: &[Vec<u8>]| HEADER + keys.iter().map(|k| SLOT + k.len()).sum::<usize>();
+                while (last_keys.is_empty() || bytes(last_keys) < Node::<PAGE>::MIN_BYTES) && keys.len() > 1 {
+                    last_keys.insert(0, ups.pop().unwrap());
+                    last_children.insert(0, children.pop().unwrap());
+                    ups.push(keys.pop().unwrap());
+                }
+            }
+            level = parents
+                .into_iter()
+                .map(|(keys, children)| {
+                    let mut node = Node::default();
+                    node.set(&[], keys);
+                    node.children = children;
+                    node
+                })
+                .collect();
+            seps = ups;
+        }
+        Self {
+            root: level.pop().unwrap_or_default(),
+            len,
+        }
+    }
+
+    // a copy of the tree with its nodes packed as densely as they go, see
+    // `from_sorted`
+    pub fn compacted(&self) -> Self {
+        Self::from_sorted(self.keys())
+    }
+
+    // the shape of the tree, level by level
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        let mut level = vec![&self.root];
+        let mut leaf_bytes = 0;
+        while !level.is_empty() {
+            stats.level_nodes.push(level.len());
+            let mut below = Vec::new();
+            for node in level {
+                if stats.height > 0 && node.bytes() < Node::<PAGE>::MIN_BYTES {
+                    stats.underfull_nodes += 1;
+                }
+                if node.is_leaf() {
+                    stats.leaf_nodes += 1;
+                    stats.keys += node.n();
+                    leaf_bytes += node.bytes();
+                } else {
+                    stats.internal_nodes += 1;
+                    below.extend(&node.children);
+                }
+            }
+            stats.height += 1;
+            level = below;
+        }
+        stats.avg_leaf_density = match stats.keys {
+            0 => 0.0,
+            _ => 100.0 * leaf_bytes as f64 / (stats.leaf_nodes * PAGE) as f64,
+        };
+        stats.key_bytes = self.key_bytes();
+        stats
+    }
+}
+
+// The keys of an index as `v2` holds them, with the encodings of a
+// `KeySchema` as keys. A key's encoding may take up to `MAX_KEY` bytes.
+#[derive(Debug)]
+pub struct KeyTree<const PAGE: usize = 8192> {
+    schema: KeySchema,
+    tree: BytesTree<PAGE>,
+}
+
+impl<const PAGE: usize> KeyTree<PAGE> {
+    pub fn new(schema: KeySchema) -> Self {
+        Self {
+            schema,
+            tree: BytesTree::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn contains(&self, key: &CompositeKey) -> bool {
+        self.tree.contains(&key.encode())
+    }
+
+    // an error if `key` encodes to more than `MAX_KEY` bytes
+    pub fn check(&self, key: &CompositeKey) -> Result<(), String> {
+        BytesTree::<PAGE>::check(&key.encode())
+    }
+
+    // insert `key`, false if it is already there
+    pub fn insert(&mut self, key: &CompositeKey) -> Result<bool, String> {
+        self.tree.insert(&key.encode())
+    }
+
+    // delete `key`, false if it was not there
+    pub fn delete(&mut self, key: &CompositeKey) -> bool {
+        self.tree.delete(&key.encode())
+    }
+
+    // all keys matching `range` in index order, like `KeyRange::scan` on
+    // a `v2` tree: from the range's start on until a key lies past it
+    pub fn scan(&self, range: &KeyRange) -> Vec<CompositeKey> {
+        let mut out = Vec::new();
+        self.tree.scan_from(&range.start(&self.schema), |bytes| {
+            let key = self.schema.decode(bytes);
+            match range.probe(&key) {
+                std::cmp::Ordering::Less => true,
+                std::cmp::Ordering::Equal => {
+                    out.push(key);
+                    true
+                }
+                std::cmp::Ordering::Greater => false,
+            }
+        });
+        out
+    }
+
+    // the keys within `range` in index order
+    pub fn range<R: RangeBounds<CompositeKey>>(&self, range: R) -> Vec<CompositeKey> {
+        let start = match range.start_bound() {
+            Bound::Included(key) | Bound::Excluded(key) => key.encode(),
+            Bound::Unbounded => Vec::new(),
+        };
+        let mut out = Vec::new();
+        self.tree.scan_from(&start, |bytes| {
+            let key = self.schema.decode(bytes);
+            let past_end = match range.end_bound() {
+                Bound::Included(end) => key > *end,
+                Bound::Excluded(end) => key >= *end,
+                Bound::Unbounded => false,
+            };
+            if !past_end && range.contains(&key) {
+                out.push(key);
+            }
+            !past_end
+        });
+        out
+    }
+
+    // the bytes spent on keys and separators in all nodes
+    pub fn key_bytes(&self) -> usize {
+        self.tree.key_bytes()
+    }
+
+    // a copy of the tree with its nodes packed as densely as they go
+    pub fn compacted(&self) -> Self {
+        Self {
+            schema: self.schema.clone(),
+            tree: self.tree.compacted(),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.tree.stats()
+    }
+
+    // what a node shows of its keys: a leaf its keys, an inner node the
+    // bytes of its separators, which may end within a column
+    fn labels(&self, node: &Node<PAGE>) -> Vec<String> {
+        match node.is_leaf() {
+            true => (0..node.n()).map(|i| self.schema.decode(&node.full_key(i)).to_string()).collect(),
+            false => node.keys().iter().map(|k| k.escape_ascii().to_string()).collect(),
+        }
+    }
+
+    fn fmt_node(&self, node: &Node<PAGE>, level: usize, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let indent = " ".repeat(level);
+        let labels = self.labels(node);
+        if node.is_leaf() {
+            return writeln!(f, "{}[{}],", indent, labels.join(", "));
+        }
+        writeln!(f, "{}{{", indent)?;
+        for (i, child) in node.children.iter().enumerate() {
+            self.fmt_node(child, level + 1, f)?;
+            if let Some(label) = labels.get(i) {
+                writeln!(f, "{}{},", indent, label)?;
+            }
+        }
+        writeln!(f, "{}}},", indent)
+    }
+
+    // the tree as a graphviz graph, like `v2::Node::to_dot`
+    pub fn to_dot(&self) -> String {
+        let mut nodes = Vec::new();
+        self.tree.root.preorder(0, &mut nodes);
+        let mut out = String::from("digraph btree {\n  node [shape=record];\n");
+        for (id, (_, node, children)) in nodes.iter().enumerate() {
+            let keys = self.labels(node).into_iter().map(|k| escape_record(&k));
+            let label = match node.is_leaf() {
+                true => keys.collect::<Vec<_>>().join("|"),
+                false => keys.enumerate().fold("<c0>".to_string(), |label, (i, key)| format!("{}|{}|<c{}>", label, key, i + 1)),
+            };
+            out.push_str(&format!("  n{} [label=\"{}\"];\n", id, label));
+            for (i, child) in children.iter().enumerate() {
+                out.push_str(&format!("  n{}:c{} -> n{};\n", id, i, child));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    // the tree as json, like `v2::Node::to_json`, a node's fill being the
+    // share of its page in use
+    pub fn to_json(&self) -> String {
+        let mut nodes = Vec::new();
+        self.tree.root.preorder(0, &mut nodes);
+        let items: Vec<String> = nodes
+            .iter()
+            .enumerate()
+            .map(|(id, (level, node, children))| {
+                let keys: Vec<String> = self.labels(node).iter().map(|k| format!("\"{}\"", escape_json(k))).collect();
+                let children: Vec<String> = children.iter().map(|c| c.to_string()).collect();
+                format!(
+                    "{{\"id\": {}, \"level\": {}, \"leaf\": {}, \"keys\": [{}], \"fill\": {:.2}, \"children\": [{}]}}",
+                    id,
+                    level,
+                    node.is_leaf(),
+                    keys.join(", "),
+                    node.bytes() as f64 / PAGE as f64,
+                    children.join(", ")
+                )
+            })
+            .collect();
+        format!("{{\"height\": {}, \"nodes\": [{}]}}", self.stats().height, items.join(", "))
+    }
+}
+
+// the tree in `v2`'s notation, leaves in brackets and inner nodes in
+// braces
+impl<const PAGE: usize> Display for KeyTree<PAGE> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_node(&self.tree.root, 0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::index::conformance::rng;
+    use crate::btree::key::{Datum, SortOrder};
+    use crate::btree::v2;
+    use std::collections::BTreeSet;
+
+    // keys are ordered and within the bounds their parents set, every leaf
+    // is at the same depth and every node fits in its page
+    fn check<const PAGE: usize>(node: &Node<PAGE>, low: Option<&[u8]>, high: Option<&[u8]>, depth: usize, leaf_depth: &mut Option<usize>) {
+        assert!(node.bytes() <= PAGE);
+        assert!(low.is_none() || node.n() >= 1);
+        let keys: Vec<Vec<u8>> = match node.is_leaf() {
+            true => (0..node.n()).map(|i| node.full_key(i)).collect(),
+            false => node.keys(),
+        };
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(keys.iter().all(|k| low.is_none_or(|l| &k[..] >= l) && high.is_none_or(|h| &k[..] < h)));
+        if node.is_leaf() {
+            assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+            return;
+        }
+        assert!(!keys.is_empty());
+        assert_eq!(node.children.len(), keys.len() + 1);
+        for (i, child) in node.children.iter().enumerate() {
+            let low = if i == 0 { low } else { Some(&keys[i - 1][..]) };
+            let high = if i == keys.len() { high } else { Some(&keys[i][..]) };
+            check(child, low.or(Some(b"")), high, depth + 1, leaf_depth);
+        }
+    }
+
+    fn leaves<const PAGE: usize>(node: &Node<PAGE>) -> usize {
+        match node.is_leaf() {
+            true => 1,
+            false => node.children.iter().map(leaves).sum(),
+        }
+    }
+
+    #[test]
+    fn test_separator() {
+        assert_eq!(separator(b"abc", b"abd"), b"abd");
+        assert_eq!(separator(b"abc", b"abzzz"), b"abz");
+        assert_eq!(separator(b"ab", b"abc"), b"abc");
+        assert_eq!(separator(b"", b"x"), b"x");
+    }
+
+    #[test]
+    fn test_slots() {
+        let mut node = Node::<64>::default();
+        node.insert_slot(0, b"bb");
+        node.insert_slot(0, b"a");
+        node.insert_slot(2, b"ccc");
+        assert_eq!(node.keys(), [b"a".to_vec(), b"bb".to_vec(), b"ccc".to_vec()]);
+        assert_eq!(node.bytes(), HEADER + 3 * SLOT + 6);
+        assert_eq!(node.remove_slot(1), b"bb");
+        // the hole is used once the page is compacted
+        for i in 0..5 {
+            node.insert_slot(1, &[b'b'; 4][..i]);
+            node.remove_slot(1);
+        }
+        node.insert_slot(1, &[b'x'; 30]);
+        assert_eq!(node.page.len(), 64);
+        assert_eq!(node.key(1), [b'x'; 30]);
+        assert_eq!(node.key(2), b"ccc");
+    }
+
+    #[test]
+    fn test_random() {
+        let mut tree = BytesTree::<256>::new();
+        let mut expected = BTreeSet::new();
+        let mut next = rng(13);
+        for step in 0..8000 {
+            // keys of any length from a small alphabet, with plenty of shared
+            // prefixes and keys that are prefixes of others
+            let len = next() % (BytesTree::<256>::MAX_KEY as u64 + 1);
+            let key: Vec<u8> = (0..len).map(|_| b'a' + (next() % 3) as u8).collect();
+            if next().is_multiple_of(3) {
+                assert_eq!(tree.delete(&key), expected.remove(&key));
+            } else {
+                assert_eq!(tree.insert(&key).unwrap(), expected.insert(key));
+            }
+            if step % 50 == 0 {
+                check(&tree.root, None, None, 0, &mut None);
+            }
+        }
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.keys(), expected.iter().cloned().collect::<Vec<_>>());
+        for key in &expected {
+            assert!(tree.contains(key));
+        }
+
+        // deleting everything shrinks the tree back to a leaf
+        for (step, key) in expected.iter().enumerate() {
+            assert!(tree.delete(key));
+            if step % 50 == 0 {
+                check(&tree.root, None, None, 0, &mut None);
+            }
+        }
+        assert!(tree.root.is_leaf() && tree.is_empty());
+    }
+
+    #[test]
+    fn test_capacity_in_bytes() {
+        let mut short = BytesTree::<512>::new();
+        let mut long = BytesTree::<512>::new();
+        let mut next = rng(5);
+        for _ in 0..2000 {
+            let key = next().to_be_bytes();
+            short.insert(&key[..4]).unwrap();
+            long.insert(&[&key[..], &[b'-'; 56]].concat()).unwrap();
+        }
+        check(&short.root, None, None, 0, &mut None);
+        check(&long.root, None, None, 0, &mut None);
+        // a leaf holds many more short keys than long ones
+        assert!(leaves(&long.root) > leaves(&short.root) * 4);
+    }
+
+    #[test]
+    fn test_compression() {
+        let mut tree = BytesTree::<8192>::new();
+        let mut raw = 0;
+        for user in 0..2000 {
+            for page in ["avatar", "profile", "settings"] {
+                let key = format!("https://example.com/users/{:06}/{}", user * 7, page);
+                raw += key.len();
+                tree.insert(key.as_bytes()).unwrap();
+            }
+        }
+        check(&tree.root, None, None, 0, &mut None);
+        // the common part of the urls is stored once per leaf
+        assert!(tree.key_bytes() * 2 < raw, "{} of {} bytes", tree.key_bytes(), raw);
+
+        // separators stop where the halves start to differ
+        assert!(!tree.root.is_leaf());
+        let keys = tree.root.keys();
+        assert!(keys.iter().all(|s| s.len() <= "https://example.com/users/000000/a".len()));
+    }
+
+    // every separator of the inner nodes below `node`
+    fn separators<const PAGE: usize>(node: &Node<PAGE>, out: &mut Vec<Vec<u8>>) {
+        if !node.is_leaf() {
+            out.extend(node.keys());
+            node.children.iter().for_each(|c| separators(c, out));
+        }
+    }
+
+    #[test]
+    fn test_composite_keys() {
+        // an index on (name, visits DESC) with the row id last, like
+        // `storage` builds them
+        let schema = KeySchema::new(vec![SortOrder::Asc, SortOrder::Desc, SortOrder::Asc]);
+        let mut tree = KeyTree::<1024>::new(schema.clone());
+        let mut v2 = v2::Node::new();
+        let mut next = rng(3);
+        for rowid in 0..3000 {
+            let name = format!("customer-{:06}", next() % 100_000);
+            let key = schema.key(vec![Datum::Text(name), Datum::Int((next() % 50) as i64), Datum::Int(rowid)]);
+            assert!(tree.insert(&key).unwrap());
+            v2.insert(key);
+        }
+        check(&tree.tree.root, None, None, 0, &mut None);
+        assert_eq!(tree.range(..), v2.range(..).into_iter().cloned().collect::<Vec<_>>());
+
+        // separators end within the name, never keeping the visits or row id
+        let mut seps = Vec::new();
+        separators(&tree.tree.root, &mut seps);
+        assert!(!seps.is_empty());
+        assert!(seps.iter().all(|s| s.len() <= 1 + "customer-000000".len()), "{:?}", seps);
+
+        let some = v2.select(1234).unwrap().datums()[0].clone();
+        let ranges = [
+            KeyRange::prefix(vec![some.clone()]),
+            KeyRange::prefix(vec![some]).upper(Bound::Excluded(Datum::Int(20))),
+            KeyRange::prefix(vec![]).lower(Bound::Included(Datum::Text("customer-05".to_string()))).upper(Bound::Excluded(Datum::Text("customer-06".to_string()))),
+            KeyRange::prefix(vec![Datum::Text("nobody".to_string())]),
+        ];
+        for range in ranges {
+            let want: Vec<CompositeKey> = range.scan(&v2).into_iter().cloned().collect();
+            assert_eq!(tree.scan(&range), want);
+        }
+
+        let key = v2.select(77).unwrap().clone();
+        assert!(tree.contains(&key) && tree.delete(&key) && !tree.contains(&key));
+        assert_eq!(tree.len(), 2999);
+
+        // a name too long for a page is refused, like an index row too
+        // large for nbtree
+        let long = schema.key(vec![Datum::Text("x".repeat(200)), Datum::Int(0), Datum::Int(0)]);
+        let err = tree.insert(&long).unwrap_err();
+        assert_eq!(err, "index row size 221 exceeds maximum 128");
+        assert_eq!(tree.check(&long), Err(err));
+        assert!(tree.check(&key).is_ok() && tree.len() == 2999);
+    }
+
+    #[test]
+    fn test_from_sorted() {
+        let mut next = rng(11);
+        let keys: BTreeSet<Vec<u8>> = (0..5000).map(|_| format!("k{}", next() % 100_000).into_bytes()).collect();
+        let mut tree = BytesTree::<256>::new();
+        for key in &keys {
+            tree.insert(key).unwrap();
+        }
+        let packed = tree.compacted();
+        check(&packed.root, None, None, 0, &mut None);
+        assert_eq!(packed.keys(), tree.keys());
+        assert_eq!(packed.len(), keys.len());
+        let (before, after) = (tree.stats(), packed.stats());
+        assert!(after.leaf_nodes * 3 < before.leaf_nodes * 2, "{} of {} leaves", after.leaf_nodes, before.leaf_nodes);
+        assert!(after.avg_leaf_density > 90.0 && after.underfull_nodes == 0);
+        assert_eq!((after.keys, after.height), (keys.len(), after.level_nodes.len()));
+
+        // and stays a tree that takes changes
+        let mut packed = packed;
+        for key in keys.iter().step_by(3) {
+            assert!(packed.delete(key));
+        }
+        assert!(packed.insert(b"k").unwrap());
+        check(&packed.root, None, None, 0, &mut None);
+
+        for n in [0, 1, 2, 40] {
+            let keys: Vec<Vec<u8>> = keys.iter().take(n).cloned().collect();
+            let tree = BytesTree::<256>::from_sorted(keys.clone());
+            check(&tree.root, None, None, 0, &mut None);
+            assert_eq!(tree.keys(), keys);
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let schema = KeySchema::new(vec![SortOrder::Asc, SortOrder::Asc]);
+        let mut tree = KeyTree::<128>::new(schema.clone());
+        for (id, name) in ["ann", "bob", "cy", "dee", "eve", "fay", "gus", "hal"].into_iter().enumerate() {
+            tree.insert(&schema.key(vec![Datum::Text(name.to_string()), Datum::Int(id as i64)])).unwrap();
+        }
+        // the separator ends within the name where the leaves differ
+        let exp = r"{
+ [('ann', 0), ('bob', 1), ('cy', 2), ('dee', 3)],
+\x03e,
+ [('eve', 4), ('fay', 5), ('gus', 6), ('hal', 7)],
+},
+";
+        assert_eq!(tree.to_string(), exp);
+        let dot = tree.to_dot();
+        assert!(dot.contains(r#"n0 [label="<c0>|\\x03e|<c1>"];"#), "{}", dot);
+        assert!(dot.contains(r#"n2 [label="('eve', 4)|('fay', 5)|('gus', 6)|('hal', 7)"];"#));
+        let json = tree.to_json();
+        assert!(json.starts_with(r#"{"height": 2, "nodes": [{"id": 0, "level": 0, "leaf": false, "keys": ["\\x03e"], "fill": 0.11, "children": [1, 2]}"#));
+    }
+}
@@ -0,0 +1,167 @@
+#![allow(dead_code)]
+
+// Operator classes: orders for the keys of a tree other than their own
+// `Ord`, like a case-insensitive or a descending text index.
+//
+// A `Comparator` is a type rather than a value, named by the tree it
+// orders: `v2::Node<String, CaseInsensitive>` holds plain strings and
+// compares them through `CaseInsensitive` wherever it compares keys, so
+// `find` and `delete` take keys the comparator calls equal for the same
+// key, "APPLE" for "apple". Nothing is stored per key, nor per tree.
+
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+
+pub trait Comparator<K> {
+    fn compare(a: &K, b: &K) -> Ordering;
+}
+
+// the keys' own order
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Natural;
+
+impl<K: Ord> Comparator<K> for Natural {
+    fn compare(a: &K, b: &K) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+// another comparator's order backwards, for DESC indexes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reverse<C>(PhantomData<C>);
+
+impl<K, C: Comparator<K>> Comparator<K> for Reverse<C> {
+    fn compare(a: &K, b: &K) -> Ordering {
+        C::compare(a, b).reverse()
+    }
+}
+
+// an entry of a tree that carries data of its own next to its key, like a
+// flag or a posting list, which takes no part in the order
+pub trait Keyed {
+    type Key: Ord;
+
+    fn key(&self) -> &Self::Key;
+}
+
+// entries ordered and equal by their key alone
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ByKey;
+
+impl<T: Keyed> Comparator<T> for ByKey {
+    fn compare(a: &T, b: &T) -> Ordering {
+        a.key().cmp(b.key())
+    }
+}
+
+// text compared by its lower case, so that keys differing in case alone
+// are equal, like citext
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaseInsensitive;
+
+impl<S: AsRef<str>> Comparator<S> for CaseInsensitive {
+    fn compare(a: &S, b: &S) -> Ordering {
+        let lower = |s: &S| s.as_ref().chars().flat_map(char::to_lowercase).collect::<Vec<_>>();
+        lower(a).cmp(&lower(b))
+    }
+}
+
+// text in dictionary order, the way locale collations compare it in levels:
+//   primary    letters regardless of accents and case, "cote" = "Côte"
+//   secondary  accents, an unaccented letter first, "cote" < "côte"
+//   tertiary   case, lower case first, "côte" < "Côte"
+// a level only counts when the ones before it tie. Only Latin-1 accents
+// are known.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Dictionary;
+
+// the letter under an accent
+fn unaccent(c: char) -> char {
+    const LETTERS: [(&str, char); 8] = [
+        ("àáâãäå", 'a'),
+        ("ç", 'c'),
+        ("èéêë", 'e'),
+        ("ìíîï", 'i'),
+        ("ñ", 'n'),
+        ("òóôõöø", 'o'),
+        ("ùúûü", 'u'),
+        ("ýÿ", 'y'),
+    ];
+    LETTERS.iter().find(|(accented, _)| accented.contains(c)).map_or(c, |&(_, base)| base)
+}
+
+impl<S: AsRef<str>> Comparator<S> for Dictionary {
+    fn compare(a: &S, b: &S) -> Ordering {
+        let (a, b) = (a.as_ref(), b.as_ref());
+        let lower = |s: &str| s.chars().flat_map(char::to_lowercase).collect::<Vec<_>>();
+        let primary = |s: &str| lower(s).into_iter().map(unaccent).collect::<Vec<_>>();
+        let tertiary = |s: &str| s.chars().map(|c| (c.is_uppercase(), c)).collect::<Vec<_>>();
+        primary(a)
+            .cmp(&primary(b))
+            .then_with(|| lower(a).cmp(&lower(b)))
+            .then_with(|| tertiary(a).cmp(&tertiary(b)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::index::conformance;
+    use crate::btree::v2::Node;
+    use std::ops::Bound;
+
+    fn keys<C: Comparator<String>>(tree: &Node<String, C>) -> Vec<&str> {
+        tree.range(..).into_iter().map(|k| k.as_str()).collect()
+    }
+
+    fn build_tree<C: Comparator<String>>(words: &[&str]) -> Node<String, C> {
+        let mut tree = Node::default();
+        for word in words {
+            tree.insert(word.to_string());
+        }
+        tree
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut tree: Node<String, CaseInsensitive> = build_tree(&["banana", "Cherry", "apple", "BANANA2", "date"]);
+        assert_eq!(keys(&tree), ["apple", "banana", "BANANA2", "Cherry", "date"]);
+
+        // equal by the comparator is the same key
+        let key = |s: &str| s.to_string();
+        assert_eq!(tree.range(key("CHERRY")..=key("cherry")).len(), 1);
+        assert_eq!(tree.first_from(Bound::Included(&key("APPLE"))).unwrap(), "apple");
+        tree.delete(key("Banana"));
+        assert_eq!(keys(&tree), ["apple", "BANANA2", "Cherry", "date"]);
+        assert!(!tree.entry(key("DATE")).or_insert() && tree.len() == 4);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut tree = Node::<i32, Reverse<Natural>>::default();
+        for i in [5, 1, 9, 3, 7, 2, 8] {
+            tree.insert(i);
+        }
+        let keys: Vec<i32> = tree.range(..).into_iter().copied().collect();
+        assert_eq!(keys, [9, 8, 7, 5, 3, 2, 1]);
+        // bounds follow the index order too
+        let keys: Vec<i32> = tree.range((Bound::Included(7), Bound::Excluded(2))).into_iter().copied().collect();
+        assert_eq!(keys, [7, 5, 3]);
+        assert_eq!(tree.compacted().range(..).len(), 7);
+    }
+
+    #[test]
+    fn test_dictionary() {
+        let tree: Node<String, Dictionary> = build_tree(&["zebra", "Éclair", "côte", "eclair", "Apple", "Côte", "apple", "cote", "éclair"]);
+        assert_eq!(keys(&tree), ["apple", "Apple", "cote", "côte", "Côte", "eclair", "éclair", "Éclair", "zebra"]);
+        let cmp = |a: &str, b: &str| Dictionary::compare(&a, &b);
+        assert_eq!(cmp("côte", "côte"), Ordering::Equal);
+        assert_eq!(cmp("cotes", "côte"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_conformance() {
+        conformance::run(Node::<u64, Natural>::default, |k| k);
+        conformance::run(Node::<String, CaseInsensitive>::default, |k| format!("K{:04}", k));
+    }
+}
@@ -0,0 +1,438 @@
+#![allow(dead_code)]
+
+// A persistent (copy-on-write) version of the `v2` btree.
+//
+// Nodes are shared through `Arc` and never changed while shared: `insert`
+// and `delete` copy every node on the path from the root down to the leaf
+// they change (`Arc::make_mut` copies a node only if someone else holds it)
+// and leave the rest shared. So an old root stays a complete, unchanging
+// tree, which is all a snapshot is. Taking one is an `Arc` clone, and it
+// can be read from any thread while the tree goes on changing.
+//
+// Splits, merges and borrows work like in `v2`, except that keys are
+// unique: a tree is a set.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+const MAX_CHILDREN: usize = 5;
+const MAX_KEYS: usize = MAX_CHILDREN - 1;
+// the fewest keys a node other than the root may hold
+const MIN_KEYS: usize = (MAX_CHILDREN - 1) / 2;
+
+#[derive(Debug, Clone)]
+struct Node<K> {
+    keys: Vec<K>,
+    // empty for a leaf
+    children: Vec<Arc<Node<K>>>,
+}
+
+impl<K: Ord + Clone> Node<K> {
+    fn leaf() -> Self {
+        Self {
+            keys: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        let i = self.keys.partition_point(|k| k < key);
+        if i < self.keys.len() && self.keys[i] == *key {
+            return true;
+        }
+        !self.is_leaf() && self.children[i].contains(key)
+    }
+
+    fn last(&self) -> &K {
+        match self.children.last() {
+            Some(child) => child.last(),
+            None => self.keys.last().unwrap(),
+        }
+    }
+}
+
+// insert `key` below `node`, copying the path to it, returns the separator
+// and the right half if the node split
+fn insert<K: Ord + Clone>(node: &mut Arc<Node<K>>, key: K) -> Option<(K, Arc<Node<K>>)> {
+    let node = Arc::make_mut(node);
+    let i = node.keys.partition_point(|k| *k < key);
+    if node.is_leaf() {
+        node.keys.insert(i, key);
+    } else if let Some((sep, right)) = insert(&mut node.children[i], key) {
+        node.keys.insert(i, sep);
+        node.children.insert(i + 1, right);
+    }
+    if node.keys.len() <= MAX_KEYS {
+        return None;
+    }
+    let mid = node.keys.len() / 2;
+    let right = Node {
+        keys: node.keys.split_off(mid + 1),
+        children: match node.is_leaf() {
+            true => Vec::new(),
+            false => node.children.split_off(mid + 1),
+        },
+    };
+    let sep = node.keys.pop().unwrap();
+    Some((sep, Arc::new(right)))
+}
+
+// delete `key`, which must be in the subtree, copying the path to it. A
+// child left with too few keys is fixed up on the way back.
+fn delete<K: Ord + Clone>(node: &mut Arc<Node<K>>, key: &K) {
+    let node = Arc::make_mut(node);
+    let i = node.keys.partition_point(|k| k < key);
+    if i < node.keys.len() && node.keys[i] == *key {
+        if node.is_leaf() {
+            node.keys.remove(i);
+            return;
+        }
+        // swap in the predecessor and delete that from the leaf it is in
+        let pred = node.children[i].last().clone();
+        node.keys[i] = pred.clone();
+        delete(&mut node.children[i], &pred);
+    } else {
+        delete(&mut node.children[i], key);
+    }
+    fill_child(node, i);
+}
+
+// borrow a key from a sibling of children[i] that can spare one, or merge
+// it with a sibling
+fn fill_child<K: Ord + Clone>(node: &mut Node<K>, i: usize) {
+    if node.children[i].keys.len() >= MIN_KEYS {
+        return;
+    }
+    if i > 0 && node.children[i - 1].keys.len() > MIN_KEYS {
+        let (left, child) = node.children.split_at_mut(i);
+        let left = Arc::make_mut(&mut left[i - 1]);
+        let child = Arc::make_mut(&mut child[0]);
+        let sep = std::mem::replace(&mut node.keys[i - 1], left.keys.pop().unwrap());
+        child.keys.insert(0, sep);
+        if let Some(last) = left.children.pop() {
+            child.children.insert(0, last);
+        }
+    } else if i + 1 < node.children.len() && node.children[i + 1].keys.len() > MIN_KEYS {
+        let (child, right) = node.children.split_at_mut(i + 1);
+        let child = Arc::make_mut(&mut child[i]);
+        let right = Arc::make_mut(&mut right[0]);
+        let sep = std::mem::replace(&mut node.keys[i], right.keys.remove(0));
+        child.keys.push(sep);
+        if !right.is_leaf() {
+            child.children.push(right.children.remove(0));
+        }
+    } else {
+        // merge children[j] and children[j + 1]
+        let j = if i > 0 { i - 1 } else { i };
+        let sep = node.keys.remove(j);
+        let right = Arc::unwrap_or_clone(node.children.remove(j + 1));
+        let left = Arc::make_mut(&mut node.children[j]);
+        left.keys.push(sep);
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CowTree<K> {
+    root: Arc<Node<K>>,
+    len: usize,
+}
+
+impl<K: Ord + Clone> Default for CowTree<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone> CowTree<K> {
+    pub fn new() -> Self {
+        Self {
+            root: Arc::new(Node::leaf()),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.root.contains(key)
+    }
+
+    // insert `key`, false if it is already there
+    pub fn insert(&mut self, key: K) -> bool {
+        if self.contains(&key) {
+            return false;
+        }
+        if let Some((sep, right)) = insert(&mut self.root, key) {
+            let left = self.root.clone();
+            self.root = Arc::new(Node {
+                keys: vec![sep],
+                children: vec![left, right],
+            });
+        }
+        self.len += 1;
+        true
+    }
+
+    // delete `key`, false if it was not there
+    pub fn delete(&mut self, key: &K) -> bool {
+        // nothing gets copied for a key that is not there
+        if !self.contains(key) {
+            return false;
+        }
+        delete(&mut self.root, key);
+        // the root lost its last key, its only child is the new root
+        if self.root.keys.is_empty() && !self.root.is_leaf() {
+            self.root = self.root.children[0].clone();
+        }
+        self.len -= 1;
+        true
+    }
+
+    // the tree as it is now, unaffected by later changes
+    pub fn snapshot(&self) -> Snapshot<K> {
+        Snapshot {
+            root: self.root.clone(),
+            len: self.len,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K> {
+        Iter::new(&self.root)
+    }
+
+    // the keys from the first one not below `start` on, found in a single
+    // descent
+    pub fn iter_from(&self, start: Bound<&K>) -> Iter<'_, K> {
+        Iter::seek(&self.root, start)
+    }
+}
+
+// A read-only tree sharing its nodes with the tree it was taken from.
+#[derive(Debug, Clone)]
+pub struct Snapshot<K> {
+    root: Arc<Node<K>>,
+    len: usize,
+}
+
+impl<K: Ord + Clone> Snapshot<K> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.root.contains(key)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K> {
+        Iter::new(&self.root)
+    }
+
+    pub fn iter_from(&self, start: Bound<&K>) -> Iter<'_, K> {
+        Iter::seek(&self.root, start)
+    }
+}
+
+// The keys in order, keeping the path to the next key on a stack.
+pub struct Iter<'a, K> {
+    // a node and the index of its next key
+    stack: Vec<(&'a Node<K>, usize)>,
+}
+
+impl<'a, K> Iter<'a, K> {
+    fn new(root: &'a Node<K>) -> Self {
+        let mut iter = Self { stack: Vec::new() };
+        iter.push_leftmost(root);
+        iter
+    }
+
+    // positioned before the first key not below `start`: every node on the
+    // way down waits at the first of its keys that is not
+    fn seek(root: &'a Node<K>, start: Bound<&K>) -> Self
+    where
+        K: Ord,
+    {
+        let mut iter = Self { stack: Vec::new() };
+        let mut node = root;
+        loop {
+            let i = node.keys.partition_point(|k| match start {
+                Bound::Included(start) => k < start,
+                Bound::Excluded(start) => k <= start,
+                Bound::Unbounded => false,
+            });
+            iter.stack.push((node, i));
+            match node.children.get(i) {
+                Some(child) => node = child,
+                None => return iter,
+            }
+        }
+    }
+
+    fn push_leftmost(&mut self, mut node: &'a Node<K>) {
+        loop {
+            self.stack.push((node, 0));
+            match node.children.first() {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+    }
+}
+
+impl<'a, K> Iterator for Iter<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        loop {
+            let (node, i) = self.stack.last_mut()?;
+            let node: &'a Node<K> = node;
+            if *i < node.keys.len() {
+                let key = &node.keys[*i];
+                *i += 1;
+                if let Some(child) = node.children.get(*i) {
+                    self.push_leftmost(child);
+                }
+                return Some(key);
+            }
+            self.stack.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::index::conformance::rng;
+    use std::collections::BTreeSet;
+
+    // every leaf is at the same depth and no node has too few or too many keys
+    fn check<K: Ord + Clone>(node: &Node<K>, depth: usize, leaf_depth: &mut Option<usize>, is_root: bool) {
+        assert!(node.keys.len() <= MAX_KEYS);
+        assert!(is_root || node.keys.len() >= MIN_KEYS);
+        assert!(node.keys.windows(2).all(|w| w[0] < w[1]));
+        if node.is_leaf() {
+            assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+            return;
+        }
+        assert_eq!(node.children.len(), node.keys.len() + 1);
+        for child in &node.children {
+            check(child, depth + 1, leaf_depth, false);
+        }
+    }
+
+    #[test]
+    fn test_random() {
+        let mut tree = CowTree::new();
+        let mut expected = BTreeSet::new();
+        let mut next = rng(7);
+        for _ in 0..5000 {
+            let key = next() % 400;
+            if next().is_multiple_of(3) {
+                assert_eq!(tree.delete(&key), expected.remove(&key));
+            } else {
+                assert_eq!(tree.insert(key), expected.insert(key));
+            }
+            check(&tree.root, 0, &mut None, true);
+        }
+        assert_eq!(tree.len(), expected.len());
+        assert!(tree.iter().eq(expected.iter()));
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let mut tree = CowTree::new();
+        for key in (0..600).step_by(3) {
+            tree.insert(key);
+        }
+        for start in 0..605 {
+            let included: Vec<_> = (0..600).step_by(3).filter(|k| *k >= start).collect();
+            assert!(tree.iter_from(Bound::Included(&start)).eq(included.iter()));
+            let excluded: Vec<_> = (0..600).step_by(3).filter(|k| *k > start).collect();
+            assert!(tree.iter_from(Bound::Excluded(&start)).eq(excluded.iter()));
+        }
+        assert!(tree.iter_from(Bound::Unbounded).eq(tree.iter()));
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let mut tree = CowTree::new();
+        let mut snapshots = Vec::new();
+        let mut expected = BTreeSet::new();
+        let mut versions = Vec::new();
+        let mut next = rng(3);
+        for round in 0..50 {
+            for _ in 0..40 {
+                let key = next() % 300;
+                if round % 3 == 2 {
+                    tree.delete(&key);
+                    expected.remove(&key);
+                } else {
+                    tree.insert(key);
+                    expected.insert(key);
+                }
+            }
+            snapshots.push(tree.snapshot());
+            versions.push(expected.clone());
+        }
+        for (snapshot, keys) in snapshots.iter().zip(&versions) {
+            check(&snapshot.root, 0, &mut None, true);
+            assert_eq!(snapshot.len(), keys.len());
+            assert!(snapshot.iter().eq(keys.iter()));
+        }
+
+        // a change copies one path and shares the rest
+        let before = tree.snapshot();
+        let key = (0..).find(|k| !tree.contains(k)).unwrap();
+        tree.insert(key);
+        assert!(!Arc::ptr_eq(&before.root, &tree.root));
+        let shared = tree.root.children.iter().filter(|c| before.root.children.iter().any(|b| Arc::ptr_eq(b, c))).count();
+        assert!(shared >= tree.root.children.len() - 2);
+        assert!(!before.contains(&key));
+    }
+
+    #[test]
+    fn test_concurrent_readers() {
+        let mut tree = CowTree::new();
+        for key in 0..2000u64 {
+            tree.insert(key * 2);
+        }
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let snapshot = tree.snapshot();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        assert!(snapshot.iter().copied().eq((0..2000).map(|k| k * 2)));
+                    }
+                })
+            })
+            .collect();
+        let mut next = rng(9);
+        for _ in 0..20_000 {
+            let key = next() % 4000;
+            if next().is_multiple_of(2) {
+                tree.insert(key);
+            } else {
+                tree.delete(&key);
+            }
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        check(&tree.root, 0, &mut None, true);
+    }
+}
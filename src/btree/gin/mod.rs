@@ -0,0 +1,432 @@
+#![allow(dead_code)]
+
+// An inverted index, like PostgreSQL's GIN: a row comes with many keys,
+// the elements of an array or the words of a text, and the index maps
+// every key to the rows that have it.
+//
+// The keys are in a `v2` tree, the entry tree. An entry holds its key and
+// the number of its postings, the row ids with that key, the way a GIN
+// entry points at the root page of a posting tree. Postings start out as
+// a posting list, compressed as the differences of the sorted row ids in
+// a variable number of bytes each, which is a byte or two per row for the
+// dense ids of a table. Past `LIST_MAX` rows a list becomes a posting tree
+// of its own, a counted `v2` tree of row ids, and below half of that it
+// is compressed back into a list.
+//
+// `contains` finds the rows having all of some keys, like `@>`, by
+// intersecting their postings from the shortest, `overlaps` those having
+// any of them, like `&&`. Rows without keys are kept apart so that every
+// row contains no keys at all, as `'{}'` is contained in every array.
+
+use std::ops::Bound;
+
+use super::collate::{ByKey, Keyed};
+use super::v2::Node;
+
+// the row ids a posting list holds before it becomes a tree
+const LIST_MAX: usize = 64;
+
+// row ids in ascending order, each stored as the difference to the one
+// before it in 7 bit groups, lowest first, the high bit set on all but
+// the last byte of a number
+#[derive(Debug, Clone, Default)]
+struct PostingList {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl PostingList {
+    fn encode(rows: &[u64]) -> Self {
+        let mut bytes = Vec::new();
+        let mut prev = 0;
+        for &row in rows {
+            let mut delta = row - prev;
+            prev = row;
+            while delta >= 0x80 {
+                bytes.push(delta as u8 | 0x80);
+                delta >>= 7;
+            }
+            bytes.push(delta as u8);
+        }
+        Self { bytes, len: rows.len() }
+    }
+
+    fn decode(&self) -> Vec<u64> {
+        let mut rows = Vec::with_capacity(self.len);
+        let (mut prev, mut delta, mut shift) = (0, 0, 0);
+        for &byte in &self.bytes {
+            delta |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 != 0 {
+                shift += 7;
+                continue;
+            }
+            prev += delta;
+            rows.push(prev);
+            (delta, shift) = (0, 0);
+        }
+        rows
+    }
+}
+
+#[derive(Debug)]
+enum Postings {
+    List(PostingList),
+    Tree(Node<u64>),
+}
+
+impl Default for Postings {
+    fn default() -> Self {
+        Postings::List(PostingList::default())
+    }
+}
+
+impl Postings {
+    fn len(&self) -> usize {
+        match self {
+            Postings::List(list) => list.len,
+            Postings::Tree(tree) => tree.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn rows(&self) -> Vec<u64> {
+        match self {
+            Postings::List(list) => list.decode(),
+            Postings::Tree(tree) => tree.range(..).into_iter().copied().collect(),
+        }
+    }
+
+    // keep only the `rows` found here too
+    fn intersect(&self, rows: &mut Vec<u64>) {
+        match self {
+            Postings::List(list) => {
+                let mine = list.decode();
+                rows.retain(|row| mine.binary_search(row).is_ok());
+            }
+            Postings::Tree(tree) => rows.retain(|row| tree.first_from(Bound::Included(row)) == Some(row)),
+        }
+    }
+
+    // add `row`, false if it is already there
+    fn insert(&mut self, row: u64) -> bool {
+        let rows = match self {
+            Postings::Tree(tree) => {
+                if tree.first_from(Bound::Included(&row)) == Some(&row) {
+                    return false;
+                }
+                tree.insert(row);
+                return true;
+            }
+            Postings::List(list) => {
+                let mut rows = list.decode();
+                let Err(pos) = rows.binary_search(&row) else {
+                    return false;
+                };
+                rows.insert(pos, row);
+                rows
+            }
+        };
+        *self = match rows.len() > LIST_MAX {
+            true => {
+                let mut tree = Node::new_counted();
+                for row in rows {
+                    tree.insert(row);
+                }
+                Postings::Tree(tree)
+            }
+            false => Postings::List(PostingList::encode(&rows)),
+        };
+        true
+    }
+
+    // remove `row`, false if it was not there
+    fn delete(&mut self, row: u64) -> bool {
+        let rows = match self {
+            Postings::Tree(tree) => {
+                if tree.first_from(Bound::Included(&row)) != Some(&row) {
+                    return false;
+                }
+                tree.delete(row);
+                if tree.len() > LIST_MAX / 2 {
+                    return true;
+                }
+                self.rows()
+            }
+            Postings::List(list) => {
+                let mut rows = list.decode();
+                let Ok(pos) = rows.binary_search(&row) else {
+                    return false;
+                };
+                rows.remove(pos);
+                rows
+            }
+        };
+        *self = Postings::List(PostingList::encode(&rows));
+        true
+    }
+}
+
+// a key of the entry tree and its postings, the tree orders them `ByKey`
+#[derive(Debug, Clone, Default)]
+struct Entry<K> {
+    key: K,
+    postings: usize,
+}
+
+impl<K: Ord> Keyed for Entry<K> {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+fn probe<K>(key: K) -> Entry<K> {
+    Entry { key, postings: 0 }
+}
+
+#[derive(Debug)]
+pub struct GinIndex<K: Ord + Clone + Default> {
+    entries: Node<Entry<K>, ByKey>,
+    // indexed by `Entry::postings`, the free ones empty and in `free`
+    postings: Vec<Postings>,
+    free: Vec<usize>,
+    // the rows that came without keys
+    empty: Postings,
+}
+
+impl<K: Ord + Clone + Default> Default for GinIndex<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone + Default> GinIndex<K> {
+    pub fn new() -> Self {
+        Self {
+            entries: Node::default_counted(),
+            postings: Vec::new(),
+            free: Vec::new(),
+            empty: Postings::default(),
+        }
+    }
+
+    // the number of distinct keys
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn slot(&self, key: &K) -> Option<usize> {
+        let probe = probe(key.clone());
+        self.entries.first_from(Bound::Included(&probe)).filter(|e| e.key == *key).map(|e| e.postings)
+    }
+
+    // add `row` with `keys`, repeated keys count once
+    pub fn insert(&mut self, row: u64, keys: impl IntoIterator<Item = K>) {
+        let mut any = false;
+        for key in keys {
+            any = true;
+            let slot = match self.slot(&key) {
+                Some(slot) => slot,
+                None => {
+                    let slot = self.free.pop().unwrap_or_else(|| {
+                        self.postings.push(Postings::default());
+                        self.postings.len() - 1
+                    });
+                    self.entries.insert(Entry { key, postings: slot });
+                    slot
+                }
+            };
+            self.postings[slot].insert(row);
+        }
+        if !any {
+            self.empty.insert(row);
+        }
+    }
+
+    // remove `row`, which was added with `keys`. A key left without rows
+    // leaves the entry tree.
+    pub fn delete(&mut self, row: u64, keys: impl IntoIterator<Item = K>) {
+        let mut any = false;
+        for key in keys {
+            any = true;
+            let Some(slot) = self.slot(&key) else {
+                continue;
+            };
+            if self.postings[slot].delete(row) && self.postings[slot].is_empty() {
+                self.entries.delete(probe(key));
+                self.postings[slot] = Postings::default();
+                self.free.push(slot);
+            }
+        }
+        if !any {
+            self.empty.delete(row);
+        }
+    }
+
+    // the rows with `key`, in order
+    pub fn rows(&self, key: &K) -> Vec<u64> {
+        self.slot(key).map_or_else(Vec::new, |slot| self.postings[slot].rows())
+    }
+
+    // the rows with all of `keys`, in order, like `@>`
+    pub fn contains(&self, keys: &[K]) -> Vec<u64> {
+        if keys.is_empty() {
+            return self.all();
+        }
+        let Some(mut slots) = keys.iter().map(|k| self.slot(k)).collect::<Option<Vec<usize>>>() else {
+            return Vec::new();
+        };
+        slots.sort_by_key(|&slot| self.postings[slot].len());
+        let mut rows = self.postings[slots[0]].rows();
+        for &slot in &slots[1..] {
+            if rows.is_empty() {
+                break;
+            }
+            self.postings[slot].intersect(&mut rows);
+        }
+        rows
+    }
+
+    // the rows with any of `keys`, in order, like `&&`
+    pub fn overlaps(&self, keys: &[K]) -> Vec<u64> {
+        let mut rows: Vec<u64> = keys.iter().filter_map(|k| self.slot(k)).flat_map(|slot| self.postings[slot].rows()).collect();
+        rows.sort();
+        rows.dedup();
+        rows
+    }
+
+    // every row, read from all postings
+    fn all(&self) -> Vec<u64> {
+        let mut rows = self.empty.rows();
+        rows.extend(self.postings.iter().flat_map(|p| p.rows()));
+        rows.sort();
+        rows.dedup();
+        rows
+    }
+}
+
+// The keys of a text for a full text index: its words in lower case,
+// sorted and each once, like a `tsvector` without positions.
+pub fn tokens(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase).collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::index::conformance::rng;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_posting_list() {
+        let rows: Vec<u64> = vec![0, 1, 2, 127, 128, 300, 16_384, 1 << 40, u64::MAX];
+        assert_eq!(PostingList::encode(&rows).decode(), rows);
+        // dense row ids take a byte each
+        let rows: Vec<u64> = (1000..1064).chain((2000..3000).step_by(3)).collect();
+        let list = PostingList::encode(&rows);
+        assert_eq!(list.decode(), rows);
+        assert_eq!(list.bytes.len(), rows.len() + 2);
+        assert!(PostingList::encode(&[]).decode().is_empty());
+    }
+
+    #[test]
+    fn test_postings() {
+        let mut postings = Postings::default();
+        for row in (0..200).rev() {
+            assert!(postings.insert(row * 2));
+            assert!(!postings.insert(row * 2));
+        }
+        assert!(matches!(postings, Postings::Tree(_)) && postings.len() == 200);
+        // a list again only once well below `LIST_MAX`
+        for row in 0..170 {
+            assert!(postings.delete(row * 2));
+            assert!(!postings.delete(row * 2));
+        }
+        assert!(matches!(postings, Postings::List(_)));
+        assert_eq!(postings.rows(), (170..200).map(|r| r * 2).collect::<Vec<_>>());
+        let mut rows = vec![1, 340, 341, 398, 400];
+        postings.intersect(&mut rows);
+        assert_eq!(rows, [340, 398]);
+    }
+
+    // every query against the arrays of the rows still there
+    fn check(index: &GinIndex<i64>, arrays: &[Option<Vec<i64>>]) {
+        let queries: [&[i64]; 6] = [&[], &[0], &[1, 2], &[0, 1, 3], &[39], &[38, 39, 100]];
+        for query in queries {
+            let want = |f: &dyn Fn(&[i64]) -> bool| -> Vec<u64> {
+                let rows = arrays.iter().enumerate().filter_map(|(r, a)| a.as_ref().map(|a| (r, a)));
+                rows.filter(|(_, a)| f(a)).map(|(r, _)| r as u64).collect()
+            };
+            let contains = want(&|array| query.iter().all(|k| array.contains(k)));
+            let overlaps = want(&|array| query.iter().any(|k| array.contains(k)));
+            assert_eq!(index.contains(query), contains, "@> {:?}", query);
+            assert_eq!(index.overlaps(query), overlaps, "&& {:?}", query);
+        }
+    }
+
+    #[test]
+    fn test_arrays() {
+        let mut next = rng(0x51ed27);
+        // arrays of up to 6 of 40 elements, the small ones far more common,
+        // and some empty
+        let mut arrays: Vec<Option<Vec<i64>>> = (0..600)
+            .map(|_| {
+                let n = next() % 7;
+                Some((0..n).map(|_| (next() % 40).min(next() % 40) as i64).collect())
+            })
+            .collect();
+        let mut index = GinIndex::new();
+        for (row, array) in arrays.iter().enumerate() {
+            index.insert(row as u64, array.iter().flatten().copied());
+        }
+        assert!(index.postings.iter().any(|p| matches!(p, Postings::Tree(_))));
+        assert!(index.postings.iter().any(|p| matches!(p, Postings::List(_))));
+        check(&index, &arrays);
+
+        for row in (0..600).filter(|r| r % 3 != 0) {
+            let array = arrays[row].take().unwrap();
+            index.delete(row as u64, array);
+        }
+        check(&index, &arrays);
+        assert!(index.postings.iter().all(|p| matches!(p, Postings::List(_))));
+        let keys: BTreeSet<i64> = arrays.iter().flatten().flatten().copied().collect();
+        assert_eq!(index.len(), keys.len());
+        assert_eq!(index.free.len(), index.postings.len() - keys.len());
+    }
+
+    #[test]
+    fn test_text() {
+        let docs = [
+            "The quick brown fox jumps over the lazy dog.",
+            "A quick movement of the enemy will jeopardize six gunboats",
+            "Sphinx of black quartz, judge my vow!",
+            "The five boxing wizards jump quickly",
+        ];
+        let mut index = GinIndex::new();
+        for (row, doc) in docs.iter().enumerate() {
+            index.insert(row as u64, tokens(doc));
+        }
+        assert_eq!(tokens("Fox, fox; FOX!"), ["fox"]);
+        let words = |s: &str| tokens(s);
+        assert_eq!(index.contains(&words("quick the")), [0, 1]);
+        assert_eq!(index.contains(&words("QUICK fox")), [0]);
+        assert!(index.contains(&words("quick wizards")).is_empty());
+        assert_eq!(index.overlaps(&words("quick wizards")), [0, 1, 3]);
+        assert_eq!(index.rows(&"of".to_string()), [1, 2]);
+
+        index.delete(0, tokens(docs[0]));
+        assert_eq!(index.contains(&words("the")), [1, 3]);
+        assert!(index.rows(&"fox".to_string()).is_empty());
+    }
+}
@@ -0,0 +1,632 @@
+#![allow(dead_code)]
+
+// A generalized search tree: a balanced tree whose keys are predicates
+// over the keys below them rather than separators, like PostgreSQL's GiST.
+//
+// What a key means is up to an `OpClass`: a leaf key is a value, an inner
+// key the `union` of its child's keys, so that a search descends into
+// every child whose key is `consistent` with the query. An insert goes
+// down the child whose key the new key grows the least (`penalty`), and a
+// node that overflows is split in two by `picksplit`. Nodes are managed
+// like in `v2`: a split adds an entry to the parent and may split it in
+// turn, up to a new root; a node left with too few entries by a delete
+// takes the entries of a sibling, the one it grows the least, and the two
+// are either merged or, when that is too many entries, split anew. So all
+// leaves stay at the same depth and every node but the root holds at
+// least `MIN_ENTRIES`.
+//
+// `BoxOps` makes the tree an R-tree over 2D boxes, points being boxes of
+// no size, for overlap, containment and nearest-neighbour queries.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt::Debug;
+
+const MAX_ENTRIES: usize = 6;
+// the fewest entries a node other than the root may hold
+const MIN_ENTRIES: usize = MAX_ENTRIES / 2;
+
+pub trait OpClass {
+    type Key: Clone + Debug;
+    type Query;
+
+    // whether keys under `key` may match `query`, or for a leaf key,
+    // whether it does
+    fn consistent(&self, key: &Self::Key, query: &Self::Query, is_leaf: bool) -> bool;
+
+    // a key covering all of `keys`, never empty
+    fn union(&self, keys: &[Self::Key]) -> Self::Key;
+
+    // how much worse `existing` gets by covering `new` too
+    fn penalty(&self, existing: &Self::Key, new: &Self::Key) -> f64;
+
+    // split `keys` into two groups of at least `min` keys each, returning
+    // the positions of either group
+    fn picksplit(&self, keys: &[Self::Key], min: usize) -> (Vec<usize>, Vec<usize>);
+
+    // whether two keys are equal, which finds the entry to delete
+    fn same(&self, a: &Self::Key, b: &Self::Key) -> bool;
+
+    // the distance from `key` to `origin`, for a key under an inner key
+    // never less than from the inner key
+    fn distance(&self, key: &Self::Key, origin: &Self::Key) -> f64;
+}
+
+// keys[i] is a leaf's i-th key with values[i] or, in an inner node, the
+// union of children[i]. A node without children is a leaf.
+#[derive(Debug, Clone)]
+struct Node<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    children: Vec<Node<K, V>>,
+}
+
+impl<K, V> Node<K, V> {
+    fn leaf() -> Self {
+        Self {
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    // move the entries in `from` to the end of this node
+    fn append(&mut self, from: Node<K, V>) {
+        self.keys.extend(from.keys);
+        self.values.extend(from.values);
+        self.children.extend(from.children);
+    }
+}
+
+// the items at the positions of `right` move to the second vector
+fn partition<T>(items: Vec<T>, right: &[bool]) -> (Vec<T>, Vec<T>) {
+    let (mut a, mut b) = (Vec::new(), Vec::new());
+    for (item, &r) in items.into_iter().zip(right) {
+        if r {
+            b.push(item)
+        } else {
+            a.push(item)
+        }
+    }
+    (a, b)
+}
+
+// a node to visit or a leaf entry, by its distance in a nearest search
+enum Candidate<'a, K, V> {
+    Node(&'a Node<K, V>),
+    Entry(&'a K, &'a V),
+}
+
+struct Ranked<'a, K, V>(f64, Candidate<'a, K, V>);
+
+impl<K, V> Ord for Ranked<'_, K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl<K, V> PartialOrd for Ranked<'_, K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K, V> PartialEq for Ranked<'_, K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K, V> Eq for Ranked<'_, K, V> {}
+
+#[derive(Debug, Clone)]
+pub struct GistTree<O: OpClass, V> {
+    op: O,
+    root: Node<O::Key, V>,
+    len: usize,
+}
+
+impl<O: OpClass, V> GistTree<O, V> {
+    pub fn new(op: O) -> Self {
+        Self {
+            op,
+            root: Node::leaf(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, key: O::Key, value: V) {
+        Self::insert_into(&self.op, &mut self.root, key, value);
+        if self.root.len() > MAX_ENTRIES {
+            let left = std::mem::replace(&mut self.root, Node::leaf());
+            let (left, right) = Self::split(&self.op, left);
+            self.root.keys = vec![self.op.union(&left.keys), self.op.union(&right.keys)];
+            self.root.children = vec![left, right];
+        }
+        self.len += 1;
+    }
+
+    fn insert_into(op: &O, node: &mut Node<O::Key, V>, key: O::Key, value: V) {
+        if node.is_leaf() {
+            node.keys.push(key);
+            node.values.push(value);
+            return;
+        }
+        let penalties = node.keys.iter().map(|k| op.penalty(k, &key));
+        let (i, _) = penalties.enumerate().min_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+        Self::insert_into(op, &mut node.children[i], key, value);
+        if node.children[i].len() > MAX_ENTRIES {
+            let child = node.children.remove(i);
+            node.keys.remove(i);
+            let (left, right) = Self::split(op, child);
+            node.keys.insert(i, op.union(&right.keys));
+            node.children.insert(i, right);
+            node.keys.insert(i, op.union(&left.keys));
+            node.children.insert(i, left);
+        } else {
+            node.keys[i] = op.union(&node.children[i].keys);
+        }
+    }
+
+    // split `node` by `picksplit` into two of the same level
+    fn split(op: &O, node: Node<O::Key, V>) -> (Node<O::Key, V>, Node<O::Key, V>) {
+        let (left, right) = op.picksplit(&node.keys, MIN_ENTRIES);
+        assert!(
+            left.len() >= MIN_ENTRIES && right.len() >= MIN_ENTRIES && left.len() + right.len() == node.len(),
+            "picksplit split {} keys into {} and {}",
+            node.len(),
+            left.len(),
+            right.len()
+        );
+        let mut to_right = vec![false; node.len()];
+        for i in right {
+            to_right[i] = true;
+        }
+        let (keys_a, keys_b) = partition(node.keys, &to_right);
+        let (values_a, values_b) = partition(node.values, &to_right);
+        let (children_a, children_b) = partition(node.children, &to_right);
+        let a = Node {
+            keys: keys_a,
+            values: values_a,
+            children: children_a,
+        };
+        let b = Node {
+            keys: keys_b,
+            values: values_b,
+            children: children_b,
+        };
+        (a, b)
+    }
+
+    // all entries whose key matches `query`
+    pub fn search(&self, query: &O::Query) -> Vec<(&O::Key, &V)> {
+        let mut out = Vec::new();
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            for (i, key) in node.keys.iter().enumerate() {
+                if !self.op.consistent(key, query, node.is_leaf()) {
+                    continue;
+                }
+                match node.is_leaf() {
+                    true => out.push((key, &node.values[i])),
+                    false => stack.push(&node.children[i]),
+                }
+            }
+        }
+        out
+    }
+
+    // the `k` entries nearest to `origin`, nearest first. Nodes are
+    // visited best first, by the distance of their keys, so that a node
+    // further away than the `k`-th entry found is never read.
+    pub fn nearest(&self, origin: &O::Key, k: usize) -> Vec<(&O::Key, &V)> {
+        let mut out = Vec::new();
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse(Ranked(0.0, Candidate::Node(&self.root))));
+        while let Some(Reverse(Ranked(_, candidate))) = queue.pop() {
+            if out.len() == k {
+                break;
+            }
+            match candidate {
+                Candidate::Entry(key, value) => out.push((key, value)),
+                Candidate::Node(node) => {
+                    for (i, key) in node.keys.iter().enumerate() {
+                        let next = match node.is_leaf() {
+                            true => Candidate::Entry(key, &node.values[i]),
+                            false => Candidate::Node(&node.children[i]),
+                        };
+                        queue.push(Reverse(Ranked(self.op.distance(key, origin), next)));
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl<O: OpClass, V: PartialEq> GistTree<O, V> {
+    // delete the entry of `key` and `value`, false if there is none
+    pub fn delete(&mut self, key: &O::Key, value: &V) -> bool {
+        if !Self::delete_from(&self.op, &mut self.root, key, value) {
+            return false;
+        }
+        if !self.root.is_leaf() && self.root.len() == 1 {
+            self.root = self.root.children.pop().unwrap();
+        }
+        self.len -= 1;
+        true
+    }
+
+    fn delete_from(op: &O, node: &mut Node<O::Key, V>, key: &O::Key, value: &V) -> bool {
+        if node.is_leaf() {
+            let Some(i) = (0..node.len()).find(|&i| op.same(&node.keys[i], key) && node.values[i] == *value) else {
+                return false;
+            };
+            node.keys.remove(i);
+            node.values.remove(i);
+            return true;
+        }
+        // only children whose key covers `key` may hold it
+        for i in 0..node.len() {
+            let covers = op.same(&op.union(&[node.keys[i].clone(), key.clone()]), &node.keys[i]);
+            if covers && Self::delete_from(op, &mut node.children[i], key, value) {
+                if node.children[i].len() < MIN_ENTRIES && node.len() > 1 {
+                    Self::fill_child(op, node, i);
+                } else {
+                    node.keys[i] = op.union(&node.children[i].keys);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    // give children[i], which has too few entries, those of the sibling
+    // it grows the least: both merge into one node or, if too many for
+    // one, are split in two anew
+    fn fill_child(op: &O, node: &mut Node<O::Key, V>, i: usize) {
+        let union = op.union(&node.children[i].keys);
+        let siblings = (0..node.len()).filter(|&j| j != i);
+        let j = siblings.min_by(|&a, &b| op.penalty(&node.keys[a], &union).total_cmp(&op.penalty(&node.keys[b], &union))).unwrap();
+        let (first, second) = (i.min(j), i.max(j));
+        let mut merged = node.children.remove(first);
+        merged.append(node.children.remove(second - 1));
+        node.keys.remove(second);
+        node.keys.remove(first);
+        if merged.len() <= MAX_ENTRIES {
+            node.keys.insert(first, op.union(&merged.keys));
+            node.children.insert(first, merged);
+            return;
+        }
+        let (left, right) = Self::split(op, merged);
+        node.keys.insert(first, op.union(&right.keys));
+        node.children.insert(first, right);
+        node.keys.insert(first, op.union(&left.keys));
+        node.children.insert(first, left);
+    }
+}
+
+// An axis-aligned box, a point when both corners are the same.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x1: f64,
+    pub y1: f64,
+    pub x2: f64,
+    pub y2: f64,
+}
+
+impl Rect {
+    // the box between two corners in any order
+    pub fn new(x1: f64, y1: f64, x2: f64, y2: f64) -> Self {
+        Self {
+            x1: x1.min(x2),
+            y1: y1.min(y2),
+            x2: x1.max(x2),
+            y2: y1.max(y2),
+        }
+    }
+
+    pub fn point(x: f64, y: f64) -> Self {
+        Self::new(x, y, x, y)
+    }
+
+    pub fn area(&self) -> f64 {
+        (self.x2 - self.x1) * (self.y2 - self.y1)
+    }
+
+    // half the perimeter
+    pub fn margin(&self) -> f64 {
+        (self.x2 - self.x1) + (self.y2 - self.y1)
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+            x2: self.x2.max(other.x2),
+            y2: self.y2.max(other.y2),
+        }
+    }
+
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x1 <= other.x2 && other.x1 <= self.x2 && self.y1 <= other.y2 && other.y1 <= self.y2
+    }
+
+    pub fn contains(&self, other: &Rect) -> bool {
+        self.x1 <= other.x1 && other.x2 <= self.x2 && self.y1 <= other.y1 && other.y2 <= self.y2
+    }
+
+    // the shortest distance between a point of either box, 0 if they overlap
+    pub fn distance(&self, other: &Rect) -> f64 {
+        let dx = (other.x1 - self.x2).max(self.x1 - other.x2).max(0.0);
+        let dy = (other.y1 - self.y2).max(self.y1 - other.y2).max(0.0);
+        dx.hypot(dy)
+    }
+
+    // what covering a box costs: its area, and its margin so that points
+    // and lines, which have no area, still grow
+    fn cost(&self) -> f64 {
+        self.area() + self.margin()
+    }
+}
+
+// the operators of a box query, like PostgreSQL's `&&`, `@>` and `<@`
+#[derive(Debug, Clone, Copy)]
+pub enum BoxQuery {
+    Overlaps(Rect),
+    Contains(Rect),
+    ContainedBy(Rect),
+}
+
+// The R-tree operator class for `Rect`, with Guttman's quadratic split.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BoxOps;
+
+impl OpClass for BoxOps {
+    type Key = Rect;
+    type Query = BoxQuery;
+
+    fn consistent(&self, key: &Rect, query: &BoxQuery, is_leaf: bool) -> bool {
+        match query {
+            BoxQuery::Overlaps(q) => key.overlaps(q),
+            BoxQuery::Contains(q) => key.contains(q),
+            BoxQuery::ContainedBy(q) if is_leaf => q.contains(key),
+            // a box within `q` may be under any key that overlaps it
+            BoxQuery::ContainedBy(q) => key.overlaps(q),
+        }
+    }
+
+    fn union(&self, keys: &[Rect]) -> Rect {
+        keys[1..].iter().fold(keys[0], |acc, k| acc.union(k))
+    }
+
+    fn penalty(&self, existing: &Rect, new: &Rect) -> f64 {
+        existing.union(new).cost() - existing.cost()
+    }
+
+    fn picksplit(&self, keys: &[Rect], min: usize) -> (Vec<usize>, Vec<usize>) {
+        // the seeds are the two keys that waste the most room together
+        let mut seeds = (0, 1);
+        let mut worst = f64::NEG_INFINITY;
+        for i in 0..keys.len() {
+            for j in i + 1..keys.len() {
+                let waste = keys[i].union(&keys[j]).cost() - keys[i].cost() - keys[j].cost();
+                if waste > worst {
+                    (seeds, worst) = ((i, j), waste);
+                }
+            }
+        }
+        let (mut left, mut right) = (vec![seeds.0], vec![seeds.1]);
+        let (mut left_box, mut right_box) = (keys[seeds.0], keys[seeds.1]);
+        let mut rest: Vec<usize> = (0..keys.len()).filter(|&i| i != seeds.0 && i != seeds.1).collect();
+        while !rest.is_empty() {
+            // the rest all go to a group that needs them to reach `min`
+            if left.len() + rest.len() == min {
+                left.append(&mut rest);
+                break;
+            }
+            if right.len() + rest.len() == min {
+                right.append(&mut rest);
+                break;
+            }
+            // next the key that prefers one group the most
+            let growth = |i: usize| (self.penalty(&left_box, &keys[i]), self.penalty(&right_box, &keys[i]));
+            let preference = |&i: &usize| {
+                let (l, r) = growth(i);
+                (l - r).abs()
+            };
+            let pos = (0..rest.len()).max_by(|&a, &b| preference(&rest[a]).total_cmp(&preference(&rest[b]))).unwrap();
+            let i = rest.swap_remove(pos);
+            let (l, r) = growth(i);
+            let to_left = match l.total_cmp(&r) {
+                Ordering::Less => true,
+                Ordering::Greater => false,
+                Ordering::Equal => (left_box.cost(), left.len()) <= (right_box.cost(), right.len()),
+            };
+            if to_left {
+                left.push(i);
+                left_box = left_box.union(&keys[i]);
+            } else {
+                right.push(i);
+                right_box = right_box.union(&keys[i]);
+            }
+        }
+        (left, right)
+    }
+
+    fn same(&self, a: &Rect, b: &Rect) -> bool {
+        a == b
+    }
+
+    fn distance(&self, key: &Rect, origin: &Rect) -> f64 {
+        key.distance(origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::index::conformance::rng;
+
+    // random boxes up to 10 wide in a 1000 square, every `points`-th a point
+    fn boxes(n: usize, points: usize) -> Vec<Rect> {
+        let mut next = rng(0x9e3779b9);
+        let mut coord = move |max: u64| (next() % max) as f64;
+        (0..n)
+            .map(|i| {
+                let (x, y) = (coord(1000), coord(1000));
+                match i % points == 0 {
+                    true => Rect::point(x, y),
+                    false => Rect::new(x, y, x + coord(10), y + coord(10)),
+                }
+            })
+            .collect()
+    }
+
+    // the structure holds: keys are the unions of their children, every
+    // node but the root is filled to at least half, leaves are level
+    fn check<O: OpClass, V>(tree: &GistTree<O, V>) {
+        fn walk<O: OpClass, V>(op: &O, node: &Node<O::Key, V>, depth: usize, root: bool, leaf_depth: &mut Option<usize>) -> usize {
+            assert!(node.len() <= MAX_ENTRIES && (root || node.len() >= MIN_ENTRIES), "a node of {} entries", node.len());
+            if node.is_leaf() {
+                assert_eq!(node.values.len(), node.len());
+                assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+                return node.len();
+            }
+            assert!(node.values.is_empty() && node.children.len() == node.len());
+            let mut count = 0;
+            for (key, child) in node.keys.iter().zip(&node.children) {
+                assert!(op.same(key, &op.union(&child.keys)));
+                count += walk(op, child, depth + 1, false, leaf_depth);
+            }
+            count
+        }
+        assert_eq!(walk(&tree.op, &tree.root, 0, true, &mut None), tree.len());
+    }
+
+    fn ids(mut entries: Vec<(&Rect, &usize)>) -> Vec<usize> {
+        entries.sort_by_key(|(_, &id)| id);
+        entries.into_iter().map(|(_, &id)| id).collect()
+    }
+
+    fn queries() -> Vec<BoxQuery> {
+        let mut queries = Vec::new();
+        for q in boxes(30, 5).into_iter().map(|b| Rect::new(b.x1, b.y1, b.x1 + 80.0, b.y1 + 50.0)) {
+            queries.push(BoxQuery::Overlaps(q));
+            queries.push(BoxQuery::ContainedBy(q));
+        }
+        for q in boxes(30, 1) {
+            queries.push(BoxQuery::Contains(q));
+        }
+        queries
+    }
+
+    fn brute_force(all: &[(Rect, usize)], query: &BoxQuery) -> Vec<usize> {
+        let keep = |r: &Rect| match query {
+            BoxQuery::Overlaps(q) => r.overlaps(q),
+            BoxQuery::Contains(q) => r.contains(q),
+            BoxQuery::ContainedBy(q) => q.contains(r),
+        };
+        let mut ids: Vec<usize> = all.iter().filter(|(r, _)| keep(r)).map(|&(_, id)| id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_search() {
+        let all: Vec<(Rect, usize)> = boxes(2000, 4).into_iter().zip(0..).collect();
+        let mut tree = GistTree::new(BoxOps);
+        for (i, &(rect, id)) in all.iter().enumerate() {
+            tree.insert(rect, id);
+            if i % 200 == 0 {
+                check(&tree);
+            }
+        }
+        check(&tree);
+        let mut matched = 0;
+        for query in queries() {
+            let want = brute_force(&all, &query);
+            matched += want.len();
+            assert_eq!(ids(tree.search(&query)), want, "{:?}", query);
+        }
+        assert!(matched > 100);
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut all: Vec<(Rect, usize)> = boxes(1500, 3).into_iter().zip(0..).collect();
+        let mut tree = GistTree::new(BoxOps);
+        for &(rect, id) in &all {
+            tree.insert(rect, id);
+        }
+        // the same box twice tells entries apart by value
+        tree.insert(all[0].0, 9999);
+        assert!(tree.delete(&all[0].0, &9999) && !tree.delete(&all[0].0, &9999));
+        assert!(!tree.delete(&Rect::point(-1.0, -1.0), &0));
+
+        let mut next = rng(7);
+        while all.len() > 100 {
+            let (rect, id) = all.swap_remove(next() as usize % all.len());
+            assert!(tree.delete(&rect, &id));
+            if all.len().is_multiple_of(100) {
+                check(&tree);
+                for query in queries().iter().step_by(7) {
+                    assert_eq!(ids(tree.search(query)), brute_force(&all, query));
+                }
+            }
+        }
+        for (rect, id) in all.drain(..) {
+            assert!(tree.delete(&rect, &id));
+        }
+        check(&tree);
+        assert!(tree.is_empty() && tree.root.is_leaf());
+    }
+
+    #[test]
+    fn test_nearest() {
+        let points: Vec<Rect> = boxes(1000, 1);
+        let mut tree = GistTree::new(BoxOps);
+        for (id, &point) in points.iter().enumerate() {
+            tree.insert(point, id);
+        }
+        for origin in [Rect::point(500.0, 500.0), Rect::point(0.0, 990.0), Rect::new(100.0, 100.0, 200.0, 120.0)] {
+            let found: Vec<f64> = tree.nearest(&origin, 10).iter().map(|(r, _)| r.distance(&origin)).collect();
+            let mut want: Vec<f64> = points.iter().map(|r| r.distance(&origin)).collect();
+            want.sort_by(f64::total_cmp);
+            assert_eq!(found, want[..10]);
+        }
+        assert_eq!(tree.nearest(&Rect::point(0.0, 0.0), 5000).len(), 1000);
+        assert!(GistTree::<BoxOps, u32>::new(BoxOps).nearest(&Rect::point(0.0, 0.0), 3).is_empty());
+    }
+
+    #[test]
+    fn test_picksplit() {
+        // two clusters come apart
+        let keys: Vec<Rect> = [0.0, 1.0, 2.0, 100.0, 101.0, 102.0, 3.0].iter().map(|&x| Rect::point(x, x)).collect();
+        let (mut left, mut right) = BoxOps.picksplit(&keys, 3);
+        left.sort();
+        right.sort();
+        assert_eq!((left, right), (vec![0, 1, 2, 6], vec![3, 4, 5]));
+        // however lopsided, both groups get `min` keys
+        let mut keys = vec![Rect::point(1000.0, 1000.0)];
+        keys.extend((0..6).map(|i| Rect::point(i as f64, 0.0)));
+        let (left, right) = BoxOps.picksplit(&keys, 3);
+        assert!(left.len() >= 3 && right.len() >= 3);
+    }
+}
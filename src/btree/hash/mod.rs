@@ -0,0 +1,295 @@
+#![allow(dead_code)]
+
+// A linear hash index, for lookups by equality alone.
+//
+// Keys live in buckets of fixed size pages: a primary page and, once it
+// is full, a chain of overflow pages. The bucket of a key is its hash
+// modulo the number of buckets, which grows one bucket at a time: when
+// the keys fill more than `FILL` of the primary pages, the bucket at
+// `next` is split, half of its keys (by one more bit of their hash) move
+// to a new bucket at the end, and `next` moves on. Once every bucket of
+// a round has been split the number of buckets has doubled and the next
+// round begins. Buckets that have not been split yet in a round are
+// addressed by one bit less, so the table never has to be rehashed at
+// once, and a bucket that gets more keys than its turn to split comes
+// around holds them in overflow pages.
+//
+// Like PostgreSQL's hash indexes, the table never shrinks: deleting keys
+// frees overflow pages, but buckets stay until the index is rebuilt.
+//
+// A table is a set. Keys keep no order, so finding the keys equal to a
+// value means reading the one bucket its hash leads to and checking them.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// keys per page
+const PAGE_KEYS: usize = 8;
+// the share of the primary pages' room past which a bucket is split
+const FILL: f64 = 0.75;
+const INITIAL_BUCKETS: usize = 2;
+
+// a primary page followed by its overflow pages, all full but the last
+#[derive(Debug, Clone)]
+struct Bucket<K> {
+    pages: Vec<Vec<K>>,
+}
+
+impl<K> Bucket<K> {
+    fn new() -> Self {
+        Self {
+            pages: vec![Vec::with_capacity(PAGE_KEYS)],
+        }
+    }
+
+    fn push(&mut self, key: K) {
+        if self.pages.last().unwrap().len() == PAGE_KEYS {
+            self.pages.push(Vec::with_capacity(PAGE_KEYS));
+        }
+        self.pages.last_mut().unwrap().push(key);
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &K> {
+        self.pages.iter().flatten()
+    }
+}
+
+fn hash<Q: Hash + ?Sized>(key: &Q) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Debug, Clone)]
+pub struct HashIndex<K> {
+    buckets: Vec<Bucket<K>>,
+    // the number of rounds of splits done, the table had
+    // `INITIAL_BUCKETS << level` buckets at the start of this one
+    level: u32,
+    // the next bucket to split
+    next: usize,
+    len: usize,
+}
+
+impl<K: Hash + Eq> Default for HashIndex<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq> HashIndex<K> {
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..INITIAL_BUCKETS).map(|_| Bucket::new()).collect(),
+            level: 0,
+            next: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn buckets(&self) -> usize {
+        self.buckets.len()
+    }
+
+    // all pages, primary and overflow
+    pub fn pages(&self) -> usize {
+        self.buckets.iter().map(|b| b.pages.len()).sum()
+    }
+
+    pub fn overflow_pages(&self) -> usize {
+        self.pages() - self.buckets.len()
+    }
+
+    fn bucket_of(&self, hash: u64) -> usize {
+        let round = (INITIAL_BUCKETS as u64) << self.level;
+        let b = hash % round;
+        // split already this round, so addressed by one more bit
+        if (b as usize) < self.next {
+            return (hash % (round * 2)) as usize;
+        }
+        b as usize
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.buckets[self.bucket_of(hash(key))].keys().any(|k| k == key)
+    }
+
+    // the keys that hash like `probe`, which holds all keys equal to it
+    // for any `Eq` that agrees with the hash, and maybe others
+    pub fn lookup<Q: Hash + ?Sized>(&self, probe: &Q) -> impl Iterator<Item = &K> {
+        self.buckets[self.bucket_of(hash(probe))].keys()
+    }
+
+    // all keys, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.buckets.iter().flat_map(|b| b.keys())
+    }
+
+    // insert `key`, false if it is already there
+    pub fn insert(&mut self, key: K) -> bool {
+        if self.contains(&key) {
+            return false;
+        }
+        let b = self.bucket_of(hash(&key));
+        self.buckets[b].push(key);
+        self.len += 1;
+        if self.len as f64 > FILL * (self.buckets.len() * PAGE_KEYS) as f64 {
+            self.split();
+        }
+        true
+    }
+
+    // delete `key`, false if it was not there
+    pub fn delete(&mut self, key: &K) -> bool {
+        let bucket = self.bucket_of(hash(key));
+        let pages = &mut self.buckets[bucket].pages;
+        let Some((p, i)) = pages
+            .iter()
+            .enumerate()
+            .find_map(|(p, page)| page.iter().position(|k| k == key).map(|i| (p, i)))
+        else {
+            return false;
+        };
+        // the last key of the bucket fills the hole, so that only the
+        // last page is ever short and an emptied overflow page is freed
+        let last = pages.last_mut().unwrap().pop().unwrap();
+        if p + 1 < pages.len() || i < pages[p].len() {
+            pages[p][i] = last;
+        }
+        if pages.len() > 1 && pages.last().unwrap().is_empty() {
+            pages.pop();
+        }
+        self.len -= 1;
+        true
+    }
+
+    fn split(&mut self) {
+        let old = std::mem::replace(&mut self.buckets[self.next], Bucket::new());
+        self.buckets.push(Bucket::new());
+        self.next += 1;
+        if self.next == INITIAL_BUCKETS << self.level {
+            self.level += 1;
+            self.next = 0;
+        }
+        for key in old.pages.into_iter().flatten() {
+            let b = self.bucket_of(hash(&key));
+            self.buckets[b].push(key);
+        }
+    }
+
+    // the same keys in as few buckets as they need and no overflow pages
+    // but those of keys sharing a bucket, what REINDEX makes of the table
+    pub fn compacted(&self) -> Self
+    where
+        K: Clone,
+    {
+        let mut table = Self::new();
+        for key in self.keys() {
+            table.insert(key.clone());
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a key that hashes like every other
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Collide(u32);
+
+    impl Hash for Collide {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            0.hash(state)
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        let mut table = HashIndex::new();
+        for key in 0..1000u32 {
+            assert!(table.insert(key));
+            assert!(table.len() as f64 <= FILL * (table.buckets() * PAGE_KEYS) as f64);
+        }
+        assert!(!table.insert(500));
+        // grown a bucket at a time
+        assert_eq!(table.buckets(), (1000.0 / FILL / PAGE_KEYS as f64).ceil() as usize);
+        assert_eq!(table.buckets(), (INITIAL_BUCKETS << table.level) + table.next);
+        assert!(table.overflow_pages() < table.buckets() / 4);
+        for (b, bucket) in table.buckets.iter().enumerate() {
+            assert!(bucket.keys().all(|k| table.bucket_of(hash(k)) == b));
+        }
+        assert!((0..1000).all(|k| table.contains(&k)) && !table.contains(&1000));
+
+        for key in (0..1000).step_by(2) {
+            assert!(table.delete(&key));
+            assert!(!table.delete(&key));
+        }
+        assert_eq!(table.len(), 500);
+        assert!((0..1000).all(|k| table.contains(&k) == (k % 2 == 1)));
+        let mut keys: Vec<u32> = table.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, (1..1000).step_by(2).collect::<Vec<_>>());
+
+        // deletes leave the buckets, a rebuild does not
+        let buckets = table.buckets();
+        let table = table.compacted();
+        assert!(table.buckets() < buckets && table.len() == 500);
+        assert!((0..1000).all(|k| table.contains(&k) == (k % 2 == 1)));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut table = HashIndex::new();
+        for key in 0..30 {
+            table.insert(Collide(key));
+        }
+        // a bucket splits into itself, the keys stay together
+        let full = table.buckets.iter().find(|b| b.keys().count() == 30).unwrap();
+        assert_eq!(full.pages.len(), 30usize.div_ceil(PAGE_KEYS));
+        assert_eq!(table.overflow_pages(), 3);
+        assert!(!table.insert(Collide(7)));
+
+        for key in 0..20 {
+            assert!(table.delete(&Collide(key)));
+        }
+        assert_eq!(table.overflow_pages(), 1);
+        assert_eq!(table.lookup(&Collide(0)).count(), 10);
+        assert!((20..30).all(|k| table.contains(&Collide(k))) && !table.contains(&Collide(3)));
+        for key in 20..30 {
+            table.delete(&Collide(key));
+        }
+        assert!(table.is_empty() && table.overflow_pages() == 0);
+    }
+
+    #[test]
+    fn test_lookup() {
+        // keys hashed by their first field alone, like an index entry by
+        // its column value
+        #[derive(Debug, PartialEq, Eq)]
+        struct Entry(&'static str, u32);
+
+        impl Hash for Entry {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.0.hash(state)
+            }
+        }
+
+        let mut table = HashIndex::new();
+        for (i, name) in ["ann", "bob", "cy", "ann", "dee", "bob", "ann"].into_iter().enumerate() {
+            table.insert(Entry(name, i as u32));
+        }
+        let mut ids: Vec<u32> = table.lookup("ann").filter(|e| e.0 == "ann").map(|e| e.1).collect();
+        ids.sort();
+        assert_eq!(ids, [0, 3, 6]);
+        assert_eq!(table.lookup("eve").filter(|e| e.0 == "eve").count(), 0);
+    }
+}
@@ -0,0 +1,410 @@
+#![allow(dead_code)]
+
+// What every tree variant offers as an index over a set of keys, so that
+// they can be swapped for one another and held to the same behaviour.
+//
+// The variants that hold a set of keys in an order implement `Index`:
+// v2, blink, olc, cow, arena, lazy, bytes (and its `KeyTree`), and hash,
+// whose ranges sort what they read. `gist` and `gin` do not, they answer
+// other questions than equality and order (overlapping boxes, rows
+// holding an element).
+// `conformance::run` is the spec the variants are tested against: a new
+// access method implements `Index` and gets a test below that passes it
+// to `run`.
+//
+// An index is a set: inserting a key that is already there does nothing.
+// `v2` keeps duplicates of its own accord, as an index it checks first.
+
+use std::hash::Hash;
+use std::ops::{Bound, RangeBounds};
+
+use super::arena::ArenaTree;
+use super::blink::BlinkTree;
+use super::bytes::{BytesTree, KeyTree};
+use super::collate::Comparator;
+use super::cow::CowTree;
+use super::hash::HashIndex;
+use super::key::CompositeKey;
+use super::lazy::LazyTree;
+use super::olc::OlcTree;
+use super::v2::{Entry, Node};
+
+pub trait Index<K> {
+    // insert `key`, false if it is already there
+    fn insert(&mut self, key: K) -> bool;
+
+    fn find(&self, key: &K) -> bool;
+
+    // delete `key`, false if it was not there
+    fn delete(&mut self, key: &K) -> bool;
+
+    // the keys within `range` in order
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<K>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Clone + Default, C: Comparator<K>> Index<K> for Node<K, C> {
+    fn insert(&mut self, key: K) -> bool {
+        self.entry(key).or_insert()
+    }
+
+    fn find(&self, key: &K) -> bool {
+        self.first_from(Bound::Included(key)).is_some_and(|k| C::compare(k, key).is_eq())
+    }
+
+    fn delete(&mut self, key: &K) -> bool {
+        match self.entry(key.clone()) {
+            Entry::Occupied(e) => {
+                e.remove();
+                true
+            }
+            Entry::Vacant(_) => false,
+        }
+    }
+
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<K> {
+        Node::range(self, range).into_iter().cloned().collect()
+    }
+
+    fn len(&self) -> usize {
+        Node::len(self)
+    }
+}
+
+impl<K: Ord + Clone> Index<K> for BlinkTree<K> {
+    fn insert(&mut self, key: K) -> bool {
+        BlinkTree::insert(self, key)
+    }
+
+    fn find(&self, key: &K) -> bool {
+        self.contains(key)
+    }
+
+    fn delete(&mut self, key: &K) -> bool {
+        BlinkTree::delete(self, key)
+    }
+
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<K> {
+        BlinkTree::range(self, range)
+    }
+
+    fn len(&self) -> usize {
+        BlinkTree::len(self)
+    }
+}
+
+impl Index<u64> for OlcTree {
+    fn insert(&mut self, key: u64) -> bool {
+        OlcTree::insert(self, key)
+    }
+
+    fn find(&self, key: &u64) -> bool {
+        OlcTree::find(self, *key)
+    }
+
+    fn delete(&mut self, key: &u64) -> bool {
+        OlcTree::delete(self, *key)
+    }
+
+    fn range<R: RangeBounds<u64>>(&self, range: R) -> Vec<u64> {
+        OlcTree::range(self, range)
+    }
+
+    fn len(&self) -> usize {
+        OlcTree::len(self)
+    }
+}
+
+impl<K: Ord + Clone> Index<K> for CowTree<K> {
+    fn insert(&mut self, key: K) -> bool {
+        CowTree::insert(self, key)
+    }
+
+    fn find(&self, key: &K) -> bool {
+        self.contains(key)
+    }
+
+    fn delete(&mut self, key: &K) -> bool {
+        CowTree::delete(self, key)
+    }
+
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<K> {
+        self.iter_from(range.start_bound()).take_while(|k| range.contains(k)).cloned().collect()
+    }
+
+    fn len(&self) -> usize {
+        CowTree::len(self)
+    }
+}
+
+impl<K: Ord + Clone + Default> Index<K> for ArenaTree<K, ()> {
+    fn insert(&mut self, key: K) -> bool {
+        ArenaTree::insert(self, key, ()).is_none()
+    }
+
+    fn find(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn delete(&mut self, key: &K) -> bool {
+        self.remove(key).is_some()
+    }
+
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<K> {
+        ArenaTree::range(self, range).map(|(k, _)| k.clone()).collect()
+    }
+
+    fn len(&self) -> usize {
+        ArenaTree::len(self)
+    }
+}
+
+impl<K: Hash + Ord + Clone> Index<K> for HashIndex<K> {
+    fn insert(&mut self, key: K) -> bool {
+        HashIndex::insert(self, key)
+    }
+
+    fn find(&self, key: &K) -> bool {
+        self.contains(key)
+    }
+
+    fn delete(&mut self, key: &K) -> bool {
+        HashIndex::delete(self, key)
+    }
+
+    // the table keeps no order, a range reads it all
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<K> {
+        let mut keys: Vec<K> = self.keys().filter(|k| range.contains(k)).cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    fn len(&self) -> usize {
+        HashIndex::len(self)
+    }
+}
+
+impl<K: Ord + Clone + Default> Index<K> for LazyTree<K> {
+    fn insert(&mut self, key: K) -> bool {
+        LazyTree::insert(self, key)
+    }
+
+    fn find(&self, key: &K) -> bool {
+        self.contains(key)
+    }
+
+    fn delete(&mut self, key: &K) -> bool {
+        LazyTree::delete(self, key)
+    }
+
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<K> {
+        LazyTree::range(self, range).into_iter().cloned().collect()
+    }
+
+    fn len(&self) -> usize {
+        LazyTree::len(self)
+    }
+}
+
+// `Index` has no room for errors, a key longer than `MAX_KEY` panics
+impl<const PAGE: usize> Index<Vec<u8>> for BytesTree<PAGE> {
+    fn insert(&mut self, key: Vec<u8>) -> bool {
+        BytesTree::insert(self, &key).unwrap()
+    }
+
+    fn find(&self, key: &Vec<u8>) -> bool {
+        self.contains(key)
+    }
+
+    fn delete(&mut self, key: &Vec<u8>) -> bool {
+        BytesTree::delete(self, key)
+    }
+
+    fn range<R: RangeBounds<Vec<u8>>>(&self, range: R) -> Vec<Vec<u8>> {
+        BytesTree::range(self, range)
+    }
+
+    fn len(&self) -> usize {
+        BytesTree::len(self)
+    }
+}
+
+impl<const PAGE: usize> Index<CompositeKey> for KeyTree<PAGE> {
+    fn insert(&mut self, key: CompositeKey) -> bool {
+        KeyTree::insert(self, &key).unwrap()
+    }
+
+    fn find(&self, key: &CompositeKey) -> bool {
+        self.contains(key)
+    }
+
+    fn delete(&mut self, key: &CompositeKey) -> bool {
+        KeyTree::delete(self, key)
+    }
+
+    fn range<R: RangeBounds<CompositeKey>>(&self, range: R) -> Vec<CompositeKey> {
+        KeyTree::range(self, range)
+    }
+
+    fn len(&self) -> usize {
+        KeyTree::len(self)
+    }
+}
+
+// The behaviour every `Index` must have, checked against `BTreeSet`.
+#[cfg(test)]
+pub mod conformance {
+    use super::Index;
+    use std::collections::BTreeSet;
+    use std::fmt::Debug;
+    use std::ops::Bound;
+
+    // a small xorshift generator, the same seed gives the same keys
+    pub(crate) fn rng(mut seed: u64) -> impl FnMut() -> u64 {
+        move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        }
+    }
+
+    // run every check on fresh indexes from `make`, with keys made from
+    // integers by `key`, which must keep their order
+    pub fn run<K, I>(make: impl Fn() -> I, key: impl Fn(u64) -> K)
+    where
+        K: Ord + Clone + Debug,
+        I: Index<K>,
+    {
+        empty(make(), &key);
+        sequential(make(), &key);
+        random(make(), &key);
+    }
+
+    fn empty<K: Ord + Clone + Debug, I: Index<K>>(mut index: I, key: impl Fn(u64) -> K) {
+        assert!(index.is_empty());
+        assert_eq!(index.len(), 0);
+        assert!(!index.find(&key(1)));
+        assert!(!index.delete(&key(1)));
+        assert!(index.range(..).is_empty());
+        assert!(index.range(key(0)..=key(10)).is_empty());
+    }
+
+    // in order, in reverse and interleaved, then deleted again
+    fn sequential<K: Ord + Clone + Debug, I: Index<K>>(mut index: I, key: impl Fn(u64) -> K) {
+        let keys: Vec<u64> = (0..300).chain((300..600).rev()).chain((600..900).step_by(2)).chain((601..900).step_by(2)).collect();
+        for (i, &k) in keys.iter().enumerate() {
+            assert!(index.insert(key(k)), "key {} inserted twice", k);
+            assert!(!index.insert(key(k)), "duplicate of {} inserted", k);
+            assert_eq!(index.len(), i + 1);
+        }
+        assert_eq!(index.range(..), (0..900).map(&key).collect::<Vec<_>>());
+        assert_eq!(index.range(key(100)..key(110)), (100..110).map(&key).collect::<Vec<_>>());
+        for k in (0..900).step_by(3) {
+            assert!(index.delete(&key(k)));
+            assert!(!index.delete(&key(k)));
+            assert!(!index.find(&key(k)));
+        }
+        assert_eq!(index.len(), 600);
+        for k in 0..900 {
+            assert_eq!(index.find(&key(k)), k % 3 != 0, "find {}", k);
+        }
+        for k in 0..900 {
+            index.delete(&key(k));
+        }
+        assert!(index.is_empty());
+        assert!(index.range(..).is_empty());
+    }
+
+    // random inserts and deletes checked against a `BTreeSet`, ranges with
+    // every kind of bound
+    fn random<K: Ord + Clone + Debug, I: Index<K>>(mut index: I, key: impl Fn(u64) -> K) {
+        let mut expected = BTreeSet::new();
+        let mut next = rng(0x2545f491);
+        for step in 0..6000 {
+            let k = next() % 1000;
+            if next().is_multiple_of(3) {
+                assert_eq!(index.delete(&key(k)), expected.remove(&k), "delete {}", k);
+            } else {
+                assert_eq!(index.insert(key(k)), expected.insert(k), "insert {}", k);
+            }
+            assert_eq!(index.len(), expected.len());
+            if step % 100 == 0 {
+                let (a, b) = (next() % 1000, next() % 1000);
+                let (lo, hi) = (a.min(b), a.max(b));
+                let bounds = [
+                    (Bound::Included(lo), Bound::Included(hi)),
+                    (Bound::Excluded(lo), Bound::Excluded(hi)),
+                    (Bound::Included(lo), Bound::Unbounded),
+                    (Bound::Unbounded, Bound::Excluded(hi)),
+                ];
+                for (start, end) in bounds {
+                    let want: Vec<K> = expected.range((start, end)).map(|&k| key(k)).collect();
+                    assert_eq!(index.range((start.map(&key), end.map(&key))), want, "range {:?}..{:?}", start, end);
+                }
+            }
+        }
+        for k in 0..1000 {
+            assert_eq!(index.find(&key(k)), expected.contains(&k), "find {}", k);
+        }
+        assert_eq!(index.range(..), expected.iter().map(|&k| key(k)).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::key::{Datum, KeySchema, SortOrder};
+
+    #[test]
+    fn test_v2() {
+        conformance::run(Node::<u64>::new, |k| k);
+        conformance::run(Node::<u64>::new_counted, |k| k);
+    }
+
+    #[test]
+    fn test_blink() {
+        conformance::run(BlinkTree::new, |k| k);
+    }
+
+    #[test]
+    fn test_hash() {
+        conformance::run(HashIndex::new, |k| k);
+        conformance::run(HashIndex::new, |k| format!("{:04}", k));
+    }
+
+    #[test]
+    fn test_lazy() {
+        conformance::run(LazyTree::<u64>::new, |k| k);
+    }
+
+    #[test]
+    fn test_olc() {
+        conformance::run(OlcTree::new, |k| k);
+    }
+
+    #[test]
+    fn test_cow() {
+        conformance::run(CowTree::new, |k| k);
+    }
+
+    #[test]
+    fn test_arena() {
+        conformance::run(ArenaTree::<u64, ()>::new, |k| k);
+    }
+
+    #[test]
+    fn test_bytes() {
+        conformance::run(BytesTree::<256>::new, |k| k.to_be_bytes().to_vec());
+        // keys of different lengths, in the same order as the integers
+        conformance::run(BytesTree::<256>::new, |k| format!("{}{}", k.to_string().len(), k).into_bytes());
+        let schema = KeySchema::new(vec![SortOrder::Asc, SortOrder::Desc]);
+        let key = |k: u64| schema.key(vec![Datum::Text(format!("K{:04}", k / 3)), Datum::Int(-((k % 3) as i64))]);
+        conformance::run(|| KeyTree::<256>::new(schema.clone()), key);
+    }
+}
@@ -0,0 +1,508 @@
+#![allow(dead_code)]
+
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use super::v2::Node;
+
+// A typed value of one index column.
+//
+// Values of different types compare by the order of the variants below.
+// NULL is declared last so that it sorts after every other value, which
+// is PostgreSQL's default(`NULLS LAST` for ASC, `NULLS FIRST` for DESC).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Datum {
+    Bool(bool),
+    Int(i64),
+    Text(String),
+    #[default]
+    Null,
+}
+
+impl Display for Datum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Datum::Bool(b) => write!(f, "{}", b),
+            Datum::Int(i) => write!(f, "{}", i),
+            Datum::Text(s) => write!(f, "'{}'", s),
+            Datum::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    // turn the natural order of two datums into the index order
+    pub fn apply(self, ord: Ordering) -> Ordering {
+        match self {
+            SortOrder::Asc => ord,
+            SortOrder::Desc => ord.reverse(),
+        }
+    }
+}
+
+// The column orders of a multi-column index, e.g. `(a, b DESC, c)`.
+// Every key built from the same schema shares its order list.
+#[derive(Debug, Clone)]
+pub struct KeySchema {
+    orders: Arc<[SortOrder]>,
+}
+
+impl KeySchema {
+    pub fn new(orders: Vec<SortOrder>) -> Self {
+        Self {
+            orders: orders.into(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    pub fn key(&self, datums: Vec<Datum>) -> CompositeKey {
+        assert_eq!(datums.len(), self.orders.len());
+        CompositeKey {
+            datums,
+            orders: self.orders.clone(),
+        }
+    }
+
+    // the encoding of the leading columns `datums`, see `CompositeKey::encode`,
+    // which the encoding of every key starting with them starts with
+    pub fn encode_prefix(&self, datums: &[Datum]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (col, datum) in datums.iter().enumerate() {
+            encode_datum(datum, self.orders.get(col).copied().unwrap_or_default(), &mut out);
+        }
+        out
+    }
+
+    // the key `CompositeKey::encode` made `bytes` of
+    pub fn decode(&self, mut bytes: &[u8]) -> CompositeKey {
+        let mut datums = Vec::with_capacity(self.orders.len());
+        for &order in self.orders.iter() {
+            datums.push(decode_datum(&mut bytes, order));
+        }
+        assert!(bytes.is_empty(), "trailing bytes after a key");
+        self.key(datums)
+    }
+}
+
+// Keys as bytes that compare like the keys, so that a tree of byte strings
+// can hold them, see `bytes::KeyTree`. A datum is a byte for its variant,
+// in the variants' order, then
+//   Int   8 bytes big-endian with the sign bit flipped
+//   Text  the bytes with every 0 escaped as 0 0xff, then 0 1
+//   Bool  0 or 1
+// so no datum's encoding is a prefix of another's. A DESC column has all
+// its bytes inverted.
+const TAG_BOOL: u8 = 1;
+const TAG_INT: u8 = 2;
+const TAG_TEXT: u8 = 3;
+const TAG_NULL: u8 = 4;
+
+fn encode_datum(datum: &Datum, order: SortOrder, out: &mut Vec<u8>) {
+    let start = out.len();
+    match datum {
+        Datum::Bool(b) => out.extend([TAG_BOOL, u8::from(*b)]),
+        Datum::Int(i) => {
+            out.push(TAG_INT);
+            out.extend(((*i as u64) ^ (1 << 63)).to_be_bytes());
+        }
+        Datum::Text(text) => {
+            out.push(TAG_TEXT);
+            for &b in text.as_bytes() {
+                match b {
+                    0 => out.extend([0, 0xff]),
+                    b => out.push(b),
+                }
+            }
+            out.extend([0, 1]);
+        }
+        Datum::Null => out.push(TAG_NULL),
+    }
+    if order == SortOrder::Desc {
+        out[start..].iter_mut().for_each(|b| *b = !*b);
+    }
+}
+
+fn decode_datum(bytes: &mut &[u8], order: SortOrder) -> Datum {
+    let mut next = || {
+        let (&b, rest) = bytes.split_first().expect("a key cut short");
+        *bytes = rest;
+        if order == SortOrder::Desc { !b } else { b }
+    };
+    match next() {
+        TAG_BOOL => Datum::Bool(next() == 1),
+        TAG_INT => {
+            let be: [u8; 8] = std::array::from_fn(|_| next());
+            Datum::Int((u64::from_be_bytes(be) ^ (1 << 63)) as i64)
+        }
+        TAG_TEXT => {
+            let mut text = Vec::new();
+            loop {
+                match next() {
+                    0 => match next() {
+                        1 => break,
+                        _ => text.push(0),
+                    },
+                    b => text.push(b),
+                }
+            }
+            Datum::Text(String::from_utf8(text).expect("text of a key is not UTF-8"))
+        }
+        TAG_NULL => Datum::Null,
+        tag => panic!("unknown datum tag {}", tag),
+    }
+}
+
+// A tuple of datums compared column by column, each column in the
+// direction given by its schema.
+#[derive(Debug, Clone, Default)]
+pub struct CompositeKey {
+    datums: Vec<Datum>,
+    orders: Arc<[SortOrder]>,
+}
+
+impl CompositeKey {
+    pub fn datums(&self) -> &[Datum] {
+        &self.datums
+    }
+
+    // the key as bytes that compare like the keys of its schema do
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (col, datum) in self.datums.iter().enumerate() {
+            encode_datum(datum, self.order(col), &mut out);
+        }
+        out
+    }
+
+    fn order(&self, col: usize) -> SortOrder {
+        self.orders.get(col).copied().unwrap_or_default()
+    }
+}
+
+impl Ord for CompositeKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for (col, (a, b)) in self.datums.iter().zip(&other.datums).enumerate() {
+            let ord = self.order(col).apply(a.cmp(b));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        self.datums.len().cmp(&other.datums.len())
+    }
+}
+
+impl PartialOrd for CompositeKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for CompositeKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CompositeKey {}
+
+impl Display for CompositeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(")?;
+        for (i, datum) in self.datums.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", datum)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// A scan condition on a prefix of the index columns: equality on the
+// first `prefix.len()` columns and an optional range on the next one,
+// e.g. `a = 1 AND b > 5` on an index over `(a, b, c)`.
+//
+// Bounds are written in terms of values, not index order, so
+// `b > 5` is `lower = Excluded(5)` whether `b` is ASC or DESC.
+// A bounded column never matches NULL.
+#[derive(Debug, Clone)]
+pub struct KeyRange {
+    prefix: Vec<Datum>,
+    lower: Bound<Datum>,
+    upper: Bound<Datum>,
+}
+
+impl KeyRange {
+    pub fn prefix(prefix: Vec<Datum>) -> Self {
+        Self {
+            prefix,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+
+    pub fn lower(mut self, bound: Bound<Datum>) -> Self {
+        self.lower = bound;
+        self
+    }
+
+    pub fn upper(mut self, bound: Bound<Datum>) -> Self {
+        self.upper = bound;
+        self
+    }
+
+    // the values the leading columns equal
+    pub fn prefix_values(&self) -> &[Datum] {
+        &self.prefix
+    }
+
+    // where `key` lies relative to this range, in index order. A key with
+    // fewer columns than the prefix, like a separator cut short, is
+    // compared by the columns it has.
+    pub fn probe(&self, key: &CompositeKey) -> Ordering {
+        for (col, (value, datum)) in key.datums.iter().zip(&self.prefix).enumerate() {
+            let ord = key.order(col).apply(value.cmp(datum));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        let col = self.prefix.len();
+        if col >= key.datums.len() {
+            return Ordering::Equal;
+        }
+        let value = &key.datums[col];
+        let below = match &self.lower {
+            Bound::Included(lo) => value < lo,
+            Bound::Excluded(lo) => value <= lo,
+            Bound::Unbounded => false,
+        };
+        let above = match &self.upper {
+            Bound::Included(hi) => value > hi,
+            Bound::Excluded(hi) => value >= hi,
+            Bound::Unbounded => false,
+        };
+        let bounded = !matches!(
+            (&self.lower, &self.upper),
+            (Bound::Unbounded, Bound::Unbounded)
+        );
+        // NULL is the greatest datum, so it lies "above" any bounded range
+        let logical = if below {
+            Ordering::Less
+        } else if above || (bounded && *value == Datum::Null) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        };
+        key.order(col).apply(logical)
+    }
+
+    // the encoding, in `schema`, every key of the range sorts at or after:
+    // the prefix and the bound that comes first in index order, if any
+    pub fn start(&self, schema: &KeySchema) -> Vec<u8> {
+        let first = match schema.orders.get(self.prefix.len()).copied().unwrap_or_default() {
+            SortOrder::Asc => &self.lower,
+            SortOrder::Desc => &self.upper,
+        };
+        let mut datums = self.prefix.clone();
+        if let Bound::Included(value) | Bound::Excluded(value) = first {
+            datums.push(value.clone());
+        }
+        schema.encode_prefix(&datums)
+    }
+
+    // all keys of `tree` matching this range, in index order
+    pub fn scan<'a>(&self, tree: &'a Node<CompositeKey>) -> Vec<&'a CompositeKey> {
+        let mut out = Vec::new();
+        tree.scan_by(&|key: &CompositeKey| self.probe(key), &mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(i: i64) -> Datum {
+        Datum::Int(i)
+    }
+
+    fn build_tree(schema: &KeySchema) -> Node<CompositeKey> {
+        let mut root = Node::new();
+        for a in 1..=3 {
+            for b in 1..=8 {
+                for c in ["x", "y"] {
+                    root.insert(schema.key(vec![int(a), int(b), Datum::Text(c.to_string())]));
+                }
+            }
+            root.insert(schema.key(vec![int(a), Datum::Null, Datum::Text("z".to_string())]));
+        }
+        root
+    }
+
+    fn column(keys: &[&CompositeKey], col: usize) -> Vec<Datum> {
+        keys.iter().map(|k| k.datums()[col].clone()).collect()
+    }
+
+    #[test]
+    fn test_datum_order() {
+        assert!(int(1) < int(2));
+        assert!(int(100) < Datum::Null);
+        assert!(Datum::Text("b".to_string()) < Datum::Null);
+        assert!(Datum::Text("a".to_string()) < Datum::Text("b".to_string()));
+    }
+
+    #[test]
+    fn test_composite_order() {
+        let schema = KeySchema::new(vec![SortOrder::Asc, SortOrder::Desc]);
+        let k1 = schema.key(vec![int(1), int(9)]);
+        let k2 = schema.key(vec![int(1), int(3)]);
+        let k3 = schema.key(vec![int(2), int(9)]);
+        let k4 = schema.key(vec![int(1), Datum::Null]);
+        assert!(k4 < k1);
+        assert!(k1 < k2);
+        assert!(k2 < k3);
+        assert_eq!(k1, schema.key(vec![int(1), int(9)]));
+        assert_eq!(format!("{}", k4), "(1, NULL)");
+    }
+
+    #[test]
+    fn test_prefix_scan() {
+        let schema = KeySchema::new(vec![SortOrder::Asc; 3]);
+        let root = build_tree(&schema);
+
+        // a = 2
+        let keys = KeyRange::prefix(vec![int(2)]).scan(&root);
+        assert_eq!(keys.len(), 17);
+        assert!(keys.iter().all(|k| k.datums()[0] == int(2)));
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(keys[16].datums()[1], Datum::Null);
+
+        // a = 2 AND b > 5
+        let keys = KeyRange::prefix(vec![int(2)])
+            .lower(Bound::Excluded(int(5)))
+            .scan(&root);
+        assert_eq!(column(&keys, 1), [6, 6, 7, 7, 8, 8].map(int));
+
+        // a = 1 AND b >= 2 AND b < 4
+        let keys = KeyRange::prefix(vec![int(1)])
+            .lower(Bound::Included(int(2)))
+            .upper(Bound::Excluded(int(4)))
+            .scan(&root);
+        assert_eq!(column(&keys, 1), [2, 2, 3, 3].map(int));
+
+        // a = 3 AND b = 4 AND c = 'y'
+        let keys = KeyRange::prefix(vec![int(3), int(4), Datum::Text("y".to_string())]).scan(&root);
+        assert_eq!(keys.len(), 1);
+
+        let keys = KeyRange::prefix(vec![int(4)]).scan(&root);
+        assert!(keys.is_empty());
+    }
+
+    #[test]
+    fn test_prefix_scan_desc() {
+        let schema = KeySchema::new(vec![SortOrder::Asc, SortOrder::Desc, SortOrder::Asc]);
+        let root = build_tree(&schema);
+
+        // NULLs come first in a DESC column
+        let keys = KeyRange::prefix(vec![int(1)]).scan(&root);
+        assert_eq!(keys[0].datums()[1], Datum::Null);
+        assert_eq!(keys[1].datums()[1], int(8));
+
+        // a = 1 AND b > 5, returned in index(descending) order
+        let keys = KeyRange::prefix(vec![int(1)])
+            .lower(Bound::Excluded(int(5)))
+            .scan(&root);
+        assert_eq!(column(&keys, 1), [8, 8, 7, 7, 6, 6].map(int));
+
+        // a = 3 AND b <= 2
+        let keys = KeyRange::prefix(vec![int(3)])
+            .upper(Bound::Included(int(2)))
+            .scan(&root);
+        assert_eq!(column(&keys, 1), [2, 2, 1, 1].map(int));
+    }
+
+    #[test]
+    fn test_probe_short_key() {
+        let range = KeyRange::prefix(vec![int(2), int(5)]).lower(Bound::Included(int(1)));
+        let schema = KeySchema::new(vec![SortOrder::Asc, SortOrder::Desc]);
+        assert_eq!(range.probe(&schema.key(vec![int(2), int(7)])), Ordering::Less);
+        assert_eq!(range.probe(&schema.key(vec![int(2), int(5)])), Ordering::Equal);
+        let schema = KeySchema::new(vec![SortOrder::Asc]);
+        assert_eq!(range.probe(&schema.key(vec![int(1)])), Ordering::Less);
+        assert_eq!(range.probe(&schema.key(vec![int(2)])), Ordering::Equal);
+        assert_eq!(range.probe(&schema.key(vec![int(3)])), Ordering::Greater);
+        assert_eq!(range.probe(&CompositeKey::default()), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_encode() {
+        let schema = KeySchema::new(vec![SortOrder::Asc, SortOrder::Desc]);
+        let text = |s: &str| Datum::Text(s.to_string());
+        let datums = [
+            Datum::Bool(false),
+            Datum::Bool(true),
+            int(i64::MIN),
+            int(-1),
+            int(0),
+            int(7),
+            int(i64::MAX),
+            text(""),
+            text("a"),
+            text("a\0"),
+            text("a\0b"),
+            text("ab"),
+            text("b"),
+            Datum::Null,
+        ];
+        let mut keys = Vec::new();
+        for a in &datums {
+            for b in &datums {
+                keys.push(schema.key(vec![a.clone(), b.clone()]));
+            }
+        }
+        for x in &keys {
+            assert_eq!(schema.decode(&x.encode()).datums(), x.datums());
+            for y in &keys {
+                assert_eq!(x.encode().cmp(&y.encode()), x.cmp(y), "{} and {}", x, y);
+            }
+        }
+        // every key starting with some columns starts with their encoding
+        let prefix = schema.encode_prefix(&[text("a")]);
+        assert!(keys.iter().all(|k| k.encode().starts_with(&prefix) == (k.datums()[0] == text("a"))));
+    }
+
+    #[test]
+    fn test_range_start() {
+        let schema = KeySchema::new(vec![SortOrder::Asc, SortOrder::Desc, SortOrder::Asc]);
+        let root = build_tree(&schema);
+        let ranges = [
+            KeyRange::prefix(vec![int(2)]),
+            KeyRange::prefix(vec![int(2)]).lower(Bound::Excluded(int(5))),
+            KeyRange::prefix(vec![int(2)]).upper(Bound::Included(int(3))),
+            KeyRange::prefix(vec![]).lower(Bound::Included(int(3))),
+            KeyRange::prefix(vec![int(1), int(4)]).upper(Bound::Excluded(Datum::Text("y".to_string()))),
+        ];
+        for range in ranges {
+            // no key of the range sorts before its start
+            let start = range.start(&schema);
+            let keys = range.scan(&root);
+            assert!(!keys.is_empty() && keys.iter().all(|k| k.encode() >= start));
+        }
+    }
+}
@@ -0,0 +1,183 @@
+#![allow(dead_code)]
+
+// A `v2` tree whose deletes only mark keys dead, like PostgreSQL marks
+// index tuples LP_DEAD, and whose `vacuum` removes the dead keys in bulk.
+//
+// A delete in `v2` rebalances right away, borrowing from or merging with
+// a sibling whenever a node gets too few keys, which adds up for a bulk
+// DELETE. Here a delete finds the key and flips its flag in place, the
+// tree keeps its shape, and reads skip dead keys. Inserting a key that is
+// dead brings it back to life in the same place. `vacuum` then removes all
+// dead keys at once: a few are deleted one by one, past `VACUUM_REBUILD`
+// of the tree the live keys are bulk loaded into a new, densely packed
+// tree, which merges and frees all the nodes that emptied.
+//
+// A tree is a set, like the other variants behind `Index`.
+
+use std::ops::{Bound, RangeBounds};
+
+use super::collate::{ByKey, Keyed};
+use super::v2::{Entry, Node};
+
+// the share of dead keys past which `vacuum` rebuilds the tree rather than
+// deleting them one by one
+const VACUUM_REBUILD: f64 = 0.1;
+
+// a key and whether it was deleted, the tree orders them `ByKey`
+#[derive(Debug, Clone, Default)]
+struct Slot<K> {
+    key: K,
+    dead: bool,
+}
+
+impl<K: Ord> Keyed for Slot<K> {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+fn live<K>(key: K) -> Slot<K> {
+    Slot { key, dead: false }
+}
+
+#[derive(Debug, Default)]
+pub struct LazyTree<K: Ord + Clone + Default> {
+    root: Node<Slot<K>, ByKey>,
+    live: usize,
+    dead: usize,
+}
+
+impl<K: Ord + Clone + Default> LazyTree<K> {
+    pub fn new() -> Self {
+        Self {
+            root: Node::default(),
+            live: 0,
+            dead: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    // the number of keys deleted since the last vacuum
+    pub fn dead(&self) -> usize {
+        self.dead
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        let probe = live(key.clone());
+        matches!(self.root.first_from(Bound::Included(&probe)), Some(slot) if slot.key == *key && !slot.dead)
+    }
+
+    // insert `key`, false if it is already there
+    pub fn insert(&mut self, key: K) -> bool {
+        match self.root.entry(live(key)) {
+            Entry::Occupied(mut entry) => {
+                if !entry.get().dead {
+                    return false;
+                }
+                let key = entry.get().key.clone();
+                entry.replace(live(key));
+                self.dead -= 1;
+            }
+            Entry::Vacant(entry) => entry.insert(),
+        }
+        self.live += 1;
+        true
+    }
+
+    // mark `key` dead, false if it was not there
+    pub fn delete(&mut self, key: &K) -> bool {
+        let Entry::Occupied(mut entry) = self.root.entry(live(key.clone())) else {
+            return false;
+        };
+        if entry.get().dead {
+            return false;
+        }
+        entry.replace(Slot { key: key.clone(), dead: true });
+        self.live -= 1;
+        self.dead += 1;
+        true
+    }
+
+    // the live keys within `range` in order
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<&K> {
+        let bound = |b: Bound<&K>| b.map(|k| live(k.clone()));
+        let slots = self.root.range((bound(range.start_bound()), bound(range.end_bound())));
+        slots.into_iter().filter(|s| !s.dead).map(|s| &s.key).collect()
+    }
+
+    // remove the dead keys
+    pub fn vacuum(&mut self) {
+        if self.dead == 0 {
+            return;
+        }
+        if self.dead as f64 <= VACUUM_REBUILD * (self.live + self.dead) as f64 {
+            let dead: Vec<Slot<K>> = self.root.range(..).into_iter().filter(|s| s.dead).cloned().collect();
+            for slot in dead {
+                self.root.delete(slot);
+            }
+        } else {
+            let keys = self.root.range(..).into_iter().filter(|s| !s.dead).cloned().collect();
+            self.root = Node::from_sorted(keys);
+        }
+        self.dead = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delete_in_place() {
+        let mut tree = LazyTree::new();
+        for key in 0..1000 {
+            tree.insert(key);
+        }
+        let shape = tree.root.stats();
+        for key in (0..1000).filter(|k| k % 10 != 0) {
+            assert!(tree.delete(&key));
+        }
+        // nothing moved
+        assert_eq!(tree.root.stats(), shape);
+        assert_eq!((tree.len(), tree.dead()), (100, 900));
+        assert!(!tree.contains(&5) && tree.contains(&10));
+        assert_eq!(tree.range(..35), [&0, &10, &20, &30]);
+
+        // a dead key comes back where it was
+        assert!(tree.insert(5));
+        assert!(!tree.insert(5));
+        assert_eq!(tree.root.stats(), shape);
+        assert_eq!((tree.len(), tree.dead()), (101, 899));
+
+        tree.vacuum();
+        assert_eq!(tree.dead(), 0);
+        let stats = tree.root.stats();
+        assert_eq!(stats.keys, 101);
+        assert!(stats.leaf_nodes < shape.leaf_nodes / 5 && stats.height < shape.height);
+        assert_eq!(tree.range(..35), [&0, &5, &10, &20, &30]);
+    }
+
+    #[test]
+    fn test_vacuum_few() {
+        let mut tree = LazyTree::new();
+        for key in 0..1000 {
+            tree.insert(key);
+        }
+        for key in (0..1000).step_by(50) {
+            tree.delete(&key);
+        }
+        tree.vacuum();
+        assert_eq!(tree.root.stats().keys, 980);
+        assert_eq!(tree.range(..).len(), 980);
+        assert!(!tree.contains(&50) && tree.contains(&51));
+    }
+}
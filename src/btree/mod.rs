@@ -1 +1,13 @@
-mod v2;
+pub mod arena;
+pub mod blink;
+pub mod bytes;
+pub mod collate;
+pub mod cow;
+pub mod gin;
+pub mod gist;
+pub mod hash;
+pub mod index;
+pub mod key;
+pub mod lazy;
+pub mod olc;
+pub mod v2;
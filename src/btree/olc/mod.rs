@@ -0,0 +1,736 @@
+#![allow(dead_code)]
+
+// A B+tree of u64 keys with optimistic lock coupling, after Leis et al.,
+// "Optimistic Lock Coupling: A Scalable and Efficient General-Purpose
+// Synchronization Method" (2019).
+//
+// Every node has a version counter that is odd while a writer holds the
+// node. A reader never writes shared memory: it remembers the version of a
+// node, reads what it needs, and checks the version is unchanged before it
+// trusts what it read, restarting from the root otherwise. On the way down
+// the parent is checked again after the child's version was taken, so the
+// child pointer followed was valid at that moment (the coupling).
+//
+//   find: optimistic all the way down, no lock is ever taken
+//   insert_down_to_leaf: optimistic like find, splitting every full node
+//     met on the way down (write locking it and its parent by upgrading the
+//     versions read), then upgrading the leaf to insert
+//   delete: optimistic down to the leaf, upgrading just the leaf. Nodes are
+//     not merged, like in `blink`, but a leaf left empty is unlinked from
+//     its parent (upgraded too) when that keeps a key, and its node reused.
+//
+// Readers may see a node while it changes, so all node fields are atomics
+// and nodes are never given back to the allocator: they live in an arena
+// of chunks, each twice the size of the one before, for the life of the
+// tree. A node unlinked from the tree goes on a free list and is handed
+// out again by the next split; a reader still looking at it fails its
+// validation, as the unlink changed the parent it came from and reuse
+// changes the node. What a reader saw is only used once its version
+// checked out.
+//
+// This is a tree of its own, `v2` itself is not made optimistic: its
+// nodes own their children through `Box`es and hold keys of any type,
+// which readers could not look at while a writer moves them.
+
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+const MAX_KEYS: usize = 15;
+// the size of the first chunk, chunk i holds `FIRST_CHUNK << i` nodes
+const FIRST_CHUNK: usize = 1024;
+// enough chunks for every u32 id
+const CHUNKS: usize = 23;
+
+struct Node {
+    // odd while write locked, bumped by every unlock
+    version: AtomicU64,
+    leaf: AtomicBool,
+    count: AtomicUsize,
+    // the keys of a leaf, or the separators of an inner node:
+    // children[i] holds the keys in (keys[i - 1], keys[i]]
+    keys: [AtomicU64; MAX_KEYS],
+    children: [AtomicU32; MAX_KEYS + 1],
+}
+
+impl Node {
+    fn new() -> Self {
+        Self {
+            version: AtomicU64::new(0),
+            leaf: AtomicBool::new(true),
+            count: AtomicUsize::new(0),
+            keys: std::array::from_fn(|_| AtomicU64::new(0)),
+            children: std::array::from_fn(|_| AtomicU32::new(0)),
+        }
+    }
+
+    // the version to check later, `None` while a writer holds the node
+    fn read_lock(&self) -> Option<u64> {
+        let v = self.version.load(Ordering::Acquire);
+        if v & 1 == 1 {
+            std::hint::spin_loop();
+            return None;
+        }
+        Some(v)
+    }
+
+    // whether nothing changed since `read_lock` returned `v`
+    fn validate(&self, v: u64) -> bool {
+        fence(Ordering::Acquire);
+        self.version.load(Ordering::Relaxed) == v
+    }
+
+    // turn a read of version `v` into a write lock, fails if the node
+    // changed meanwhile
+    fn upgrade(&self, v: u64) -> bool {
+        if self
+            .version
+            .compare_exchange(v, v + 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        // readers that see any of the following writes see the lock as well
+        fence(Ordering::Release);
+        true
+    }
+
+    fn unlock(&self) {
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.leaf.load(Ordering::Relaxed)
+    }
+
+    // clamped, a racing reader may see anything
+    fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed).min(MAX_KEYS)
+    }
+
+    fn key(&self, i: usize) -> u64 {
+        self.keys[i].load(Ordering::Relaxed)
+    }
+
+    fn child(&self, i: usize) -> u32 {
+        self.children[i].load(Ordering::Relaxed)
+    }
+
+    // the number of keys less than `key`
+    fn lower_bound(&self, key: u64) -> usize {
+        let (mut lo, mut hi) = (0, self.count());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.key(mid) < key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+}
+
+// the chunk of node `id` and its place in there
+fn locate(id: u32) -> (usize, usize) {
+    let chunk = (id as usize / FIRST_CHUNK + 1).ilog2() as usize;
+    (chunk, id as usize - FIRST_CHUNK * ((1 << chunk) - 1))
+}
+
+// Nodes by id, allocated in chunks that are never freed. The chunks double
+// in size, so a few of them cover every id and none is ever moved.
+struct Arena {
+    chunks: [OnceLock<Box<[Node]>>; CHUNKS],
+    next: AtomicUsize,
+    // nodes unlinked from the tree, to hand out again
+    free: Mutex<Vec<u32>>,
+}
+
+impl Arena {
+    fn new() -> Self {
+        Self {
+            chunks: std::array::from_fn(|_| OnceLock::new()),
+            next: AtomicUsize::new(0),
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn get(&self, id: u32) -> &Node {
+        let (chunk, i) = locate(id);
+        let nodes = self.chunks[chunk].get_or_init(|| (0..FIRST_CHUNK << chunk).map(|_| Node::new()).collect());
+        &nodes[i]
+    }
+
+    // a fresh node, not yet reachable by anyone else
+    fn alloc(&self, leaf: bool) -> u32 {
+        let reused = self.free.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let id = match reused {
+            Some(id) => {
+                // fail whoever still validates a version of its last life
+                self.get(id).version.fetch_add(2, Ordering::Release);
+                id
+            }
+            // u32 ids run out past what memory holds, about a terabyte of nodes
+            None => u32::try_from(self.next.fetch_add(1, Ordering::Relaxed)).expect("out of node ids"),
+        };
+        let node = self.get(id);
+        node.leaf.store(leaf, Ordering::Relaxed);
+        node.count.store(0, Ordering::Relaxed);
+        id
+    }
+
+    // take back a node no longer reachable from the tree
+    fn free(&self, id: u32) {
+        self.free.lock().unwrap_or_else(|e| e.into_inner()).push(id);
+    }
+
+    // the nodes handed out and not taken back
+    fn live(&self) -> usize {
+        self.next.load(Ordering::Relaxed) - self.free.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+// a node id and the version it was read at
+type Read = (u32, u64);
+
+pub struct OlcTree {
+    arena: Arena,
+    root: AtomicU32,
+    // the number of keys, changed under the write lock of the leaf changed
+    len: AtomicUsize,
+}
+
+impl Default for OlcTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OlcTree {
+    pub fn new() -> Self {
+        let arena = Arena::new();
+        let root = arena.alloc(true);
+        Self {
+            arena,
+            root: AtomicU32::new(root),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    // the root and its version, `None` to restart
+    fn read_root(&self) -> Option<Read> {
+        let id = self.root.load(Ordering::Acquire);
+        let v = self.arena.get(id).read_lock()?;
+        // the root may have split between loading it and its version
+        if self.root.load(Ordering::Acquire) != id {
+            return None;
+        }
+        Some((id, v))
+    }
+
+    // descend optimistically to the leaf that may hold `key`, `None` to
+    // restart. Returns the leaf and its parent, if any, with their versions.
+    fn leaf_for(&self, key: u64) -> Option<(Read, Option<Read>)> {
+        let (mut id, mut v) = self.read_root()?;
+        let mut parent = None;
+        loop {
+            let node = self.arena.get(id);
+            if node.is_leaf() {
+                return Some(((id, v), parent));
+            }
+            let child = node.child(node.lower_bound(key));
+            let cv = self.arena.get(child).read_lock()?;
+            // the child pointer was valid when the child's version was taken
+            if !node.validate(v) {
+                return None;
+            }
+            parent = Some((id, v));
+            id = child;
+            v = cv;
+        }
+    }
+
+    pub fn find(&self, key: u64) -> bool {
+        loop {
+            let Some(((id, v), _)) = self.leaf_for(key) else {
+                continue;
+            };
+            let node = self.arena.get(id);
+            let i = node.lower_bound(key);
+            let found = i < node.count() && node.key(i) == key;
+            if node.validate(v) {
+                return found;
+            }
+        }
+    }
+
+    // insert `key`, false if it is already there
+    pub fn insert(&self, key: u64) -> bool {
+        loop {
+            if let Some(inserted) = self.insert_down_to_leaf(key) {
+                return inserted;
+            }
+        }
+    }
+
+    // one attempt of `insert`, `None` to restart
+    fn insert_down_to_leaf(&self, key: u64) -> Option<bool> {
+        let (mut id, mut v) = self.read_root()?;
+        let mut parent: Option<Read> = None;
+        loop {
+            let node = self.arena.get(id);
+            if node.count.load(Ordering::Relaxed) == MAX_KEYS {
+                self.split(id, v, parent)?;
+                // simpler to start over than to pick the right half
+                return None;
+            }
+            if let Some((pid, pv)) = parent {
+                if !self.arena.get(pid).validate(pv) {
+                    return None;
+                }
+            }
+            if node.is_leaf() {
+                if !node.upgrade(v) {
+                    return None;
+                }
+                let i = node.lower_bound(key);
+                let n = node.count();
+                let inserted = !(i < n && node.key(i) == key);
+                if inserted {
+                    for j in (i..n).rev() {
+                        node.keys[j + 1].store(node.key(j), Ordering::Relaxed);
+                    }
+                    node.keys[i].store(key, Ordering::Relaxed);
+                    node.count.store(n + 1, Ordering::Relaxed);
+                    self.len.fetch_add(1, Ordering::Relaxed);
+                }
+                node.unlock();
+                return Some(inserted);
+            }
+            let child = node.child(node.lower_bound(key));
+            let cv = self.arena.get(child).read_lock()?;
+            if !node.validate(v) {
+                return None;
+            }
+            parent = Some((id, v));
+            id = child;
+            v = cv;
+        }
+    }
+
+    // split the full node `id`, read at version `v`, into its parent (which
+    // is not full, full nodes are split on the way down) or a new root
+    fn split(&self, id: u32, v: u64, parent: Option<Read>) -> Option<()> {
+        if let Some((pid, pv)) = parent {
+            if !self.arena.get(pid).upgrade(pv) {
+                return None;
+            }
+        }
+        let unlock_parent = || {
+            if let Some((pid, _)) = parent {
+                self.arena.get(pid).unlock();
+            }
+        };
+        let node = self.arena.get(id);
+        if !node.upgrade(v) {
+            unlock_parent();
+            return None;
+        }
+        if parent.is_none() && self.root.load(Ordering::Acquire) != id {
+            node.unlock();
+            return None;
+        }
+
+        let leaf = node.is_leaf();
+        let right_id = self.arena.alloc(leaf);
+        let right = self.arena.get(right_id);
+        let mid = MAX_KEYS / 2;
+        // a leaf keeps its middle key as its largest, an inner node moves it up
+        let separator = node.key(mid);
+        let first = mid + 1;
+        for j in first..MAX_KEYS {
+            right.keys[j - first].store(node.key(j), Ordering::Relaxed);
+        }
+        if !leaf {
+            for j in first..=MAX_KEYS {
+                right.children[j - first].store(node.child(j), Ordering::Relaxed);
+            }
+        }
+        right.count.store(MAX_KEYS - first, Ordering::Relaxed);
+        node.count.store(if leaf { mid + 1 } else { mid }, Ordering::Relaxed);
+
+        match parent {
+            Some((pid, _)) => {
+                let p = self.arena.get(pid);
+                let i = p.lower_bound(separator);
+                let n = p.count();
+                for j in (i..n).rev() {
+                    p.keys[j + 1].store(p.key(j), Ordering::Relaxed);
+                    p.children[j + 2].store(p.child(j + 1), Ordering::Relaxed);
+                }
+                p.keys[i].store(separator, Ordering::Relaxed);
+                p.children[i + 1].store(right_id, Ordering::Relaxed);
+                p.count.store(n + 1, Ordering::Relaxed);
+            }
+            None => {
+                let root_id = self.arena.alloc(false);
+                let root = self.arena.get(root_id);
+                root.keys[0].store(separator, Ordering::Relaxed);
+                root.children[0].store(id, Ordering::Relaxed);
+                root.children[1].store(right_id, Ordering::Relaxed);
+                root.count.store(1, Ordering::Relaxed);
+                self.root.store(root_id, Ordering::Release);
+            }
+        }
+        node.unlock();
+        unlock_parent();
+        Some(())
+    }
+
+    // delete `key`, false if it was not there
+    pub fn delete(&self, key: u64) -> bool {
+        loop {
+            let Some(((id, v), parent)) = self.leaf_for(key) else {
+                continue;
+            };
+            let node = self.arena.get(id);
+            if !node.upgrade(v) {
+                continue;
+            }
+            let i = node.lower_bound(key);
+            let n = node.count();
+            let found = i < n && node.key(i) == key;
+            let mut unlinked = false;
+            if found {
+                for j in i + 1..n {
+                    node.keys[j - 1].store(node.key(j), Ordering::Relaxed);
+                }
+                node.count.store(n - 1, Ordering::Relaxed);
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                if n == 1 {
+                    if let Some(parent) = parent {
+                        unlinked = self.unlink(id, parent);
+                    }
+                }
+            }
+            node.unlock();
+            // freed only once unlocked, as a node handed out again must not
+            // still carry the lock of the delete that emptied it
+            if unlinked {
+                self.arena.free(id);
+            }
+            return found;
+        }
+    }
+
+    // take the empty, write locked leaf `id` out of its parent, read at
+    // `parent`, true if it was, for the caller to free it once unlocked.
+    // Left alone if the parent changed meanwhile or would be left without a
+    // key, an empty leaf is a valid one.
+    fn unlink(&self, id: u32, (pid, pv): Read) -> bool {
+        let p = self.arena.get(pid);
+        if !p.upgrade(pv) {
+            return false;
+        }
+        let n = p.count();
+        // unchanged since the descent, so `id` is still a child
+        let i = (0..=n).find(|&i| p.child(i) == id).unwrap();
+        if n >= 2 {
+            // the neighbouring child takes over the empty range: drop the
+            // separator on the leaf's left, or on its right for the first
+            let k = i.saturating_sub(1);
+            for j in k + 1..n {
+                p.keys[j - 1].store(p.key(j), Ordering::Relaxed);
+            }
+            for j in i + 1..=n {
+                p.children[j - 1].store(p.child(j), Ordering::Relaxed);
+            }
+            p.count.store(n - 1, Ordering::Relaxed);
+        }
+        p.unlock();
+        n >= 2
+    }
+
+    // the nodes making up the tree
+    pub fn nodes(&self) -> usize {
+        self.arena.live()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // all keys in order, only meaningful while no writer runs
+    pub fn keys(&self) -> Vec<u64> {
+        self.range(..)
+    }
+
+    // the keys within `range` in order, like `keys` only while no writer
+    // runs. Leaves are not linked, the subtrees the range overlaps are
+    // walked from the root.
+    pub fn range<R: RangeBounds<u64>>(&self, range: R) -> Vec<u64> {
+        let mut keys = Vec::new();
+        self.collect(self.root.load(Ordering::Acquire), &range, &mut keys);
+        keys
+    }
+
+    fn collect<R: RangeBounds<u64>>(&self, id: u32, range: &R, keys: &mut Vec<u64>) {
+        let node = self.arena.get(id);
+        let n = node.count();
+        if node.is_leaf() {
+            keys.extend((0..n).map(|i| node.key(i)).filter(|k| range.contains(k)));
+            return;
+        }
+        // children[i] holds the keys in (keys[i - 1], keys[i]]
+        for i in 0..=n {
+            let below_start = i < n
+                && match range.start_bound() {
+                    Bound::Included(&start) => node.key(i) < start,
+                    Bound::Excluded(&start) => node.key(i) <= start,
+                    Bound::Unbounded => false,
+                };
+            if below_start {
+                continue;
+            }
+            self.collect(node.child(i), range, keys);
+            let past_end = i < n
+                && match range.end_bound() {
+                    Bound::Included(&end) | Bound::Excluded(&end) => node.key(i) >= end,
+                    Bound::Unbounded => false,
+                };
+            if past_end {
+                return;
+            }
+        }
+    }
+
+    // Verify the structure of a quiescent tree: keys are ordered, within the
+    // bounds their parents set, and all leaves are at the same depth.
+    pub fn check(&self) -> Result<(), String> {
+        let mut leaf_depth = None;
+        self.check_node(self.root.load(Ordering::Acquire), None, None, 0, &mut leaf_depth)
+    }
+
+    fn check_node(
+        &self,
+        id: u32,
+        low: Option<u64>,
+        high: Option<u64>,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+    ) -> Result<(), String> {
+        let node = self.arena.get(id);
+        if node.version.load(Ordering::Relaxed) & 1 == 1 {
+            return Err(format!("node {} is still locked", id));
+        }
+        let n = node.count();
+        let keys: Vec<u64> = (0..n).map(|i| node.key(i)).collect();
+        if !keys.windows(2).all(|w| w[0] < w[1]) {
+            return Err(format!("the keys of node {} are out of order", id));
+        }
+        if !keys.iter().all(|&k| low.is_none_or(|l| k > l) && high.is_none_or(|h| k <= h)) {
+            return Err(format!("a key of node {} is outside of its range", id));
+        }
+        if node.is_leaf() {
+            if *leaf_depth.get_or_insert(depth) != depth {
+                return Err(format!("leaf {} is at another depth", id));
+            }
+            return Ok(());
+        }
+        if n == 0 {
+            return Err(format!("inner node {} has no keys", id));
+        }
+        for i in 0..=n {
+            let low = if i == 0 { low } else { Some(keys[i - 1]) };
+            let high = if i == n { high } else { Some(keys[i]) };
+            self.check_node(node.child(i), low, high, depth + 1, leaf_depth)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::blink::BlinkTree;
+    use crate::btree::index::conformance::rng;
+    use std::collections::BTreeSet;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_locate() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(FIRST_CHUNK as u32 - 1), (0, FIRST_CHUNK - 1));
+        assert_eq!(locate(FIRST_CHUNK as u32), (1, 0));
+        assert_eq!(locate(3 * FIRST_CHUNK as u32), (2, 0));
+        // past the 16M nodes of the arena's first version
+        assert_eq!(locate(1 << 24), (14, (1 << 24) - FIRST_CHUNK * ((1 << 14) - 1)));
+        let (chunk, i) = locate(u32::MAX);
+        assert!(chunk < CHUNKS && i < FIRST_CHUNK << chunk);
+    }
+
+    #[test]
+    fn test_reuse_nodes() {
+        let tree = OlcTree::new();
+        for key in 0..20_000 {
+            tree.insert(key);
+        }
+        let nodes = tree.nodes();
+        // empty every leaf but the first and last few
+        for key in 100..19_900 {
+            assert!(tree.delete(key));
+        }
+        tree.check().unwrap();
+        assert!(tree.nodes() < nodes / 2, "{} of {} nodes left", tree.nodes(), nodes);
+        assert_eq!(tree.keys(), (0..100).chain(19_900..20_000).collect::<Vec<_>>());
+
+        // filling it again takes the freed nodes first
+        let high = tree.arena.next.load(Ordering::Relaxed);
+        for key in (100..19_900).rev() {
+            assert!(tree.insert(key));
+        }
+        tree.check().unwrap();
+        assert!(tree.arena.next.load(Ordering::Relaxed) < high + nodes / 4);
+        assert!((0..20_000).all(|k| tree.find(k)));
+    }
+
+    #[test]
+    fn test_single_thread() {
+        let tree = OlcTree::new();
+        let mut expected = BTreeSet::new();
+        let mut next = rng(11);
+        for _ in 0..20_000 {
+            let key = next() % 3000;
+            if next().is_multiple_of(3) {
+                assert_eq!(tree.delete(key), expected.remove(&key));
+            } else {
+                assert_eq!(tree.insert(key), expected.insert(key));
+            }
+        }
+        tree.check().unwrap();
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.keys(), expected.iter().copied().collect::<Vec<_>>());
+        assert_eq!(tree.range(1000..=2000), expected.range(1000..=2000).copied().collect::<Vec<_>>());
+        assert_eq!(tree.range((Bound::Excluded(1000), Bound::Excluded(2000))), expected.range(1001..2000).copied().collect::<Vec<_>>());
+        for key in 0..3000 {
+            assert_eq!(tree.find(key), expected.contains(&key));
+        }
+    }
+
+    // like the stress test of `blink`: writers own a residue class of keys,
+    // readers look up keys that are always there
+    #[test]
+    fn test_concurrent_stress() {
+        const WRITERS: u64 = 6;
+        const STABLE: u64 = 1 << 40;
+
+        let tree = Arc::new(OlcTree::new());
+        for key in (STABLE..STABLE + 4000).step_by(2) {
+            tree.insert(key);
+        }
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..2)
+            .map(|r| {
+                let tree = tree.clone();
+                let done = done.clone();
+                std::thread::spawn(move || {
+                    let mut next = rng(r + 100);
+                    while !done.load(Ordering::Relaxed) {
+                        let key = STABLE + next() % 2000 * 2;
+                        assert!(tree.find(key), "stable key {} not found", key);
+                        assert!(!tree.find(key + 1));
+                    }
+                })
+            })
+            .collect();
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let tree = tree.clone();
+                std::thread::spawn(move || {
+                    let mut next = rng(w + 1);
+                    let mut mine = BTreeSet::new();
+                    for _ in 0..30_000 {
+                        let key = next() % 5000 * WRITERS + w;
+                        match next() % 4 {
+                            0 => assert_eq!(tree.delete(key), mine.remove(&key)),
+                            1 => assert_eq!(tree.find(key), mine.contains(&key)),
+                            _ => assert_eq!(tree.insert(key), mine.insert(key)),
+                        }
+                    }
+                    mine
+                })
+            })
+            .collect();
+        let mut expected = BTreeSet::new();
+        for writer in writers {
+            expected.extend(writer.join().unwrap());
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        expected.extend((STABLE..STABLE + 4000).step_by(2));
+        tree.check().unwrap();
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.keys(), expected.into_iter().collect::<Vec<_>>());
+    }
+
+    // lookups per second with 1, 2, 4, ... threads for the optimistic tree
+    // and the latched B-link tree, run with
+    //   cargo test --release bench_lookup_scaling -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_lookup_scaling() {
+        const KEYS: u64 = 1_000_000;
+        const RUN: Duration = Duration::from_millis(500);
+
+        let olc = Arc::new(OlcTree::new());
+        let blink = Arc::new(BlinkTree::new());
+        let mut next = rng(5);
+        for _ in 0..KEYS {
+            let key = next() % (KEYS * 4);
+            olc.insert(key);
+            blink.insert(key);
+        }
+
+        let cores = std::thread::available_parallelism().map_or(4, |n| n.get());
+        let run = |lookup: Arc<dyn Fn(u64) -> bool + Send + Sync>, threads: usize| -> f64 {
+            let start = Instant::now();
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let lookup = lookup.clone();
+                    std::thread::spawn(move || {
+                        let mut next = rng(t as u64 + 1);
+                        let mut ops = 0u64;
+                        while start.elapsed() < RUN {
+                            for _ in 0..1000 {
+                                std::hint::black_box(lookup(next() % (KEYS * 4)));
+                            }
+                            ops += 1000;
+                        }
+                        ops
+                    })
+                })
+                .collect();
+            let ops: u64 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+            ops as f64 / start.elapsed().as_secs_f64()
+        };
+
+        println!("threads  optimistic(Mops/s)  latched(Mops/s)");
+        let mut threads = 1;
+        while threads <= cores {
+            let o = olc.clone();
+            let b = blink.clone();
+            let olc_rate = run(Arc::new(move |k| o.find(k)), threads);
+            let blink_rate = run(Arc::new(move |k| b.contains(&k)), threads);
+            println!("{:>7}  {:>18.2}  {:>15.2}", threads, olc_rate / 1e6, blink_rate / 1e6);
+            threads *= 2;
+        }
+    }
+}
@@ -1,45 +1,88 @@
 #![allow(dead_code)]
 
+pub mod parse;
+
+use std::cmp::Ordering;
 use std::fmt::Display;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use super::collate::{Comparator, Natural};
 
+// a node holds up to `MAX_CHILDREN - 1` keys whatever their size, and
+// splits, borrows and merges count keys. Nodes bounded by bytes are those
+// of `bytes::BytesTree`, which the btree indexes of `storage` are kept in.
 const MAX_CHILDREN: usize = 5;
-const INIT: Option<Box<Node>> = None;
 
+// a subtree and its height
+type Tree<K, C> = (Box<Node<K, C>>, usize);
+
+// A tree ordered by the comparator `C`, its keys' own order by default.
 #[derive(Debug)]
-pub struct Node {
+pub struct Node<K = usize, C = Natural> {
     // the number of keys
     pub n: usize,
     // one more for hypotetical right child
     // The actual maximum number of keys is `MAX_CHILDREN - 1`
-    pub keys: [usize; MAX_CHILDREN],
+    pub keys: [K; MAX_CHILDREN],
     // The actual maximum number of child is `MAX_CHILDREN`
-    pub children: [Option<Box<Node>>; MAX_CHILDREN + 1],
+    pub children: [Option<Box<Node<K, C>>>; MAX_CHILDREN + 1],
     pub is_leaf: bool,
+    // the number of keys in this subtree, `None` unless the tree was made
+    // with `new_counted`. Kept up to date, it makes `rank`, `select` and
+    // `count_range` O(log n) instead of O(n).
+    pub size: Option<usize>,
+    order: PhantomData<C>,
 }
 
-struct NodeFormatConfig<'a> {
+struct NodeFormatConfig<'a, K, C> {
     level: usize,
-    right_most_node: &'a Node,
+    right_most_node: &'a Node<K, C>,
     first_child_found: bool,
 }
 
-impl Node {
+// a tree in another order than its keys' own is made by `default`, as
+// `Node::<String, CaseInsensitive>::default()`, or by `default_counted`
+// to maintain subtree counts
+impl<K: Clone + Default, C: Comparator<K>> Default for Node<K, C> {
+    fn default() -> Self {
+        Self::empty(false)
+    }
+}
+
+impl<K: Ord + Clone + Default> Node<K> {
     pub fn new() -> Self {
+        Self::empty(false)
+    }
+
+    pub fn new_boxed() -> Box<Self> {
+        Box::new(Self::new())
+    }
+
+    // an empty tree that maintains subtree counts
+    pub fn new_counted() -> Self {
+        Self::empty(true)
+    }
+
+    pub fn new_boxed_counted() -> Box<Self> {
+        Box::new(Self::new_counted())
+    }
+}
+
+impl<K: Clone + Default, C: Comparator<K>> Node<K, C> {
+    fn empty(counted: bool) -> Self {
         Self {
             n: 0,
-            keys: [0; MAX_CHILDREN],
-            children: [INIT; MAX_CHILDREN + 1],
+            keys: std::array::from_fn(|_| K::default()),
+            children: std::array::from_fn(|_| None),
             is_leaf: true,
+            size: counted.then_some(0),
+            order: PhantomData,
         }
     }
 
-    pub fn new_boxed() -> Box<Self> {
-        Box::new(Self {
-            n: 0,
-            keys: [0; MAX_CHILDREN],
-            children: [INIT; MAX_CHILDREN + 1],
-            is_leaf: true,
-        })
+    pub fn default_counted() -> Self {
+        Self::empty(true)
     }
 
     fn is_node_full(&self) -> bool {
@@ -67,7 +110,7 @@ impl Node {
     //   set the parent's first child to the left child
     //   set the parent's second child to the right child
     //   return the parent
-    pub fn insert(&mut self, key: usize) {
+    pub fn insert(&mut self, key: K) {
         self.insert_down_to_leaf(key);
     }
 
@@ -75,23 +118,20 @@ impl Node {
         self.n >= MAX_CHILDREN
     }
 
-    fn insert_key(&mut self, key: usize, index: usize) {
+    fn insert_key(&mut self, key: K, index: usize) {
         let i = if index == usize::MAX {
-            self.find_pos(key)
+            self.find_pos(&key)
         } else {
             index
         };
-        let mut k = self.n;
+        let k = self.n;
         assert!(k < MAX_CHILDREN);
-        while k > i {
-            self.keys[k] = self.keys[k - 1];
-            k -= 1;
-        }
+        self.keys[i..=k].rotate_right(1);
         self.keys[i] = key;
         self.n += 1;
     }
 
-    fn insert_child(&mut self, index: usize, lc: Option<Box<Node>>, rc: Option<Box<Node>>) {
+    fn insert_child(&mut self, index: usize, lc: Option<Box<Node<K, C>>>, rc: Option<Box<Node<K, C>>>) {
         let i = index;
         let mut k = MAX_CHILDREN;
         while k > (i + 1) {
@@ -102,80 +142,111 @@ impl Node {
         self.children[i + 1] = rc;
     }
 
-    fn insert_down_to_leaf(&mut self, key: usize) -> bool {
+    fn insert_down_to_leaf(&mut self, key: K) -> bool {
         if self.is_leaf {
             self.insert_key(key, usize::MAX);
             if self.need_split() {
                 self.split_node();
                 return true;
             }
-            return false;
         } else {
-            let i = self.find_pos(key);
+            let i = self.find_pos(&key);
             let child = self.children[i].as_mut().unwrap();
             let splited = child.insert_down_to_leaf(key);
             if splited {
-                let key = child.keys[0];
-                let lc = child.children[0].take();
-                let rc = child.children[1].take();
-                self.insert_key(key, i);
-                self.insert_child(i, lc, rc);
-
-                if self.need_split() {
-                    self.split_node();
-
-                    #[cfg(feature="debug2")]
-                    println!("internal(full) inserted\n{}", self);
-                    return true;
-                } else {
-                    #[cfg(feature="debug2")]
-                    println!("internal inserted\n{}", self);
-                    return false;
-                }
+                return self.absorb_split(i);
+            }
+        }
+        self.recount();
+        false
+    }
+
+    // like `insert_down_to_leaf`, but the position in every node on the way
+    // down is already known: `path` has one per level, down to the leaf
+    fn insert_along(&mut self, key: K, path: &[usize]) -> bool {
+        let i = path[0];
+        if self.is_leaf {
+            self.insert_key(key, i);
+            if self.need_split() {
+                self.split_node();
+                return true;
+            }
+        } else {
+            let splited = self.children[i].as_mut().unwrap().insert_along(key, &path[1..]);
+            if splited {
+                return self.absorb_split(i);
             }
         }
-        return false;
+        self.recount();
+        false
+    }
+
+    // children[i] has split into a parent of one key, take that key and its
+    // two children in, returns whether this node had to split as well
+    fn absorb_split(&mut self, i: usize) -> bool {
+        let child = self.children[i].as_mut().unwrap();
+        let key = std::mem::take(&mut child.keys[0]);
+        let lc = child.children[0].take();
+        let rc = child.children[1].take();
+        self.insert_key(key, i);
+        self.insert_child(i, lc, rc);
+
+        if self.need_split() {
+            self.split_node();
+
+            #[cfg(feature="debug2")]
+            println!("internal(full) inserted\n{}", self);
+            true
+        } else {
+            #[cfg(feature="debug2")]
+            println!("internal inserted\n{}", self);
+            self.recount();
+            false
+        }
     }
 
     fn split_node(&mut self) {
-        let mut new_parent = Node::new_boxed();
-        let mut right_child = Node::new_boxed();
+        let mut new_parent = Box::new(Self::empty(false));
+        let mut right_child = Box::new(Self::empty(false));
 
         for i in 0..((self.n - 1) / 2) {
-            right_child.keys[i] = self.keys[self.n / 2 + 1 + i];
+            right_child.keys[i] = std::mem::take(&mut self.keys[self.n / 2 + 1 + i]);
             right_child.children[i] = self.children[self.n / 2 + 1 + i].take();
         }
         right_child.children[(self.n - 1) / 2] = self.children[self.n].take();
         right_child.n = (self.n - 1) / 2;
         right_child.is_leaf = self.is_leaf;
+        right_child.size = self.size;
+        right_child.recount();
 
         new_parent.is_leaf = false;
-        new_parent.keys[0] = self.keys[self.n / 2];
+        new_parent.keys[0] = std::mem::take(&mut self.keys[self.n / 2]);
         new_parent.n = 1;
+        new_parent.size = self.size;
         self.n = (self.n - 1) / 2;
-        // `std::mem::replace` is an VERY IMPORTANT API for this case
+        self.recount();
+        // `std::mem::take`(i.e. `std::mem::replace` with a default node) is an
+        // VERY IMPORTANT API for this case
         // Without it, I can not turn `self` to Box<Node>
-        new_parent.children[0] = Some(Box::new(std::mem::replace(self, Node::new())));
+        new_parent.children[0] = Some(Box::new(std::mem::take(self)));
         new_parent.children[1] = Some(right_child);
 
         *self = *new_parent;
+        self.recount();
     }
 
-    fn is_new_node(&self, node: &Node) -> bool {
-        (self as *const Node) != (node as *const Node)
+    fn is_new_node(&self, node: &Node<K, C>) -> bool {
+        !std::ptr::eq(self, node)
     }
 
-    fn find_pos(&self, key: usize) -> usize {
-        let mut i = 0;
-        while i < self.n && i < (MAX_CHILDREN - 1) && key > self.keys[i] {
-            i += 1;
-        }
-        i
+    // the number of keys less than `key`
+    fn find_pos(&self, key: &K) -> usize {
+        self.keys[..self.n].partition_point(|k| C::compare(k, key).is_lt())
     }
 
-    fn find(&self, key: usize) -> Option<&Node> {
-        let i = self.find_pos(key);
-        if i < self.n && key == self.keys[i] {
+    fn find(&self, key: K) -> Option<&Node<K, C>> {
+        let i = self.find_pos(&key);
+        if i < self.n && C::compare(&key, &self.keys[i]).is_eq() {
             return Some(self);
         }
         if self.is_leaf {
@@ -184,155 +255,157 @@ impl Node {
         self.children[i].as_ref().unwrap().find(key)
     }
 
-    fn is_balanced(&self) -> bool {
-        if self.is_leaf {
-            return true;
-        } else {
-            let mut ph = 0;
-            for child in &self.children {
-                match child {
-                    Some(bc) => {
-                        let h = bc.height();
-                        if ph == 0 {
-                            ph = h;
-                        }
-                        if ph != h {
-                            return false;
-                        }
-                    }
-                    None => break,
-                }
+    // collect, in key order, every key for which `probe` returns `Equal`.
+    //
+    // `probe` tells where a key lies relative to the wanted range:
+    //   `Less`    the key sorts before the range
+    //   `Equal`   the key is inside the range
+    //   `Greater` the key sorts after the range
+    // so the range must be contiguous in key order. Subtrees that lie
+    // entirely outside of the range are never visited.
+    pub fn scan_by<'a, F>(&'a self, probe: &F, out: &mut Vec<&'a K>)
+    where
+        F: Fn(&K) -> Ordering,
+    {
+        for i in 0..self.n {
+            let ord = probe(&self.keys[i]);
+            if !self.is_leaf && ord != Ordering::Less {
+                self.children[i].as_ref().unwrap().scan_by(probe, out);
+            }
+            match ord {
+                Ordering::Less => (),
+                Ordering::Equal => out.push(&self.keys[i]),
+                Ordering::Greater => return,
             }
         }
-        true
+        if !self.is_leaf {
+            self.children[self.n].as_ref().unwrap().scan_by(probe, out);
+        }
     }
 
-    fn height(&self) -> usize {
-        if self.is_leaf {
-            return 1;
-        } else {
-            let mut mh = 1;
-            for child in &self.children {
-                match child {
-                    Some(bc) => {
-                        mh = std::cmp::max(mh, bc.height());
-                    }
-                    None => break,
-                }
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<&K> {
+        let probe = |key: &K| {
+            let above_start = match range.start_bound() {
+                Bound::Included(start) => C::compare(key, start).is_ge(),
+                Bound::Excluded(start) => C::compare(key, start).is_gt(),
+                Bound::Unbounded => true,
+            };
+            if !above_start {
+                return Ordering::Less;
             }
-            return mh + 1;
-        }
+            let below_end = match range.end_bound() {
+                Bound::Included(end) => C::compare(key, end).is_le(),
+                Bound::Excluded(end) => C::compare(key, end).is_lt(),
+                Bound::Unbounded => true,
+            };
+            if below_end {
+                Ordering::Equal
+            } else {
+                Ordering::Greater
+            }
+        };
+        let mut out = Vec::new();
+        self.scan_by(&probe, &mut out);
+        out
     }
 
-    fn have_child(&self) -> bool {
-        for child in &self.children {
-            match child {
-                Some(_) => return true,
-                None => break,
-            }
-        }
-        false
+    // the smallest key within the lower bound `from`
+    pub fn first_from(&self, from: Bound<&K>) -> Option<&K> {
+        let i = match from {
+            Bound::Included(key) => self.keys[..self.n].partition_point(|k| C::compare(k, key).is_lt()),
+            Bound::Excluded(key) => self.keys[..self.n].partition_point(|k| C::compare(k, key).is_le()),
+            Bound::Unbounded => 0,
+        };
+        let below = match self.is_leaf {
+            true => None,
+            false => self.children[i].as_ref().unwrap().first_from(from),
+        };
+        below.or(self.keys[..self.n].get(i))
     }
 
-    // see `build_tree`
-    fn fmt_internal(
-        &self,
-        cfg: &mut NodeFormatConfig,
-        f: &mut std::fmt::Formatter<'_>,
-    ) -> std::fmt::Result {
-        if !self.have_child() {
-            if !cfg.first_child_found {
-                cfg.first_child_found = true;
-                writeln!(f)?;
-            }
-            for _ in 0..cfg.level {
-                write!(f, " ")?;
-            }
-            write!(f, "[")?;
-            for i in 0..self.n {
-                write!(f, "{}", self.keys[i])?;
-                if i < self.n - 1 {
-                    write!(f, ", ")?;
-                }
-            }
-            return writeln!(f, "],");
-        }
+    // the largest key within the upper bound `until`
+    pub fn last_until(&self, until: Bound<&K>) -> Option<&K> {
+        let i = match until {
+            Bound::Included(key) => self.keys[..self.n].partition_point(|k| C::compare(k, key).is_le()),
+            Bound::Excluded(key) => self.keys[..self.n].partition_point(|k| C::compare(k, key).is_lt()),
+            Bound::Unbounded => self.n,
+        };
+        let below = match self.is_leaf {
+            true => None,
+            false => self.children[i].as_ref().unwrap().last_until(until),
+        };
+        below.or(i.checked_sub(1).map(|j| &self.keys[j]))
+    }
 
-        if !cfg.first_child_found {
-            write!(f, "{{")?;
-        } else {
-            for _ in 0..cfg.level {
-                write!(f, " ")?;
-            }
-            writeln!(f, "{{")?;
+    // the number of keys less than `key` (or not greater, if `inclusive`)
+    fn count_below(&self, key: &K, inclusive: bool) -> usize {
+        let i = match inclusive {
+            true => self.keys[..self.n].partition_point(|k| C::compare(k, key).is_le()),
+            false => self.keys[..self.n].partition_point(|k| C::compare(k, key).is_lt()),
+        };
+        if self.is_leaf {
+            return i;
         }
+        let left: usize = self.children[..i].iter().map(|c| c.as_ref().unwrap().len()).sum();
+        i + left + self.children[i].as_ref().unwrap().count_below(key, inclusive)
+    }
 
-        for i in 0..self.n {
-            match self.children[i].as_ref() {
-                Some(bc) => {
-                    cfg.level += 1;
-                    bc.fmt_internal(cfg, f)?;
-                    cfg.level -= 1;
+    // how many keys are less than `key`
+    pub fn rank(&self, key: &K) -> usize {
+        self.count_below(key, false)
+    }
+
+    // the k-th smallest key, from 0
+    pub fn select(&self, mut k: usize) -> Option<&K> {
+        for i in 0..=self.n {
+            if !self.is_leaf {
+                let child = self.children[i].as_ref().unwrap();
+                let size = child.len();
+                if k < size {
+                    return child.select(k);
                 }
-                None => (),
+                k -= size;
             }
-            for _ in 0..cfg.level {
-                write!(f, " ")?;
-            }
-            writeln!(f, "{},", self.keys[i])?;
-        }
-        match self.children[self.n].as_ref() {
-            Some(bc) => {
-                cfg.level += 1;
-                bc.fmt_internal(cfg, f)?;
-                cfg.level -= 1;
-                for _ in 0..cfg.level {
-                    write!(f, " ")?;
-                }
-                if bc.as_ref() as *const Node == cfg.right_most_node as *const Node {
-                    write!(f, "}}")?;
-                } else if cfg.level == 0 {
-                    write!(f, "}}")?;
-                } else {
-                    writeln!(f, "}},")?;
+            if i < self.n {
+                if k == 0 {
+                    return Some(&self.keys[i]);
                 }
+                k -= 1;
             }
-            None => (),
         }
-        Ok(())
+        None
     }
 
-    pub fn get_rightmost_node(&self) -> &Node {
-        if self.is_leaf {
-            return self;
-        }
-        for child in self.children.iter().rev() {
-            match child {
-                Some(bc) => return bc.get_rightmost_node(),
-                None => continue,
-            }
-        }
-        unreachable!()
+    // how many keys lie in `range`
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        let below_start = match range.start_bound() {
+            Bound::Included(start) => self.count_below(start, false),
+            Bound::Excluded(start) => self.count_below(start, true),
+            Bound::Unbounded => 0,
+        };
+        let below_end = match range.end_bound() {
+            Bound::Included(end) => self.count_below(end, true),
+            Bound::Excluded(end) => self.count_below(end, false),
+            Bound::Unbounded => self.len(),
+        };
+        below_end.saturating_sub(below_start)
     }
 
+    // the fewest keys a node other than the root may hold
+    const MIN_KEYS: usize = (MAX_CHILDREN - 1) / 2;
+
+    // fix up children[i] after a key was deleted below it:
+    //   borrow a key from the left or right sibling if it has one to spare,
+    //   otherwise merge the child with a sibling
     fn fill_child(&mut self, i: usize) {
-        if i > 0 {
-            if let Some(left) = self.children[i - 1].as_mut() {
-                if left.n > (MAX_CHILDREN - 1) / 2 {
-                    self.borrow_from_left(i);
-                    return;
-                }
-            }
-        }
-        if i < self.n {
-            if let Some(right) = self.children[i + 1].as_mut() {
-                if right.n > (MAX_CHILDREN - 1) / 2 {
-                    self.borrow_from_right(i);
-                    return;
-                }
-            }
+        if self.children[i].as_ref().unwrap().n >= Self::MIN_KEYS {
+            return;
         }
-        if i > 0 {
+        if i > 0 && self.children[i - 1].as_ref().unwrap().n > Self::MIN_KEYS {
+            self.borrow_from_left(i);
+        } else if i < self.n && self.children[i + 1].as_ref().unwrap().n > Self::MIN_KEYS {
+            self.borrow_from_right(i);
+        } else if i > 0 {
             self.merge(i);
         } else {
             self.merge(i + 1);
@@ -344,11 +417,9 @@ impl Node {
         let left = left[i - 1].as_mut().unwrap();
         let child = child[0].as_mut().unwrap();
 
-        for j in (1..child.n + 1).rev() {
-            child.keys[j] = child.keys[j - 1];
-        }
-        child.keys[0] = self.keys[i - 1];
-        self.keys[i - 1] = left.keys[left.n - 1];
+        child.keys[..=child.n].rotate_right(1);
+        child.keys[0] = std::mem::take(&mut self.keys[i - 1]);
+        self.keys[i - 1] = std::mem::take(&mut left.keys[left.n - 1]);
         if !left.is_leaf {
             for j in (1..child.n + 2).rev() {
                 child.children[j] = child.children[j - 1].take();
@@ -357,26 +428,26 @@ impl Node {
         }
         child.n += 1;
         left.n -= 1;
+        child.recount();
+        left.recount();
     }
 
     fn borrow_from_right(&mut self, i: usize) {
-        let (child, right) = self.children.split_at_mut(i);
-        let child = child[i - 1].as_mut().unwrap();
+        let (child, right) = self.children.split_at_mut(i + 1);
+        let child = child[i].as_mut().unwrap();
         let right = right[0].as_mut().unwrap();
-        
-        child.keys[child.n] = self.keys[i];
-        self.keys[i] = right.keys[0];
-        for j in 0..right.n - 1 {
-            right.keys[j] = right.keys[j + 1];
-        }
+
+        child.keys[child.n] = std::mem::take(&mut self.keys[i]);
+        self.keys[i] = std::mem::take(&mut right.keys[0]);
+        right.keys[..right.n].rotate_left(1);
         if !right.is_leaf {
             child.children[child.n + 1] = right.children[0].take();
-            for j in 0..right.n {
-                right.children[j] = right.children[j + 1].take();
-            }
+            right.children[..=right.n].rotate_left(1);
         }
         child.n += 1;
         right.n -= 1;
+        child.recount();
+        right.recount();
     }
 
     fn merge(&mut self, i: usize) {
@@ -384,124 +455,580 @@ impl Node {
         let child = child[i - 1].as_mut().unwrap();
         let right = right[0].as_mut().unwrap();
 
-        for j in 0..right.n {
-            right.keys[child.n + 1 + j] = right.keys[j];
-        }
-        right.keys[child.n] = self.keys[i-1];
+        let shift = child.n + 1;
+        right.keys[..shift + right.n].rotate_right(shift);
+        right.keys[child.n] = std::mem::take(&mut self.keys[i - 1]);
         for j in 0..child.n {
-            right.keys[j] = child.keys[j];
+            right.keys[j] = std::mem::take(&mut child.keys[j]);
         }
-        right.n += child.n + 1;
         if !right.is_leaf {
-            for j in 0..=child.n {
-                right.children[child.n + 1 + j] = right.children[j].take();
-            }
+            right.children[..shift + right.n + 1].rotate_right(shift);
         }
         if !child.is_leaf {
             for j in 0..=child.n {
                 right.children[j] = child.children[j].take();
             }
         }
-        for j in i..self.n {
-            self.keys[j - 1] = self.keys[j];
-        }
-        for j in i..=self.n {
-            self.children[j - 1] = self.children[j].take();
-        }
+        right.n += shift;
+        right.recount();
+        self.keys[i - 1..self.n].rotate_left(1);
+        self.children[i - 1..=self.n].rotate_left(1);
+        self.children[self.n] = None;
         self.n -= 1;
     }
 
-    fn predecessor(&self, i: usize) -> usize {
+    fn predecessor(&self, i: usize) -> K {
         let mut cur = self.children[i].as_ref().unwrap();
         while !cur.is_leaf {
             cur = cur.children[cur.n].as_ref().unwrap();
         }
-        cur.keys[cur.n - 1]
+        cur.keys[cur.n - 1].clone()
     }
 
-    fn successor(&self, i: usize) -> usize {
+    fn successor(&self, i: usize) -> K {
         let mut cur = self.children[i + 1].as_ref().unwrap();
         while !cur.is_leaf {
             cur = cur.children[0].as_ref().unwrap();
         }
-        cur.keys[0]
+        cur.keys[0].clone()
     }
 
+    // delete keys[i] of an internal node:
+    //   replace it with its predecessor if the left child can spare a key,
+    //   or with its successor if the right child can,
+    //   if both children are leaves with the fewest keys, merge them and
+    //   delete the key from the merged leaf,
+    //   otherwise replace it with its predecessor and fix up the left child
     fn delete_internal_node(&mut self, i: usize) {
-        let key = self.keys[i];
-        if self.children[i].as_ref().unwrap().n > (MAX_CHILDREN - 1) / 2 {
-            if self.n >= MAX_CHILDREN {
-                self.split_node();
-                if i > MAX_CHILDREN / 2 {
-                    let i = i - MAX_CHILDREN / 2 - 1;
-                    self.children[1].as_mut().unwrap().delete_internal_node(i);
-                } else {
-                    self.children[0].as_mut().unwrap().delete_internal_node(0);
-                }
-            } else {
-                let pred = self.predecessor(i);
-                self.keys[i] = pred;
-                self.children[i].as_mut().unwrap().delete(pred);
-            }
-        } else if self.children[i + 1].as_ref().unwrap().n > (MAX_CHILDREN - 1) / 2 {
-            if self.n >= MAX_CHILDREN {
-                self.split_node();
-                if i > MAX_CHILDREN / 2 {
-                    let i = i - MAX_CHILDREN / 2 - 1;
-                    self.children[1].as_mut().unwrap().delete_internal_node(i);
-                } else {
-                    self.children[0].as_mut().unwrap().delete_internal_node(0);
-                }
-            } else {
-                let succ = self.successor(i);
-                self.keys[i] = succ;
-                self.children[i + 1].as_mut().unwrap().delete(succ);
-            }
+        let left = self.children[i].as_ref().unwrap();
+        let right = self.children[i + 1].as_ref().unwrap();
+        if left.n > Self::MIN_KEYS || (!left.is_leaf && right.n <= Self::MIN_KEYS) {
+            let pred = self.predecessor(i);
+            self.keys[i] = pred.clone();
+            self.children[i].as_mut().unwrap().delete_from(&pred);
+            self.fill_child(i);
+        } else if right.n > Self::MIN_KEYS {
+            let succ = self.successor(i);
+            self.keys[i] = succ.clone();
+            self.children[i + 1].as_mut().unwrap().delete_from(&succ);
+            self.fill_child(i + 1);
         } else {
+            let key = self.keys[i].clone();
             self.merge(i + 1);
-            if let Some(child) = self.children[i].as_mut() {
-                child.delete(key);
-            }
-            if self.n == 0 {
-                *self = *self.children[0].take().unwrap();
-            }
+            self.children[i].as_mut().unwrap().delete_from(&key);
         }
     }
 
-    pub fn delete(&mut self, key: usize) {
+    // delete `key` from the subtree, a child left with too few keys is
+    // fixed up on the way back
+    fn delete_from(&mut self, key: &K) {
         let i = self.find_pos(key);
-        // if the key is found in the current node
-        if i < self.n && key == self.keys[i] {
+        if i < self.n && C::compare(key, &self.keys[i]).is_eq() {
             if self.is_leaf {
-                for j in i..self.n - 1 {
-                    self.keys[j] = self.keys[j + 1];
-                }
+                self.keys[i..self.n].rotate_left(1);
                 self.n -= 1;
             } else {
                 self.delete_internal_node(i);
             }
+        } else if !self.is_leaf {
+            self.children[i].as_mut().unwrap().delete_from(key);
+            self.fill_child(i);
+        }
+        self.recount();
+    }
+
+    // the place of `key` in the tree, found in a single descent, to look at
+    // or remove the key if it is there or insert it if it is not
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, C> {
+        let mut path = Vec::new();
+        let mut node = &*self;
+        loop {
+            let i = node.find_pos(&key);
+            path.push(i);
+            if i < node.n && C::compare(&node.keys[i], &key).is_eq() {
+                return Entry::Occupied(OccupiedEntry { root: self, path });
+            }
+            if node.is_leaf {
+                return Entry::Vacant(VacantEntry { root: self, key, path });
+            }
+            node = node.children[i].as_ref().unwrap();
+        }
+    }
+
+    pub fn delete(&mut self, key: K) {
+        self.delete_from(&key);
+        // the root lost its last key, its only child is the new root
+        if self.n == 0 && !self.is_leaf {
+            *self = *self.children[0].take().unwrap();
+        }
+    }
+
+    // the number of levels, following the leftmost path
+    fn depth(&self) -> usize {
+        match self.is_leaf {
+            true => 1,
+            false => 1 + self.children[0].as_ref().unwrap().depth(),
+        }
+    }
+
+    // Join two trees and a key that sorts between them into one tree. Both
+    // trees are valid but for their roots, which may hold too few keys. The
+    // shorter tree is hung next to the edge of the taller one at its own
+    // height and fixed up like after a delete, then the key goes up with
+    // any splits like after an insert. The heights come with the trees and
+    // the joined one's is returned, so it takes O(height difference).
+    fn join((mut left, lh): Tree<K, C>, sep: K, (mut right, rh): Tree<K, C>) -> Tree<K, C> {
+        if left.n == 0 && left.is_leaf {
+            let grown = right.insert_down_to_leaf(sep);
+            return (right, rh + usize::from(grown));
+        }
+        if right.n == 0 && right.is_leaf {
+            let grown = left.insert_down_to_leaf(sep);
+            return (left, lh + usize::from(grown));
+        }
+        if lh > rh {
+            let grown = left.join_right(lh, sep, right, rh);
+            return (left, lh + usize::from(grown));
+        }
+        if rh > lh {
+            let grown = right.join_left(rh, left, sep, lh);
+            return (right, rh + usize::from(grown));
+        }
+        let mut root = Box::new(Self::empty(false));
+        root.is_leaf = false;
+        root.size = left.size.or(right.size);
+        root.keys[0] = sep;
+        root.n = 1;
+        root.children[0] = Some(left);
+        root.children[1] = Some(right);
+        root.fill_child(0);
+        if root.n == 1 {
+            root.fill_child(1);
+        }
+        if root.n == 0 {
+            return (root.children[0].take().unwrap(), lh);
+        }
+        root.recount();
+        (root, lh + 1)
+    }
+
+    // hang `tree` of height `h` to the right of this subtree of height
+    // `depth`, with `sep` in between, returns whether this node split
+    fn join_right(&mut self, depth: usize, sep: K, tree: Box<Node<K, C>>, h: usize) -> bool {
+        if depth == h + 1 {
+            let i = self.n;
+            self.insert_key(sep, i);
+            self.children[i + 1] = Some(tree);
+            self.fill_child(i + 1);
+        } else {
+            let i = self.n;
+            let splited = self.children[i].as_mut().unwrap().join_right(depth - 1, sep, tree, h);
+            if splited {
+                return self.absorb_split(i);
+            }
+        }
+        if self.need_split() {
+            self.split_node();
+            return true;
+        }
+        self.recount();
+        false
+    }
+
+    // the mirror image of `join_right`
+    fn join_left(&mut self, depth: usize, tree: Box<Node<K, C>>, sep: K, h: usize) -> bool {
+        if depth == h + 1 {
+            self.insert_key(sep, 0);
+            let first = self.children[0].take();
+            self.insert_child(0, Some(tree), first);
+            self.fill_child(0);
         } else {
-            // if the key is not found in the current node
-            if let Some(child) = self.children[i].as_ref() {
-                let oldn = self.n;
-                if child.n < 1 + (MAX_CHILDREN - 1) / 2 {
-                    self.fill_child(i);
+            let splited = self.children[0].as_mut().unwrap().join_left(depth - 1, tree, sep, h);
+            if splited {
+                return self.absorb_split(0);
+            }
+        }
+        if self.need_split() {
+            self.split_node();
+            return true;
+        }
+        self.recount();
+        false
+    }
+
+    // move keys[from..to] and the children around them into a new node of
+    // this node's `height`, or return the only child if that leaves no keys
+    fn detach(&mut self, from: usize, to: usize, height: usize) -> Tree<K, C> {
+        if !self.is_leaf && from == to {
+            return (self.children[from].take().unwrap(), height - 1);
+        }
+        let mut node = Box::new(Self::empty(false));
+        node.is_leaf = self.is_leaf;
+        node.size = self.size;
+        for j in from..to {
+            node.keys[j - from] = std::mem::take(&mut self.keys[j]);
+        }
+        if !self.is_leaf {
+            for j in from..=to {
+                node.children[j - from] = self.children[j].take();
+            }
+        }
+        node.n = to - from;
+        node.recount();
+        (node, height)
+    }
+
+    // split the subtree of `height` levels into the keys less than `key`
+    // and the rest, with their heights: split the child `key` falls into,
+    // then join each half with what is left of this node on its side.
+    // Leaves this node a husk.
+    fn split_at(&mut self, key: &K, height: usize) -> (Tree<K, C>, Tree<K, C>) {
+        let n = self.n;
+        let i = self.keys[..n].partition_point(|k| C::compare(k, key).is_lt());
+        if self.is_leaf {
+            let right = self.detach(i, n, height);
+            let left = self.detach(0, i, height);
+            return (left, right);
+        }
+        let (low, high) = self.children[i].as_mut().unwrap().split_at(key, height - 1);
+        let left = match i {
+            0 => low,
+            _ => {
+                let sep = std::mem::take(&mut self.keys[i - 1]);
+                Node::join(self.detach(0, i - 1, height), sep, low)
+            }
+        };
+        let right = match i == n {
+            true => high,
+            false => {
+                let sep = std::mem::take(&mut self.keys[i]);
+                Node::join(high, sep, self.detach(i + 1, n, height))
+            }
+        };
+        (left, right)
+    }
+
+    // the shape of the tree, level by level
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            leaf_fill: vec![0; MAX_CHILDREN],
+            internal_fill: vec![0; MAX_CHILDREN],
+            ..Stats::default()
+        };
+        let mut level = vec![self];
+        while !level.is_empty() {
+            stats.level_nodes.push(level.len());
+            let mut below = Vec::new();
+            for node in level {
+                stats.keys += node.n;
+                if stats.height > 0 && node.n < Self::MIN_KEYS {
+                    stats.underfull_nodes += 1;
                 }
-                let merged = oldn != self.n;
-                if i > 0 && merged {
-                    self.children[i - 1].as_mut().unwrap().delete(key);
+                if node.is_leaf {
+                    stats.leaf_nodes += 1;
+                    stats.leaf_fill[node.n] += 1;
                 } else {
-                    self.children[i].as_mut().unwrap().delete(key);
+                    stats.internal_nodes += 1;
+                    stats.internal_fill[node.n] += 1;
+                    below.extend(node.children[..=node.n].iter().map(|c| c.as_deref().unwrap()));
                 }
-                if self.n == 0 {
-                    *self = *self.children[0].take().unwrap();
+            }
+            stats.height += 1;
+            level = below;
+        }
+        let nodes = stats.leaf_nodes + stats.internal_nodes;
+        stats.avg_keys = stats.keys as f64 / nodes as f64;
+        let leaf_keys: usize = stats.leaf_fill.iter().enumerate().map(|(n, count)| n * count).sum();
+        stats.avg_leaf_density = 100.0 * leaf_keys as f64 / (stats.leaf_nodes * (MAX_CHILDREN - 1)) as f64;
+        stats.memory = nodes * std::mem::size_of::<Node<K, C>>();
+        stats
+    }
+
+    // move every key not less than `key` into a new tree, like
+    // `BTreeMap::split_off`
+    pub fn split_off(&mut self, key: &K) -> Box<Node<K, C>> {
+        let height = self.depth();
+        let ((left, lh), (right, rh)) = self.split_at(key, height);
+        debug_assert!(lh == left.depth() && rh == right.depth());
+        *self = *left;
+        right
+    }
+
+    // move all keys of `other`, which must sort after all keys of this
+    // tree, into this tree, leaving `other` empty
+    pub fn append(&mut self, other: &mut Node<K, C>) {
+        let counted = other.size.map(|_| 0);
+        let Some(sep) = other.first_from(Bound::Unbounded).cloned() else {
+            return;
+        };
+        if let Some(last) = self.last_until(Bound::Unbounded) {
+            assert!(C::compare(last, &sep).is_lt(), "the trees to append overlap");
+        }
+        other.delete(sep.clone());
+        let (lh, rh) = (self.depth(), other.depth());
+        let left = (Box::new(std::mem::take(self)), lh);
+        let right = (Box::new(std::mem::take(other)), rh);
+        let (joined, height) = Node::join(left, sep, right);
+        debug_assert_eq!(height, joined.depth());
+        *self = *joined;
+        other.size = counted;
+    }
+
+    // the most keys a tree of `height` levels holds
+    fn capacity(height: u32) -> usize {
+        MAX_CHILDREN.pow(height) - 1
+    }
+
+    // a tree of `height` levels holding the next `len` keys of `keys`,
+    // which are sorted: every node has as few children as hold its keys
+    // and they share them evenly, so all but a few nodes are full
+    fn build(keys: &mut impl Iterator<Item = K>, len: usize, height: u32, counted: bool) -> Node<K, C> {
+        let mut node = Self::empty(counted);
+        if height == 1 {
+            for (i, key) in keys.take(len).enumerate() {
+                node.keys[i] = key;
+            }
+            node.n = len;
+            node.recount();
+            return node;
+        }
+        let below = Self::capacity(height - 1);
+        let children = (len + 1).div_ceil(below + 1).max(2);
+        let (each, rest) = ((len + 1 - children) / children, (len + 1 - children) % children);
+        node.is_leaf = false;
+        for i in 0..children {
+            let child = Self::build(keys, each + usize::from(i < rest), height - 1, counted);
+            node.children[i] = Some(Box::new(child));
+            if i + 1 < children {
+                node.keys[i] = keys.next().unwrap();
+            }
+        }
+        node.n = children - 1;
+        node.recount();
+        node
+    }
+
+    // a tree of `keys`, which must be sorted, packed as densely as they go
+    // and as low as it can be
+    fn pack(keys: Vec<K>, counted: bool) -> Node<K, C> {
+        let mut height = 1;
+        while Self::capacity(height) < keys.len() {
+            height += 1;
+        }
+        let len = keys.len();
+        Self::build(&mut keys.into_iter(), len, height, counted)
+    }
+
+    // bulk load a tree from sorted keys
+    pub fn from_sorted(keys: Vec<K>) -> Node<K, C> {
+        assert!(keys.windows(2).all(|w| C::compare(&w[0], &w[1]).is_le()), "keys must be sorted");
+        Self::pack(keys, false)
+    }
+
+    // a copy of the tree with its nodes packed as densely as they go. Built
+    // while the tree can still be read, only swapping it in needs the tree
+    // to itself, see `compact`.
+    pub fn compacted(&self) -> Node<K, C> {
+        Self::pack(self.range(..).into_iter().cloned().collect(), self.size.is_some())
+    }
+
+    // rebuild the tree densely packed, like REINDEX
+    pub fn compact(&mut self) {
+        *self = self.compacted();
+    }
+
+    // rebuild only the keys within `range` densely packed, the rest of
+    // the tree stays as it is
+    pub fn compact_range<R: RangeBounds<K>>(&mut self, range: R) {
+        // the first keys of the range and of what follows it
+        let start = self.first_from(range.start_bound()).cloned();
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.first_from(Bound::Excluded(key)).cloned(),
+            Bound::Excluded(key) => self.first_from(Bound::Included(key)).cloned(),
+            Bound::Unbounded => None,
+        };
+        let Some(start) = start else {
+            return;
+        };
+        let mut middle = self.split_off(&start);
+        let mut tail = match &end {
+            Some(end) => middle.split_off(end),
+            None => Box::new(Self::empty(middle.size.is_some())),
+        };
+        let mut middle = middle.compacted();
+        self.append(&mut middle);
+        self.append(&mut tail);
+    }
+}
+
+// The shape of a tree, like pgstatindex reports it for an index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub height: usize,
+    // the number of nodes on every level, the root's first
+    pub level_nodes: Vec<usize>,
+    pub leaf_nodes: usize,
+    pub internal_nodes: usize,
+    pub keys: usize,
+    // leaf_fill[i] is the number of leaves with i keys, internal_fill the
+    // same for internal nodes
+    pub leaf_fill: Vec<usize>,
+    pub internal_fill: Vec<usize>,
+    pub avg_keys: f64,
+    // the percentage of leaf key slots in use, pgstatindex's avg_leaf_density
+    pub avg_leaf_density: f64,
+    // nodes other than the root with fewer keys than a node should have
+    pub underfull_nodes: usize,
+    // the bytes taken by the nodes, not counting what keys own on the heap
+    pub memory: usize,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "height            {}", self.height)?;
+        writeln!(f, "nodes per level   {:?}", self.level_nodes)?;
+        writeln!(f, "internal nodes    {}", self.internal_nodes)?;
+        writeln!(f, "leaf nodes        {}", self.leaf_nodes)?;
+        writeln!(f, "keys              {}", self.keys)?;
+        writeln!(f, "internal fill     {:?}", self.internal_fill)?;
+        writeln!(f, "leaf fill         {:?}", self.leaf_fill)?;
+        writeln!(f, "avg keys          {:.2}", self.avg_keys)?;
+        writeln!(f, "avg leaf density  {:.2}", self.avg_leaf_density)?;
+        writeln!(f, "underfull nodes   {}", self.underfull_nodes)?;
+        write!(f, "memory            {}", self.memory)
+    }
+}
+
+impl<K, C> Node<K, C> {
+    // refresh `size` from the children's, nothing to do unless counting
+    fn recount(&mut self) {
+        if self.size.is_some() {
+            let below: usize = match self.is_leaf {
+                true => 0,
+                false => self.children[..=self.n].iter().map(|c| c.as_ref().unwrap().len()).sum(),
+            };
+            self.size = Some(self.n + below);
+        }
+    }
+
+    // the number of keys in the tree, counted unless the tree keeps counts
+    pub fn len(&self) -> usize {
+        match self.size {
+            Some(size) => size,
+            None if self.is_leaf => self.n,
+            None => self.n + self.children[..=self.n].iter().map(|c| c.as_ref().unwrap().len()).sum::<usize>(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+    fn is_balanced(&self) -> bool {
+        if !self.is_leaf {
+            let mut ph = 0;
+            for bc in self.children.iter().map_while(|child| child.as_ref()) {
+                let h = bc.height();
+                if ph == 0 {
+                    ph = h;
+                }
+                if ph != h {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn height(&self) -> usize {
+        if self.is_leaf {
+            1
+        } else {
+            let mut mh = 1;
+            for bc in self.children.iter().map_while(|child| child.as_ref()) {
+                mh = std::cmp::max(mh, bc.height());
+            }
+            mh + 1
+        }
+    }
+
+    fn have_child(&self) -> bool {
+        self.children[0].is_some()
+    }
+
+    pub fn get_rightmost_node(&self) -> &Node<K, C> {
+        if self.is_leaf {
+            return self;
+        }
+        if let Some(bc) = self.children.iter().rev().flatten().next() {
+            return bc.get_rightmost_node();
+        }
+        unreachable!()
+    }
+}
+
+impl<K: Display, C> Node<K, C> {
+    // see `build_tree`
+    fn fmt_internal(
+        &self,
+        cfg: &mut NodeFormatConfig<K, C>,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        if !self.have_child() {
+            if !cfg.first_child_found {
+                cfg.first_child_found = true;
+                writeln!(f)?;
+            }
+            for _ in 0..cfg.level {
+                write!(f, " ")?;
+            }
+            write!(f, "[")?;
+            for i in 0..self.n {
+                write!(f, "{}", self.keys[i])?;
+                if i < self.n - 1 {
+                    write!(f, ", ")?;
                 }
             }
+            return writeln!(f, "],");
+        }
+
+        if !cfg.first_child_found {
+            write!(f, "{{")?;
+        } else {
+            for _ in 0..cfg.level {
+                write!(f, " ")?;
+            }
+            writeln!(f, "{{")?;
+        }
+
+        for i in 0..self.n {
+            if let Some(bc) = self.children[i].as_ref() {
+                cfg.level += 1;
+                bc.fmt_internal(cfg, f)?;
+                cfg.level -= 1;
+            }
+            for _ in 0..cfg.level {
+                write!(f, " ")?;
+            }
+            writeln!(f, "{},", self.keys[i])?;
         }
+        if let Some(bc) = self.children[self.n].as_ref() {
+            cfg.level += 1;
+            bc.fmt_internal(cfg, f)?;
+            cfg.level -= 1;
+            for _ in 0..cfg.level {
+                write!(f, " ")?;
+            }
+            if std::ptr::eq(bc.as_ref(), cfg.right_most_node) || cfg.level == 0 {
+                write!(f, "}}")?;
+            } else {
+                writeln!(f, "}},")?;
+            }
+        }
+        Ok(())
     }
 }
 
-impl Display for Node {
+impl<K: Display, C> Display for Node<K, C> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut config = NodeFormatConfig {
             level: 0,
@@ -512,12 +1039,287 @@ impl Display for Node {
     }
 }
 
+// escape what a graphviz record label treats as markup
+pub(crate) fn escape_record(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "{}|<>\"\\".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+impl<K: Display, C> Node<K, C> {
+    // number the nodes in preorder into `out`, each with its level, the
+    // root's being 0, and the ids of its children. Returns this node's id.
+    fn preorder<'a>(&'a self, level: usize, out: &mut Vec<(usize, &'a Node<K, C>, Vec<usize>)>) -> usize {
+        let id = out.len();
+        out.push((level, self, Vec::new()));
+        if !self.is_leaf {
+            let children = self.children[..=self.n].iter().map(|c| c.as_ref().unwrap().preorder(level + 1, out)).collect();
+            out[id].2 = children;
+        }
+        id
+    }
+
+    // the tree as a graphviz graph, one record per node with a port between
+    // every two keys that the edge to the child there leaves from:
+    //   dot -Tsvg tree.dot > tree.svg
+    pub fn to_dot(&self) -> String {
+        let mut nodes = Vec::new();
+        self.preorder(0, &mut nodes);
+        let mut out = String::from("digraph btree {\n  node [shape=record];\n");
+        for (id, (_, node, children)) in nodes.iter().enumerate() {
+            let keys = node.keys[..node.n].iter().map(|k| escape_record(&k.to_string()));
+            let label = match node.is_leaf {
+                true => keys.collect::<Vec<_>>().join("|"),
+                false => keys.enumerate().fold("<c0>".to_string(), |label, (i, key)| format!("{}|{}|<c{}>", label, key, i + 1)),
+            };
+            out.push_str(&format!("  n{} [label=\"{}\"];\n", id, label));
+            for (i, child) in children.iter().enumerate() {
+                out.push_str(&format!("  n{}:c{} -> n{};\n", id, i, child));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    // the tree as json, the nodes in preorder with their level, keys, how
+    // full they are and the ids of their children:
+    //   {"height": 2, "nodes": [{"id": 0, "level": 0, "leaf": false,
+    //    "keys": ["11"], "fill": 0.25, "children": [1, 2]}, ...]}
+    // keys are strings as `Display` writes them.
+    pub fn to_json(&self) -> String {
+        let mut nodes = Vec::new();
+        self.preorder(0, &mut nodes);
+        let items: Vec<String> = nodes
+            .iter()
+            .enumerate()
+            .map(|(id, (level, node, children))| {
+                let keys: Vec<String> = node.keys[..node.n].iter().map(|k| format!("\"{}\"", escape_json(&k.to_string()))).collect();
+                let children: Vec<String> = children.iter().map(|c| c.to_string()).collect();
+                format!(
+                    "{{\"id\": {}, \"level\": {}, \"leaf\": {}, \"keys\": [{}], \"fill\": {}, \"children\": [{}]}}",
+                    id,
+                    level,
+                    node.is_leaf,
+                    keys.join(", "),
+                    node.n as f64 / (MAX_CHILDREN - 1) as f64,
+                    children.join(", ")
+                )
+            })
+            .collect();
+        format!("{{\"height\": {}, \"nodes\": [{}]}}", self.height(), items.join(", "))
+    }
+}
+
+// The result of `Node::entry`, like `std::collections::btree_map::Entry`
+// for a tree of keys alone.
+pub enum Entry<'a, K, C = Natural> {
+    Occupied(OccupiedEntry<'a, K, C>),
+    Vacant(VacantEntry<'a, K, C>),
+}
+
+// an equal key is in the tree
+pub struct OccupiedEntry<'a, K, C = Natural> {
+    root: &'a mut Node<K, C>,
+    // the child taken in every node down to the one holding the key, then
+    // the key's position in that node
+    path: Vec<usize>,
+}
+
+// the key is not in the tree
+pub struct VacantEntry<'a, K, C = Natural> {
+    root: &'a mut Node<K, C>,
+    key: K,
+    // the child taken in every node down to the leaf, then the key's
+    // position in the leaf
+    path: Vec<usize>,
+}
+
+impl<'a, K: Clone + Default, C: Comparator<K>> Entry<'a, K, C> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.get(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    // insert the key unless it is there, returns whether it was inserted
+    pub fn or_insert(self) -> bool {
+        match self {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(e) => {
+                e.insert();
+                true
+            }
+        }
+    }
+}
+
+impl<'a, K: Clone + Default, C: Comparator<K>> OccupiedEntry<'a, K, C> {
+    fn node(&self) -> &Node<K, C> {
+        let (last, down) = self.path.split_last().unwrap();
+        let mut node = &*self.root;
+        for &i in down {
+            node = node.children[i].as_ref().unwrap();
+        }
+        debug_assert!(*last < node.n);
+        node
+    }
+
+    // the key in the tree, which may differ from the one looked for in
+    // what its ordering ignores (e.g. a payload)
+    pub fn get(&self) -> &K {
+        &self.node().keys[*self.path.last().unwrap()]
+    }
+
+    // swap the key in the tree for an equal one, returning the old key
+    pub fn replace(&mut self, key: K) -> K {
+        let (last, down) = self.path.split_last().unwrap();
+        let mut node = &mut *self.root;
+        for &i in down {
+            node = node.children[i].as_mut().unwrap();
+        }
+        assert!(C::compare(&node.keys[*last], &key).is_eq(), "the replacement key must equal the old one");
+        std::mem::replace(&mut node.keys[*last], key)
+    }
+
+    // remove the key from the tree, this descends again to rebalance on
+    // the way back up
+    pub fn remove(self) -> K {
+        let key = self.get().clone();
+        self.root.delete(key.clone());
+        key
+    }
+}
+
+impl<'a, K: Clone + Default, C: Comparator<K>> VacantEntry<'a, K, C> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn insert(self) {
+        self.root.insert_along(self.key, &self.path);
+    }
+}
+
+// which key `Cursor::seek` lands on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekMode {
+    GreaterOrEqual,
+    Greater,
+    LessOrEqual,
+    Less,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Position<K> {
+    BeforeFirst,
+    At(K),
+    AfterLast,
+}
+
+// A position in a tree that survives changes to the tree.
+//
+// The cursor holds no reference into the tree, only the last key it
+// returned, and every move searches again from that key. So inserts and
+// deletes between two moves are fine: `next` returns the smallest key
+// greater than the last one, whatever the tree looks like by then, even if
+// the last key itself was deleted. It relies on keys being unique, a cursor
+// steps over the duplicates of the key it is on.
+//
+// Like a SQL cursor it starts before the first key, and running off either
+// end leaves it there, so `prev` after the end returns the last key.
+#[derive(Debug, Clone)]
+pub struct Cursor<K> {
+    pos: Position<K>,
+}
+
+impl<K: Clone + Default> Default for Cursor<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone + Default> Cursor<K> {
+    pub fn new() -> Self {
+        Self {
+            pos: Position::BeforeFirst,
+        }
+    }
+
+    // move to the first key at or after `key` (or the last one at or before
+    // it, depending on `mode`)
+    pub fn seek<'a, C: Comparator<K>>(&mut self, tree: &'a Node<K, C>, key: &K, mode: SeekMode) -> Option<&'a K> {
+        let found = match mode {
+            SeekMode::GreaterOrEqual => tree.first_from(Bound::Included(key)),
+            SeekMode::Greater => tree.first_from(Bound::Excluded(key)),
+            SeekMode::LessOrEqual => tree.last_until(Bound::Included(key)),
+            SeekMode::Less => tree.last_until(Bound::Excluded(key)),
+        };
+        let forward = matches!(mode, SeekMode::GreaterOrEqual | SeekMode::Greater);
+        self.pos = match found {
+            Some(k) => Position::At(k.clone()),
+            None if forward => Position::AfterLast,
+            None => Position::BeforeFirst,
+        };
+        found
+    }
+
+    pub fn next<'a, C: Comparator<K>>(&mut self, tree: &'a Node<K, C>) -> Option<&'a K> {
+        let found = match &self.pos {
+            Position::BeforeFirst => tree.first_from(Bound::Unbounded),
+            Position::At(last) => tree.first_from(Bound::Excluded(last)),
+            Position::AfterLast => None,
+        };
+        self.pos = found.map_or(Position::AfterLast, |k| Position::At(k.clone()));
+        found
+    }
+
+    pub fn prev<'a, C: Comparator<K>>(&mut self, tree: &'a Node<K, C>) -> Option<&'a K> {
+        let found = match &self.pos {
+            Position::BeforeFirst => None,
+            Position::At(last) => tree.last_until(Bound::Excluded(last)),
+            Position::AfterLast => tree.last_until(Bound::Unbounded),
+        };
+        self.pos = found.map_or(Position::BeforeFirst, |k| Position::At(k.clone()));
+        found
+    }
+
+    // the key the cursor is on, `None` off either end or if the key has
+    // been deleted since
+    pub fn current<'a, C: Comparator<K>>(&self, tree: &'a Node<K, C>) -> Option<&'a K> {
+        match &self.pos {
+            Position::At(key) => tree.first_from(Bound::Included(key)).filter(|k| C::compare(k, key).is_eq()),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::btree::index::conformance::rng;
 
     // see page 9 of https://infolab.usc.edu/csci585/Spring2010/den_ar/indexing.pdf
-    // output format:
+    // as `Display` writes it:
     //   {{
     //     [1, 2, 3, 4],
     //    5,
@@ -534,79 +1336,7 @@ mod tests {
     //     [22, 23, 24, 25],
     //    }}
     fn build_tree() -> Node {
-        let mut root = Node::new();
-        root.is_leaf = false;
-        root.keys[0] = 11;
-        root.n += 1;
-
-        let mut leaf1 = Node::new();
-        leaf1.is_leaf = true;
-        leaf1.keys[0] = 1;
-        leaf1.keys[1] = 2;
-        leaf1.keys[2] = 3;
-        leaf1.keys[3] = 4;
-        leaf1.n = 4;
-
-        let mut leaf2 = Node::new();
-        leaf2.is_leaf = true;
-        leaf2.keys[0] = 6;
-        leaf2.keys[1] = 7;
-        leaf2.n = 2;
-
-        let mut leaf3 = Node::new();
-        leaf3.is_leaf = true;
-        leaf3.keys[0] = 9;
-        leaf3.keys[1] = 10;
-        leaf3.n = 2;
-
-        let mut leaf4 = Node::new();
-        leaf4.is_leaf = true;
-        leaf4.keys[0] = 12;
-        leaf4.keys[1] = 13;
-        leaf4.keys[2] = 14;
-        leaf4.keys[3] = 15;
-        leaf4.n = 4;
-
-        let mut leaf5 = Node::new();
-        leaf5.is_leaf = true;
-        leaf5.keys[0] = 17;
-        leaf5.keys[1] = 18;
-        leaf5.keys[2] = 19;
-        leaf5.keys[3] = 20;
-        leaf5.n = 4;
-
-        let mut leaf6 = Node::new();
-        leaf6.is_leaf = true;
-        leaf6.keys[0] = 22;
-        leaf6.keys[1] = 23;
-        leaf6.keys[2] = 24;
-        leaf6.keys[3] = 25;
-        leaf6.n = 4;
-
-        let mut inode1 = Node::new();
-        inode1.is_leaf = false;
-        inode1.keys[0] = 5;
-        inode1.keys[1] = 8;
-        inode1.n = 2;
-
-        let mut inode2 = Node::new();
-        inode2.is_leaf = false;
-        inode2.keys[0] = 16;
-        inode2.keys[1] = 21;
-        inode2.n = 2;
-
-        inode1.children[0] = Some(Box::new(leaf1));
-        inode1.children[1] = Some(Box::new(leaf2));
-        inode1.children[2] = Some(Box::new(leaf3));
-
-        inode2.children[0] = Some(Box::new(leaf4));
-        inode2.children[1] = Some(Box::new(leaf5));
-        inode2.children[2] = Some(Box::new(leaf6));
-
-        root.children[0] = Some(Box::new(inode1));
-        root.children[1] = Some(Box::new(inode2));
-
-        root
+        "{{[1, 2, 3, 4], 5, [6, 7], 8, [9, 10]}, 11, {[12, 13, 14, 15], 16, [17, 18, 19, 20], 21, [22, 23, 24, 25]}}".parse().unwrap()
     }
 
     #[test]
@@ -694,6 +1424,75 @@ mod tests {
         assert_eq!(ans, exp.trim_start());
     }
 
+    #[test]
+    fn test_to_dot() {
+        let root = build_tree();
+        let exp = r#"digraph btree {
+  node [shape=record];
+  n0 [label="<c0>|11|<c1>"];
+  n0:c0 -> n1;
+  n0:c1 -> n5;
+  n1 [label="<c0>|5|<c1>|8|<c2>"];
+  n1:c0 -> n2;
+  n1:c1 -> n3;
+  n1:c2 -> n4;
+  n2 [label="1|2|3|4"];
+  n3 [label="6|7"];
+  n4 [label="9|10"];
+  n5 [label="<c0>|16|<c1>|21|<c2>"];
+  n5:c0 -> n6;
+  n5:c1 -> n7;
+  n5:c2 -> n8;
+  n6 [label="12|13|14|15"];
+  n7 [label="17|18|19|20"];
+  n8 [label="22|23|24|25"];
+}
+"#;
+        assert_eq!(root.to_dot(), exp);
+
+        let mut root = Node::<String>::new();
+        root.insert("a|b".to_string());
+        root.insert("{\"c\"}".to_string());
+        assert!(root.to_dot().contains(r#"n0 [label="a\|b|\{\"c\"\}"];"#));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let root = build_tree();
+        let json = root.to_json();
+        assert!(json.starts_with(r#"{"height": 3, "nodes": [{"id": 0, "level": 0, "leaf": false, "keys": ["11"], "fill": 0.25, "children": [1, 5]}, "#));
+        assert!(json.contains(r#"{"id": 3, "level": 2, "leaf": true, "keys": ["6", "7"], "fill": 0.5, "children": []}"#));
+        assert!(json.ends_with(r#"{"id": 8, "level": 2, "leaf": true, "keys": ["22", "23", "24", "25"], "fill": 1, "children": []}]}"#));
+
+        let mut root = Node::<String>::new();
+        root.insert("say \"hi\"\n".to_string());
+        assert!(root.to_json().contains(r#""keys": ["say \"hi\"\n"]"#));
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = build_tree().stats();
+        assert_eq!(stats.height, 3);
+        assert_eq!(stats.level_nodes, [1, 2, 6]);
+        assert_eq!((stats.internal_nodes, stats.leaf_nodes, stats.keys), (3, 6, 25));
+        assert_eq!(stats.internal_fill, [0, 1, 2, 0, 0]);
+        assert_eq!(stats.leaf_fill, [0, 0, 2, 0, 4]);
+        assert_eq!(stats.avg_keys, 25.0 / 9.0);
+        assert_eq!(stats.avg_leaf_density, 100.0 * 20.0 / 24.0);
+        assert_eq!(stats.underfull_nodes, 0);
+        assert_eq!(stats.memory, 9 * std::mem::size_of::<Node>());
+
+        // the root may hold a single key, other nodes may not
+        let mut root = build_tree();
+        root.children[0].as_mut().unwrap().children[2].as_mut().unwrap().n = 1;
+        let stats = root.stats();
+        assert_eq!((stats.underfull_nodes, stats.leaf_fill[1]), (1, 1));
+
+        let stats = Node::<usize>::new().stats();
+        assert_eq!((stats.height, stats.leaf_nodes, stats.keys), (1, 1, 0));
+        assert_eq!(stats.avg_leaf_density, 0.0);
+    }
+
     #[test]
     fn test_delete_from_leaf() {
         let mut root = Node::new_boxed();
@@ -960,4 +1759,331 @@ mod tests {
 "#;
         assert_eq!(ans, exp.trim());
     }
+
+    // every leaf is at the same depth and no node has too few or too many keys
+    fn check(node: &Node, depth: usize, leaf_depth: &mut Option<usize>, is_root: bool) {
+        assert!(node.n < MAX_CHILDREN);
+        assert!(is_root || node.n >= Node::<usize>::MIN_KEYS);
+        assert!(node.keys[..node.n].windows(2).all(|w| w[0] < w[1]));
+        if let Some(size) = node.size {
+            let below: usize = match node.is_leaf {
+                true => 0,
+                false => node.children[..=node.n].iter().map(|c| c.as_ref().unwrap().len()).sum(),
+            };
+            assert_eq!(size, node.n + below);
+        }
+        if node.is_leaf {
+            assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+            return;
+        }
+        for child in &node.children[..=node.n] {
+            check(child.as_ref().unwrap(), depth + 1, leaf_depth, false);
+        }
+        assert!(node.children[node.n + 1..].iter().all(|c| c.is_none()));
+    }
+
+    #[test]
+    fn test_delete_random() {
+        let mut rng = rng(0x2545f491);
+        let mut next = move || rng() as usize % 300;
+        let mut root = Node::new_boxed();
+        let mut expected = std::collections::BTreeSet::new();
+        for _ in 0..3000 {
+            let key = next();
+            if expected.contains(&key) {
+                root.delete(key);
+                expected.remove(&key);
+            } else {
+                root.insert(key);
+                expected.insert(key);
+            }
+            check(&root, 0, &mut None, true);
+        }
+        let keys: Vec<usize> = root.range(..).into_iter().copied().collect();
+        assert_eq!(keys, expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_compact() {
+        for counted in [false, true] {
+            let mut root = match counted {
+                true => Node::new_counted(),
+                false => Node::new(),
+            };
+            for key in 0..2000 {
+                root.insert(key);
+            }
+            // every fifth key left, in nodes that hold few
+            for key in 0..2000 {
+                if key % 5 != 0 {
+                    root.delete(key);
+                }
+            }
+            let sparse = root.stats();
+            let keys: Vec<usize> = root.range(..).into_iter().copied().collect();
+
+            let compacted = root.compacted();
+            check(&compacted, 0, &mut None, true);
+            let stats = compacted.stats();
+            assert!(stats.height < sparse.height);
+            assert!(stats.avg_leaf_density > 90.0, "{}", stats);
+            assert!(stats.internal_nodes + stats.leaf_nodes < (sparse.internal_nodes + sparse.leaf_nodes) * 3 / 4);
+            assert_eq!(compacted.range(..).into_iter().copied().collect::<Vec<_>>(), keys);
+
+            // every size packs into a valid tree
+            for len in 0..200 {
+                let mut root = Node::new();
+                for key in 0..len {
+                    root.insert(key);
+                }
+                root.compact();
+                check(&root, 0, &mut None, true);
+                assert_eq!(root.len(), len);
+            }
+
+            // packing a range leaves the rest alone
+            let before = root.stats();
+            root.compact_range(100..=1200);
+            check(&root, 0, &mut None, true);
+            assert_eq!(root.range(..).into_iter().copied().collect::<Vec<_>>(), keys);
+            assert!(root.stats().leaf_nodes < before.leaf_nodes);
+            root.compact_range(5000..);
+            root.compact_range((Bound::Excluded(0), Bound::Excluded(5)));
+            check(&root, 0, &mut None, true);
+            assert_eq!(root.range(..).into_iter().copied().collect::<Vec<_>>(), keys);
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        let root = build_tree();
+        let keys: Vec<usize> = root.range(5..=12).into_iter().copied().collect();
+        assert_eq!(keys, [5, 6, 7, 8, 9, 10, 11, 12]);
+        let keys: Vec<usize> = root.range(..3).into_iter().copied().collect();
+        assert_eq!(keys, [1, 2]);
+        let keys: Vec<usize> = root.range(21..).into_iter().copied().collect();
+        assert_eq!(keys, [21, 22, 23, 24, 25]);
+        assert!(root.range(26..).is_empty());
+        assert_eq!(root.range(..).len(), 25);
+    }
+
+    #[test]
+    fn test_cursor() {
+        let root = build_tree();
+        let mut cursor = Cursor::new();
+        assert_eq!(cursor.current(&root), None);
+        assert_eq!(cursor.prev(&root), None);
+        assert_eq!(cursor.next(&root), Some(&1));
+        assert_eq!(cursor.next(&root), Some(&2));
+        assert_eq!(cursor.prev(&root), Some(&1));
+        assert_eq!(cursor.prev(&root), None);
+        assert_eq!(cursor.next(&root), Some(&1));
+
+        assert_eq!(cursor.seek(&root, &12, SeekMode::GreaterOrEqual), Some(&12));
+        assert_eq!(cursor.current(&root), Some(&12));
+        assert_eq!(cursor.seek(&root, &12, SeekMode::Greater), Some(&13));
+        assert_eq!(cursor.seek(&root, &12, SeekMode::Less), Some(&11));
+        assert_eq!(cursor.seek(&root, &12, SeekMode::LessOrEqual), Some(&12));
+        assert_eq!(cursor.seek(&root, &25, SeekMode::Greater), None);
+        assert_eq!(cursor.prev(&root), Some(&25));
+        assert_eq!(cursor.seek(&root, &1, SeekMode::Less), None);
+        assert_eq!(cursor.next(&root), Some(&1));
+
+        let mut keys = Vec::new();
+        cursor.seek(&root, &20, SeekMode::Greater);
+        while let Some(&key) = cursor.next(&root) {
+            keys.push(key);
+        }
+        assert_eq!(keys, [22, 23, 24, 25]);
+        keys.clear();
+        while let Some(&key) = cursor.prev(&root) {
+            keys.push(key);
+        }
+        assert_eq!(keys.len(), 25);
+        assert_eq!(keys[..3], [25, 24, 23]);
+    }
+
+    #[test]
+    fn test_cursor_reposition() {
+        let mut root = Node::new_boxed();
+        for key in (0..100).step_by(10) {
+            root.insert(key);
+        }
+        let mut cursor = Cursor::new();
+        assert_eq!(cursor.seek(&root, &35, SeekMode::GreaterOrEqual), Some(&40));
+
+        // the key the cursor is on goes away, it moves on from where it was
+        root.delete(40);
+        assert_eq!(cursor.current(&root), None);
+        root.insert(45);
+        root.delete(50);
+        assert_eq!(cursor.next(&root), Some(&45));
+        assert_eq!(cursor.next(&root), Some(&60));
+
+        // rebuild the tree under the cursor
+        for key in 0..100 {
+            if key % 10 != 0 && key != 45 {
+                root.insert(key);
+            }
+        }
+        assert_eq!(cursor.prev(&root), Some(&59));
+        assert_eq!(cursor.current(&root), Some(&59));
+        for key in 0..59 {
+            root.delete(key);
+        }
+        assert_eq!(cursor.prev(&root), None);
+        assert_eq!(cursor.next(&root), Some(&59));
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut root = build_tree();
+        match root.entry(12) {
+            Entry::Occupied(mut e) => {
+                assert_eq!(*e.get(), 12);
+                assert_eq!(e.replace(12), 12);
+            }
+            Entry::Vacant(_) => panic!("12 is in the tree"),
+        }
+        match root.entry(26) {
+            Entry::Vacant(e) => {
+                assert_eq!(*e.key(), 26);
+                e.insert();
+            }
+            Entry::Occupied(_) => panic!("26 is not in the tree"),
+        }
+        assert!(!root.entry(26).or_insert());
+        assert!(root.entry(0).or_insert());
+        match root.entry(11) {
+            Entry::Occupied(e) => assert_eq!(e.remove(), 11),
+            Entry::Vacant(_) => panic!("11 is in the tree"),
+        }
+        let keys: Vec<usize> = root.range(..).into_iter().copied().collect();
+        let mut exp: Vec<usize> = (0..=26).collect();
+        exp.retain(|&k| k != 11);
+        assert_eq!(keys, exp);
+        check(&root, 0, &mut None, true);
+    }
+
+    #[test]
+    fn test_entry_random() {
+        let mut rng = rng(0x9e3779b9);
+        let mut next = move || rng() as usize % 500;
+        let mut root = Node::new_boxed();
+        let mut expected = std::collections::BTreeSet::new();
+        for _ in 0..5000 {
+            let key = next();
+            match root.entry(key) {
+                Entry::Occupied(e) => {
+                    assert!(expected.remove(&key));
+                    e.remove();
+                }
+                Entry::Vacant(e) => {
+                    assert!(expected.insert(key));
+                    e.insert();
+                }
+            }
+            check(&root, 0, &mut None, true);
+        }
+        let keys: Vec<usize> = root.range(..).into_iter().copied().collect();
+        assert_eq!(keys, expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_order_statistics() {
+        let mut rng = rng(0x51ed270b);
+        let mut next = move || rng() as usize % 400;
+        let mut root = Node::new_boxed_counted();
+        let mut expected = std::collections::BTreeSet::new();
+        for step in 0..4000 {
+            let key = next();
+            if expected.contains(&key) {
+                root.delete(key);
+                expected.remove(&key);
+            } else if step % 2 == 0 {
+                root.insert(key);
+                expected.insert(key);
+            } else if let Entry::Vacant(e) = root.entry(key) {
+                e.insert();
+                expected.insert(key);
+            }
+            check(&root, 0, &mut None, true);
+            assert_eq!(root.size, Some(expected.len()));
+        }
+
+        let keys: Vec<usize> = expected.iter().copied().collect();
+        for (k, key) in keys.iter().enumerate() {
+            assert_eq!(root.select(k), Some(key));
+            assert_eq!(root.rank(key), k);
+        }
+        assert_eq!(root.select(keys.len()), None);
+        for key in 0..400 {
+            assert_eq!(root.rank(&key), expected.range(..key).count());
+        }
+        assert_eq!(root.count_range(100..200), expected.range(100..200).count());
+        assert_eq!(root.count_range(100..=200), expected.range(100..=200).count());
+        assert_eq!(root.count_range((Bound::Excluded(100), Bound::Unbounded)), expected.range(101..).count());
+        assert_eq!(root.count_range(..), expected.len());
+        assert_eq!(root.count_range((Bound::Included(300), Bound::Excluded(100))), 0);
+
+        // trees without counts answer the same, just slower
+        let plain = build_tree();
+        assert_eq!(plain.len(), 25);
+        assert_eq!(plain.rank(&12), 11);
+        assert_eq!(plain.select(11), Some(&12));
+        assert_eq!(plain.count_range(5..=12), 8);
+    }
+
+    #[test]
+    fn test_split_off_append() {
+        let mut rng = rng(0x1b873593);
+        let mut next = move || rng() as usize;
+        for round in 0..60 {
+            let len = next() % 300;
+            let mut root = match round % 2 {
+                0 => Node::new_boxed(),
+                _ => Node::new_boxed_counted(),
+            };
+            let mut expected = std::collections::BTreeSet::new();
+            for _ in 0..len {
+                let key = next() % 1000;
+                if expected.insert(key) {
+                    root.insert(key);
+                }
+            }
+            let at = next() % 1100;
+            let mut high = root.split_off(&at);
+            check(&root, 0, &mut None, true);
+            check(&high, 0, &mut None, true);
+            let keys: Vec<usize> = root.range(..).into_iter().copied().collect();
+            assert_eq!(keys, expected.range(..at).copied().collect::<Vec<_>>());
+            let keys: Vec<usize> = high.range(..).into_iter().copied().collect();
+            assert_eq!(keys, expected.range(at..).copied().collect::<Vec<_>>());
+            assert_eq!(root.len() + high.len(), expected.len());
+
+            root.append(&mut high);
+            check(&root, 0, &mut None, true);
+            assert!(high.is_empty());
+            let keys: Vec<usize> = root.range(..).into_iter().copied().collect();
+            assert_eq!(keys, expected.iter().copied().collect::<Vec<_>>());
+        }
+
+        // trees of very different heights
+        let mut low = Node::new_boxed_counted();
+        let mut high = Node::new_boxed_counted();
+        low.insert(0);
+        for key in 1..500 {
+            high.insert(key);
+        }
+        low.append(&mut high);
+        check(&low, 0, &mut None, true);
+        assert_eq!(low.size, Some(500));
+        assert_eq!(low.select(250), Some(&250));
+        let mut rest = low.split_off(&499);
+        check(&low, 0, &mut None, true);
+        assert_eq!(rest.range(..), [&499]);
+        rest.append(&mut Node::new());
+        low.append(&mut rest);
+        assert_eq!(low.size, Some(500));
+    }
 }
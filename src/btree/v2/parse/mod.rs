@@ -0,0 +1,206 @@
+#![allow(dead_code)]
+
+// The reverse of `Display for Node`: a tree from the same notation,
+//
+//   {[1, 2], 3, [4, 5, 6]}
+//
+// a leaf's keys in brackets, an inner node's children and the keys between
+// them in braces. Whitespace and trailing commas don't matter, so whatever
+// `Display` writes parses back. A key is anything its `FromStr` accepts,
+// up to the next `,`, `]` or `}` outside of parentheses.
+//
+// The tree is checked the way the tests check trees: no node holds too many
+// keys or, but for the root, too few, keys are in order and within the
+// separators above them, and all leaves are at the same depth.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use super::{Node, MAX_CHILDREN};
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at offset {}", message, self.pos)
+    }
+
+    // the next character that is not whitespace
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+        self.input[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected '{}'", c))),
+        }
+    }
+
+    fn key<K: FromStr>(&mut self) -> Result<K, String> {
+        self.peek();
+        let start = self.pos;
+        let mut depth = 0;
+        for c in self.input[start..].chars() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ',' | ']' | '}' if depth == 0 => break,
+                '[' | '{' if depth == 0 => return Err(self.error("expected a key")),
+                _ => (),
+            }
+            self.pos += c.len_utf8();
+        }
+        let text = self.input[start..self.pos].trim_end();
+        if text.is_empty() {
+            return Err(self.error("expected a key"));
+        }
+        text.parse().map_err(|_| format!("invalid key \"{}\" at offset {}", text, start))
+    }
+
+    fn node<K: FromStr + Ord + Clone + Default>(&mut self) -> Result<Node<K>, String> {
+        let mut keys = Vec::new();
+        let mut children = Vec::new();
+        if self.eat('[') {
+            while !self.eat(']') {
+                keys.push(self.key()?);
+                if !self.eat(',') {
+                    self.expect(']')?;
+                    break;
+                }
+            }
+        } else if self.eat('{') {
+            children.push(self.node()?);
+            // `, key, child` until the closing brace, maybe after a comma
+            while !self.eat('}') {
+                self.expect(',')?;
+                if self.eat('}') {
+                    break;
+                }
+                keys.push(self.key()?);
+                self.expect(',')?;
+                children.push(self.node()?);
+            }
+        } else {
+            return Err(self.error("expected '[' or '{'"));
+        }
+        if keys.len() >= MAX_CHILDREN {
+            return Err(self.error(&format!("a node of {} keys, at most {} fit", keys.len(), MAX_CHILDREN - 1)));
+        }
+        let mut node = Node::new();
+        node.n = keys.len();
+        node.is_leaf = children.is_empty();
+        for (i, key) in keys.into_iter().enumerate() {
+            node.keys[i] = key;
+        }
+        for (i, child) in children.into_iter().enumerate() {
+            node.children[i] = Some(Box::new(child));
+        }
+        Ok(node)
+    }
+}
+
+// the keys of a node for an error message
+fn describe<K: Display>(node: &Node<K>) -> String {
+    let keys: Vec<String> = node.keys[..node.n].iter().map(|k| k.to_string()).collect();
+    format!("the node with keys [{}]", keys.join(", "))
+}
+
+// keys may repeat, so a key may equal the separators around it
+fn validate<K: Ord + Clone + Default + Display>(node: &Node<K>, low: Option<&K>, high: Option<&K>, depth: usize, leaf_depth: &mut Option<usize>) -> Result<(), String> {
+    let keys = &node.keys[..node.n];
+    if depth > 0 && node.n < Node::<K>::MIN_KEYS {
+        return Err(format!("{} has fewer than {} keys", describe(node), Node::<K>::MIN_KEYS));
+    }
+    if !node.is_leaf && node.n == 0 {
+        return Err("an inner node without keys".to_string());
+    }
+    if keys.windows(2).any(|w| w[0] > w[1]) {
+        return Err(format!("{} is out of order", describe(node)));
+    }
+    if keys.iter().any(|k| low.is_some_and(|l| k < l) || high.is_some_and(|h| k > h)) {
+        return Err(format!("{} is out of the range of its parent", describe(node)));
+    }
+    if node.is_leaf {
+        return match *leaf_depth.get_or_insert(depth) == depth {
+            true => Ok(()),
+            false => Err(format!("{} is a leaf at depth {} but another is at {}", describe(node), depth, leaf_depth.unwrap())),
+        };
+    }
+    for (i, child) in node.children[..=node.n].iter().enumerate() {
+        let low = if i == 0 { low } else { Some(&keys[i - 1]) };
+        let high = if i == node.n { high } else { Some(&keys[i]) };
+        validate(child.as_ref().unwrap(), low, high, depth + 1, leaf_depth)?;
+    }
+    Ok(())
+}
+
+impl<K: FromStr + Ord + Clone + Default + Display> FromStr for Node<K> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parser = Parser { input: s, pos: 0 };
+        let node = parser.node()?;
+        parser.eat(',');
+        if parser.peek().is_some() {
+            return Err(parser.error("expected the end of the tree"));
+        }
+        validate(&node, None, None, 0, &mut None)?;
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let root: Node = "{[1, 2], 3, [4, 5, 6]}".parse().unwrap();
+        assert_eq!((root.n, root.keys[0], root.is_leaf), (1, 3, false));
+        let right = root.children[1].as_ref().unwrap();
+        assert_eq!((right.n, &right.keys[..3], right.is_leaf), (3, &[4, 5, 6][..], true));
+        assert!(root.children[2].is_none());
+
+        let root: Node = " [ ] ".parse().unwrap();
+        assert!(root.is_leaf && root.n == 0);
+
+        // whatever `Display` writes parses back
+        let mut root = Node::new();
+        for key in [50, 10, 30, 20, 40, 60, 70, 5, 15, 25, 35, 45, 55, 65, 75, 80] {
+            root.insert(key);
+        }
+        let text = root.to_string();
+        assert_eq!(text.parse::<Node>().unwrap().to_string(), text);
+
+        let root: Node<String> = "{[ab, b], c, [c, d]}".parse().unwrap();
+        assert_eq!(root.children[1].as_ref().unwrap().keys[1], "d");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |s: &str| s.parse::<Node>().unwrap_err();
+        assert_eq!(error("[1, 2"), "expected ']' at offset 5");
+        assert_eq!(error("{[1, 2] 3}"), "expected ',' at offset 8");
+        assert_eq!(error("[1, x]"), "invalid key \"x\" at offset 4");
+        assert_eq!(error("[1, 2] [3]"), "expected the end of the tree at offset 7");
+        assert_eq!(error("[1, 2, 3, 4, 5]"), "a node of 5 keys, at most 4 fit at offset 15");
+        assert_eq!(error("{[1, 2], 3, [4]}"), "the node with keys [4] has fewer than 2 keys");
+        assert_eq!(error("[2, 1]"), "the node with keys [2, 1] is out of order");
+        assert_eq!(error("{[1, 4], 3, [4, 5]}"), "the node with keys [1, 4] is out of the range of its parent");
+        assert_eq!(error("{[1, 2], 3, {[4, 5], 6, [7, 8], 9, [10, 11]}}"), "the node with keys [4, 5] is a leaf at depth 2 but another is at 1");
+    }
+}
@@ -0,0 +1,152 @@
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+
+use crate::btree::key::SortOrder;
+use crate::error::{Error, Result};
+use crate::sql::ast::{ColumnDef, CreateIndex, CreateTable};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexMethod {
+    Btree,
+    Hash,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexInfo {
+    pub name: String,
+    // (column number, order) of every index column
+    pub columns: Vec<(usize, SortOrder)>,
+    pub unique: bool,
+    pub method: IndexMethod,
+    pub stats: IndexStats,
+}
+
+// Per index statistics gathered by ANALYZE, the shape of its tree. A hash
+// index is one level of bucket pages, overflow pages included.
+// `height` is 0 when the index has not been analyzed yet.
+#[derive(Debug, Clone, Default)]
+pub struct IndexStats {
+    pub height: f64,
+    pub leaf_pages: f64,
+    pub keys: f64,
+}
+
+// Per column statistics gathered by ANALYZE.
+// `ndistinct` is 0 when the column has not been analyzed yet.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnStats {
+    pub ndistinct: f64,
+    pub null_frac: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TableStats {
+    pub rows: f64,
+    pub columns: Vec<ColumnStats>,
+}
+
+#[derive(Debug)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+    pub indexes: Vec<IndexInfo>,
+    pub stats: TableStats,
+}
+
+impl Table {
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == name)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Catalog {
+    tables: BTreeMap<String, Table>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_table(&mut self, stmt: &CreateTable) -> Result<()> {
+        if self.tables.contains_key(&stmt.name) {
+            return Err(Error::duplicate_table(&stmt.name));
+        }
+        for (i, column) in stmt.columns.iter().enumerate() {
+            if stmt.columns[..i].iter().any(|c| c.name == column.name) {
+                return Err(Error::new(
+                    "42701",
+                    format!("column \"{}\" specified more than once", column.name),
+                ));
+            }
+        }
+        let table = Table {
+            name: stmt.name.clone(),
+            columns: stmt.columns.clone(),
+            indexes: Vec::new(),
+            stats: TableStats {
+                rows: 0.0,
+                columns: vec![ColumnStats::default(); stmt.columns.len()],
+            },
+        };
+        self.tables.insert(stmt.name.clone(), table);
+        Ok(())
+    }
+
+    // register the index and return its position in `Table::indexes`
+    pub fn create_index(&mut self, stmt: &CreateIndex) -> Result<usize> {
+        if self.tables.values().any(|t| t.indexes.iter().any(|i| i.name == stmt.name)) {
+            return Err(Error::duplicate_table(&stmt.name));
+        }
+        let method = match stmt.using.as_deref() {
+            None | Some("btree") => IndexMethod::Btree,
+            Some("hash") => IndexMethod::Hash,
+            Some(other) => {
+                return Err(Error::new("42704", format!("access method \"{}\" does not exist", other)))
+            }
+        };
+        // a hash index finds the rows equal to one value
+        if method == IndexMethod::Hash {
+            let unsupported = if stmt.unique {
+                Some("unique indexes")
+            } else if stmt.columns.len() > 1 {
+                Some("multicolumn indexes")
+            } else if stmt.columns.iter().any(|(_, order)| *order == SortOrder::Desc) {
+                Some("ASC/DESC options")
+            } else {
+                None
+            };
+            if let Some(what) = unsupported {
+                return Err(Error::not_supported(format!("access method \"hash\" does not support {}", what)));
+            }
+        }
+        let table = self.table_mut(&stmt.table)?;
+        let mut columns = Vec::new();
+        for (name, order) in &stmt.columns {
+            let i = table.column_index(name).ok_or_else(|| Error::undefined_column(name))?;
+            columns.push((i, *order));
+        }
+        table.indexes.push(IndexInfo {
+            name: stmt.name.clone(),
+            columns,
+            unique: stmt.unique,
+            method,
+            stats: IndexStats::default(),
+        });
+        Ok(table.indexes.len() - 1)
+    }
+
+    pub fn table(&self, name: &str) -> Result<&Table> {
+        self.tables.get(name).ok_or_else(|| Error::undefined_table(name))
+    }
+
+    pub fn table_mut(&mut self, name: &str) -> Result<&mut Table> {
+        self.tables.get_mut(name).ok_or_else(|| Error::undefined_table(name))
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &Table> {
+        self.tables.values()
+    }
+}
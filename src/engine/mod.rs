@@ -0,0 +1,478 @@
+#![allow(dead_code)]
+
+use crate::btree::key::Datum;
+use crate::catalog::Catalog;
+use crate::error::{Error, Result};
+use crate::executor;
+use crate::planner;
+use crate::sql::ast::*;
+use crate::sql::parser::parse;
+use crate::storage::{Rebuilt, Row, Storage};
+
+// the outcome of one statement, `tag` is the command tag PostgreSQL
+// reports, e.g. "INSERT 0 1"
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<(String, DataType)>,
+    pub rows: Vec<Row>,
+    pub tag: String,
+}
+
+impl QueryResult {
+    fn command(tag: impl Into<String>) -> Self {
+        Self {
+            columns: Vec::new(),
+            rows: Vec::new(),
+            tag: tag.into(),
+        }
+    }
+}
+
+// The catalog and the table data of one database.
+#[derive(Debug, Default)]
+pub struct Engine {
+    pub catalog: Catalog,
+    pub storage: Storage,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // parse and execute every statement of `sql`, stopping at the first error
+    pub fn run(&mut self, sql: &str) -> Result<Vec<QueryResult>> {
+        let mut results = Vec::new();
+        for stmt in parse(sql)? {
+            results.push(self.execute(&stmt)?);
+        }
+        Ok(results)
+    }
+
+    pub fn execute(&mut self, stmt: &Statement) -> Result<QueryResult> {
+        match stmt {
+            Statement::CreateTable(create) => {
+                self.catalog.create_table(create)?;
+                self.storage.create_table(&create.name);
+                Ok(QueryResult::command("CREATE TABLE"))
+            }
+            Statement::CreateIndex(create) => {
+                let i = self.catalog.create_index(create)?;
+                let info = self.catalog.table(&create.table)?.indexes[i].clone();
+                if let Err(e) = self.storage.table_mut(&create.table)?.create_index(&info) {
+                    self.catalog.table_mut(&create.table)?.indexes.pop();
+                    return Err(e);
+                }
+                Ok(QueryResult::command("CREATE INDEX"))
+            }
+            Statement::Insert(insert) => self.insert(insert),
+            Statement::Delete(delete) => self.delete(delete),
+            Statement::Select(_) | Statement::Explain(_) => self.query(stmt),
+            Statement::Analyze(table) => {
+                let names: Vec<String> = match table {
+                    Some(name) => vec![self.catalog.table(name)?.name.clone()],
+                    None => self.catalog.tables().map(|t| t.name.clone()).collect(),
+                };
+                for name in names {
+                    let table = self.catalog.table_mut(&name)?;
+                    let data = self.storage.table(&name)?;
+                    table.stats = data.analyze(table.columns.len());
+                    for index in &mut table.indexes {
+                        if let Some(tree) = data.indexes.iter().find(|i| i.name == index.name) {
+                            index.stats = tree.analyze();
+                        }
+                    }
+                }
+                Ok(QueryResult::command("ANALYZE"))
+            }
+            Statement::Reindex(target) => {
+                let (table, rebuilt) = self.reindex(target)?;
+                self.swap_indexes(&table, rebuilt)
+            }
+        }
+    }
+
+    // the statements `query` runs
+    pub fn reads_only(stmt: &Statement) -> bool {
+        matches!(stmt, Statement::Select(_) | Statement::Explain(_))
+    }
+
+    // run a statement that only reads, see `reads_only`, next to others
+    // that do
+    pub fn query(&self, stmt: &Statement) -> Result<QueryResult> {
+        match stmt {
+            Statement::Select(select) => {
+                let plan = planner::plan_select(&self.catalog, select)?;
+                let rows = executor::collect(&plan, &self.storage)?;
+                Ok(QueryResult {
+                    columns: plan.columns.iter().map(|c| (c.name.clone(), c.ty)).collect(),
+                    tag: format!("SELECT {}", rows.len()),
+                    rows,
+                })
+            }
+            Statement::Explain(stmt) => {
+                let rows: Vec<Row> = planner::explain(&self.catalog, stmt)?
+                    .into_iter()
+                    .map(|line| vec![Datum::Text(line)])
+                    .collect();
+                Ok(QueryResult {
+                    columns: vec![("QUERY PLAN".to_string(), DataType::Text)],
+                    rows,
+                    tag: "EXPLAIN".to_string(),
+                })
+            }
+            _ => unreachable!("{:?} changes the database", stmt),
+        }
+    }
+
+    // the first half of REINDEX: the table and its indexes rebuilt, built
+    // while the engine can still be read
+    pub fn reindex(&self, target: &Reindex) -> Result<(String, Rebuilt)> {
+        let (table, index) = match target {
+            Reindex::Table(name) => (self.catalog.table(name)?.name.clone(), None),
+            Reindex::Index(name) => {
+                let table = self.catalog.tables().find(|t| t.indexes.iter().any(|i| i.name == *name));
+                match table {
+                    Some(table) => (table.name.clone(), Some(name.as_str())),
+                    None => return Err(Error::new("42704", format!("index \"{}\" does not exist", name))),
+                }
+            }
+        };
+        let rebuilt = self.storage.table(&table)?.rebuild(index);
+        Ok((table, rebuilt))
+    }
+
+    // the second half, which takes the engine to itself only to swap the
+    // indexes in
+    pub fn swap_indexes(&mut self, table: &str, rebuilt: Rebuilt) -> Result<QueryResult> {
+        self.storage.table_mut(table)?.swap(rebuilt);
+        Ok(QueryResult::command("REINDEX"))
+    }
+
+    // the result columns of `stmt` without running it, parameters are
+    // taken to be NULL
+    pub fn describe(&self, stmt: &Statement) -> Result<Vec<(String, DataType)>> {
+        match stmt {
+            Statement::Select(select) => {
+                let mut stmt = Statement::Select(select.clone());
+                stmt.visit_exprs_mut(&mut |expr| {
+                    if let Expr::Param(_) = expr {
+                        *expr = Expr::Literal(Datum::Null);
+                    }
+                });
+                let Statement::Select(select) = stmt else {
+                    unreachable!()
+                };
+                let plan = planner::plan_select(&self.catalog, &select)?;
+                Ok(plan.columns.iter().map(|c| (c.name.clone(), c.ty)).collect())
+            }
+            Statement::Explain(_) => Ok(vec![("QUERY PLAN".to_string(), DataType::Text)]),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn insert(&mut self, insert: &Insert) -> Result<QueryResult> {
+        let table = self.catalog.table(&insert.table)?;
+        // the column number of every value of a VALUES row
+        let targets: Vec<usize> = match &insert.columns {
+            Some(names) => {
+                let mut targets = Vec::new();
+                for name in names {
+                    let c = table.column_index(name).ok_or_else(|| Error::undefined_column(name))?;
+                    if targets.contains(&c) {
+                        return Err(Error::new("42701", format!("column \"{}\" specified more than once", name)));
+                    }
+                    targets.push(c);
+                }
+                targets
+            }
+            None => (0..table.columns.len()).collect(),
+        };
+        let mut rows = Vec::new();
+        for values in &insert.rows {
+            if values.len() > targets.len() {
+                return Err(Error::syntax("INSERT has more expressions than target columns"));
+            }
+            if values.len() < targets.len() {
+                return Err(Error::syntax("INSERT has more target columns than expressions"));
+            }
+            let mut row = vec![Datum::Null; table.columns.len()];
+            for (value, &c) in values.iter().zip(&targets) {
+                let expr = planner::bind_expr(&self.catalog, None, value)?;
+                let datum = executor::eval(&expr, &Vec::new())?;
+                let column = &table.columns[c];
+                let ok = matches!(
+                    (&datum, column.ty),
+                    (Datum::Null, _) | (Datum::Int(_), DataType::Int) | (Datum::Text(_), DataType::Text) | (Datum::Bool(_), DataType::Bool)
+                );
+                if !ok {
+                    return Err(Error::datatype_mismatch(format!(
+                        "column \"{}\" is of type {} but expression is of type {}",
+                        column.name,
+                        column.ty,
+                        planner::type_of(&expr, &[])
+                    )));
+                }
+                row[c] = datum;
+            }
+            rows.push(row);
+        }
+        let count = rows.len();
+        let data = self.storage.table_mut(&insert.table)?;
+        data.insert_all(rows)?;
+        let rows = data.len() as f64;
+        self.catalog.table_mut(&insert.table)?.stats.rows = rows;
+        Ok(QueryResult::command(format!("INSERT 0 {}", count)))
+    }
+
+    fn delete(&mut self, delete: &Delete) -> Result<QueryResult> {
+        let predicate = match &delete.selection {
+            Some(selection) => Some(planner::bind_expr(&self.catalog, Some(&delete.table), selection)?),
+            None => None,
+        };
+        let data = self.storage.table_mut(&delete.table)?;
+        let mut victims = Vec::new();
+        for (id, row) in data.iter() {
+            if executor::qualifies(predicate.as_ref(), row)? {
+                victims.push(id);
+            }
+        }
+        for &id in &victims {
+            data.delete(id);
+        }
+        let rows = data.len() as f64;
+        self.catalog.table_mut(&delete.table)?.stats.rows = rows;
+        Ok(QueryResult::command(format!("DELETE {}", victims.len())))
+    }
+}
+
+// replace every `$n` of `stmt` by `params[n - 1]`
+pub fn bind_params(stmt: &mut Statement, params: &[Datum]) -> Result<()> {
+    let mut missing = None;
+    stmt.visit_exprs_mut(&mut |expr| {
+        if let Expr::Param(n) = expr {
+            match params.get(*n - 1) {
+                Some(datum) => *expr = Expr::Literal(datum.clone()),
+                None => missing = Some(*n),
+            }
+        }
+    });
+    match missing {
+        Some(n) => Err(Error::new("42P02", format!("there is no parameter ${}", n))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::key::KeyRange;
+
+    fn engine() -> Engine {
+        let mut engine = Engine::new();
+        engine
+            .run(
+                "create table users (id int, name text, age int);
+                 create table orders (id int, user_id int, total int);
+                 insert into users values (1, 'ann', 30), (2, 'bob', 25), (3, 'cid', null), (4, 'dan', 25);
+                 insert into orders values (10, 1, 100), (11, 1, 50), (12, 2, 70), (13, 9, 5);",
+            )
+            .unwrap();
+        engine
+    }
+
+    fn query(engine: &mut Engine, sql: &str) -> Vec<String> {
+        let result = engine.run(sql).unwrap().pop().unwrap();
+        result
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(","))
+            .collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let mut engine = engine();
+        assert_eq!(
+            query(&mut engine, "select name, age + 1 from users where age >= 25 order by age desc, name"),
+            ["'ann',31", "'bob',26", "'dan',26"]
+        );
+        assert_eq!(query(&mut engine, "select id from users order by age limit 2"), ["2", "4"]);
+        // NULLs sort last ascending and first descending
+        assert_eq!(query(&mut engine, "select id from users order by age desc, id"), ["3", "1", "2", "4"]);
+        assert_eq!(query(&mut engine, "select 1 + 2, null is null"), ["3,true"]);
+    }
+
+    #[test]
+    fn test_join_and_aggregate() {
+        let mut engine = engine();
+        assert_eq!(
+            query(
+                &mut engine,
+                "select u.name, count(*), sum(o.total) from users u join orders o on u.id = o.user_id
+                 group by u.name order by 2 desc"
+            ),
+            ["'ann',2,150", "'bob',1,70"]
+        );
+        assert_eq!(
+            query(&mut engine, "select u.id, o.id from users u, orders o where u.id < o.user_id order by 1, 2"),
+            ["1,12", "1,13", "2,13", "3,13", "4,13"]
+        );
+        assert_eq!(
+            query(&mut engine, "select age, count(*) from users group by age having count(*) > 1"),
+            ["25,2"]
+        );
+        assert_eq!(
+            query(&mut engine, "select count(*), count(age), min(name), max(age) from users where id > 100"),
+            ["0,0,NULL,NULL"]
+        );
+    }
+
+    #[test]
+    fn test_reindex() {
+        let mut engine = Engine::new();
+        engine.run("create table t (a int, b int, c int); create index t_a on t (a); create index t_b on t (b)").unwrap();
+        // enough rows for many pages, three of every four deleted
+        for batch in 0..16 {
+            let rows: Vec<String> = (batch * 500..(batch + 1) * 500).map(|i| format!("({}, {}, {})", i, i % 7, i % 4)).collect();
+            engine.run(&format!("insert into t values {}", rows.join(", "))).unwrap();
+        }
+        engine.run("delete from t where c > 0").unwrap();
+        let leaves = |engine: &Engine| -> Vec<usize> {
+            let data = engine.storage.table("t").unwrap();
+            data.indexes.iter().map(|i| i.tree().unwrap().stats().leaf_nodes).collect()
+        };
+        let sparse = leaves(&engine);
+        let sql = "select a from t where a > 7000 order by a";
+        let before = query(&mut engine, sql);
+
+        let result = engine.run("reindex index t_a").unwrap().pop().unwrap();
+        assert_eq!(result.tag, "REINDEX");
+        let packed = leaves(&engine);
+        assert!(packed[0] < sparse[0] && packed[1] == sparse[1]);
+        engine.run("reindex table t").unwrap();
+        assert!(leaves(&engine)[1] < sparse[1]);
+        assert_eq!(query(&mut engine, sql), before);
+
+        // rebuilt while the engine is only read, a change before the swap
+        // gets the indexes built anew
+        let (table, rebuilt) = engine.reindex(&Reindex::Index("t_a".to_string())).unwrap();
+        engine.run("insert into t values (9000, 1, 0)").unwrap();
+        engine.swap_indexes(&table, rebuilt).unwrap();
+        let data = engine.storage.table("t").unwrap();
+        assert!(data.indexes.iter().all(|i| i.tree().unwrap().len() == 2001));
+        assert_eq!(data.indexes[0].scan(&KeyRange::prefix(vec![Datum::Int(9000)])), [8000]);
+
+        let err = engine.run("reindex index nope").unwrap_err();
+        assert_eq!((err.code, err.message.as_str()), ("42704", "index \"nope\" does not exist"));
+        assert_eq!(engine.run("reindex table nope").unwrap_err().code, "42P01");
+    }
+
+    #[test]
+    fn test_index_scan_agrees_with_seq_scan() {
+        let mut engine = Engine::new();
+        engine.run("create table t (a int, b int)").unwrap();
+        for i in 0..500 {
+            engine
+                .run(&format!("insert into t values ({}, {})", i % 50, i))
+                .unwrap();
+        }
+        let sql = "select b from t where a = 7 and b > 100 order by b";
+        let seq = query(&mut engine, sql);
+        engine.run("create index t_ab on t (a, b desc); analyze").unwrap();
+        let stats = &engine.catalog.table("t").unwrap().indexes[0].stats;
+        assert_eq!(stats.keys, 500.0);
+        assert!(stats.height > 1.0 && stats.leaf_pages > 1.0);
+        let plan = engine.run(&format!("explain {}", sql)).unwrap().pop().unwrap();
+        assert!(plan.rows.iter().any(|row| row[0].to_string().contains("Index Scan using t_ab")));
+        assert_eq!(query(&mut engine, sql), seq);
+        assert_eq!(seq.len(), 8);
+
+        let result = engine.run("delete from t where a = 7 and b < 300").unwrap().pop().unwrap();
+        assert_eq!(result.tag, "DELETE 6");
+        assert_eq!(query(&mut engine, sql), ["307", "357", "407", "457"]);
+    }
+
+    #[test]
+    fn test_hash_index() {
+        let mut engine = Engine::new();
+        engine.run("create table t (a int, b text)").unwrap();
+        for i in 0..2000 {
+            engine.run(&format!("insert into t values ({}, 'b{}')", i, i % 200)).unwrap();
+        }
+        engine.run("insert into t values (2000, null)").unwrap();
+        let sql = "select a from t where b = 'b7' and a < 1000";
+        let seq = query(&mut engine, sql);
+        engine.run("create index t_b on t using hash (b); analyze t").unwrap();
+        let plan = engine.run(&format!("explain {}", sql)).unwrap().pop().unwrap();
+        assert!(plan.rows.iter().any(|row| row[0].to_string().contains("Index Scan using t_b")));
+        assert_eq!(query(&mut engine, sql), seq);
+        assert_eq!(seq.len(), 5);
+        // NULLs are left out
+        let stats = &engine.catalog.table("t").unwrap().indexes[0].stats;
+        assert_eq!((stats.height, stats.keys), (1.0, 2000.0));
+
+        engine.run("delete from t where b = 'b7' and a > 300").unwrap();
+        assert_eq!(query(&mut engine, sql), ["7", "207"]);
+        engine.run("reindex index t_b").unwrap();
+        assert_eq!(query(&mut engine, sql), ["7", "207"]);
+
+        let err = |engine: &mut Engine, sql: &str| engine.run(sql).unwrap_err();
+        let e = err(&mut engine, "create unique index t_u on t using hash (a)");
+        assert_eq!((e.code, e.message.as_str()), ("0A000", "access method \"hash\" does not support unique indexes"));
+        assert_eq!(err(&mut engine, "create index t_ab on t using hash (a, b)").code, "0A000");
+        assert_eq!(err(&mut engine, "create index t_d on t using hash (a desc)").code, "0A000");
+        assert_eq!(err(&mut engine, "create index t_g on t using gist (a)").code, "42704");
+    }
+
+    #[test]
+    fn test_index_row_size() {
+        let mut engine = Engine::new();
+        engine.run("create table t (a int, b text); create index t_b on t (b)").unwrap();
+        let long = "x".repeat(2000);
+        let err = engine.run(&format!("insert into t values (1, 'short'), (2, '{}')", long)).unwrap_err();
+        assert_eq!((err.code, err.message.as_str()), ("54000", "index row size 2012 exceeds maximum 1024 for index \"t_b\""));
+        assert!(query(&mut engine, "select a from t").is_empty());
+
+        engine.run("create table u (b text)").unwrap();
+        engine.run(&format!("insert into u values ('{}')", long)).unwrap();
+        assert_eq!(engine.run("create index u_b on u (b)").unwrap_err().code, "54000");
+        assert!(engine.catalog.table("u").unwrap().indexes.is_empty());
+    }
+
+    #[test]
+    fn test_insert_is_atomic() {
+        let mut engine = Engine::new();
+        engine.run("create table t (a int, b int); create unique index t_a on t (a); create index t_b on t (b)").unwrap();
+        engine.run("insert into t values (1, 10), (2, 20)").unwrap();
+        // a duplicate within the statement and one of a row already there
+        assert_eq!(engine.run("insert into t values (5, 50), (5, 51)").unwrap_err().code, "23505");
+        assert_eq!(engine.run("insert into t values (6, 60), (7, 70), (2, 21)").unwrap_err().code, "23505");
+        assert_eq!(query(&mut engine, "select a, b from t order by a"), ["1,10", "2,20"]);
+        assert!(query(&mut engine, "select a from t where b = 50 or b = 60").is_empty());
+        let data = engine.storage.table("t").unwrap();
+        assert_eq!((data.len(), data.end()), (2, 2));
+        assert!(data.indexes.iter().all(|i| i.tree().unwrap().len() == 2));
+        assert_eq!(engine.catalog.table("t").unwrap().stats.rows, 2.0);
+        engine.run("insert into t values (5, 50)").unwrap();
+        assert_eq!(query(&mut engine, "select b from t where a = 5"), ["50"]);
+    }
+
+    #[test]
+    fn test_dml() {
+        let mut engine = engine();
+        let result = engine.run("insert into users (name, id) values ('eve', 5)").unwrap().pop().unwrap();
+        assert_eq!(result.tag, "INSERT 0 1");
+        assert_eq!(query(&mut engine, "select * from users where id = 5"), ["5,'eve',NULL"]);
+        engine.run("create unique index users_id on users (id)").unwrap();
+        assert_eq!(engine.run("insert into users values (5, 'x', 1)").unwrap_err().code, "23505");
+        assert_eq!(engine.run("insert into users values ('x', 1, 1)").unwrap_err().code, "42804");
+        assert_eq!(engine.run("select 1 / 0").unwrap_err().code, "22012");
+        assert_eq!(engine.run("select 'a' + 1").unwrap_err().code, "42883");
+        // a failed unique index is not left behind
+        assert_eq!(engine.run("create unique index users_age on users (age)").unwrap_err().code, "23505");
+        assert!(engine.catalog.table("users").unwrap().indexes.iter().all(|i| i.name != "users_age"));
+        assert_eq!(engine.run("delete from users").unwrap().pop().unwrap().tag, "DELETE 5");
+        assert!(query(&mut engine, "select id from users").is_empty());
+    }
+}
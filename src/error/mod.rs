@@ -0,0 +1,64 @@
+#![allow(dead_code)]
+
+use std::fmt::Display;
+
+// An error raised while parsing, planning or executing a statement.
+//
+// `code` is the PostgreSQL SQLSTATE so that it can be reported to clients
+// unchanged, see https://www.postgresql.org/docs/current/errcodes-appendix.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub code: &'static str,
+    pub message: String,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn syntax(message: impl Into<String>) -> Self {
+        Self::new("42601", message)
+    }
+
+    pub fn undefined_table(name: &str) -> Self {
+        Self::new("42P01", format!("relation \"{}\" does not exist", name))
+    }
+
+    pub fn undefined_column(name: &str) -> Self {
+        Self::new("42703", format!("column \"{}\" does not exist", name))
+    }
+
+    pub fn ambiguous_column(name: &str) -> Self {
+        Self::new("42702", format!("column reference \"{}\" is ambiguous", name))
+    }
+
+    pub fn duplicate_table(name: &str) -> Self {
+        Self::new("42P07", format!("relation \"{}\" already exists", name))
+    }
+
+    pub fn grouping(message: impl Into<String>) -> Self {
+        Self::new("42803", message)
+    }
+
+    pub fn datatype_mismatch(message: impl Into<String>) -> Self {
+        Self::new("42804", message)
+    }
+
+    pub fn not_supported(message: impl Into<String>) -> Self {
+        Self::new("0A000", message)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ERROR:  {}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
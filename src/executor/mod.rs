@@ -0,0 +1,972 @@
+#![allow(dead_code)]
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::btree::key::{Datum, KeyRange, SortOrder};
+use crate::error::{Error, Result};
+use crate::planner::plan::{AggCall, AggFunc, Plan, PlanNode, ScalarExpr};
+use crate::sql::ast::{BinaryOp, UnaryOp};
+use crate::storage::{Row, Storage, TableData};
+
+// A Volcano style iterator: `open` prepares the operator(and its inputs),
+// every `next` returns one row until `None`, `close` releases what the
+// operator holds. An operator may be opened again after it was closed.
+pub trait Operator {
+    fn open(&mut self) -> Result<()>;
+    fn next(&mut self) -> Result<Option<Row>>;
+    fn close(&mut self);
+}
+
+// turn a physical plan into a tree of operators reading from `storage`
+pub fn build<'a>(plan: &'a Plan, storage: &'a Storage) -> Result<Box<dyn Operator + 'a>> {
+    Ok(match &plan.node {
+        PlanNode::Result => Box::new(ResultOp { done: false }),
+        PlanNode::SeqScan { table, filter, .. } => Box::new(SeqScan {
+            table: storage.table(table)?,
+            filter: filter.as_ref(),
+            pos: 0,
+        }),
+        PlanNode::IndexScan {
+            table,
+            index,
+            range,
+            filter,
+            ..
+        } => Box::new(IndexScan {
+            table: storage.table(table)?,
+            index: index.as_str(),
+            range,
+            filter: filter.as_ref(),
+            rowids: Vec::new(),
+            pos: 0,
+        }),
+        PlanNode::Filter { input, predicate } => Box::new(Filter {
+            input: build(input, storage)?,
+            predicate,
+        }),
+        PlanNode::Project { input, exprs } => Box::new(Project {
+            input: build(input, storage)?,
+            exprs,
+        }),
+        PlanNode::Sort { input, keys } => Box::new(Sort {
+            input: build(input, storage)?,
+            keys,
+            rows: Vec::new(),
+        }),
+        PlanNode::Limit { input, count, offset } => Box::new(Limit {
+            input: build(input, storage)?,
+            count: *count,
+            offset: *offset,
+            seen: 0,
+        }),
+        PlanNode::NestedLoopJoin {
+            left,
+            right,
+            condition,
+        } => Box::new(NestedLoopJoin {
+            left: build(left, storage)?,
+            right: build(right, storage)?,
+            condition: condition.as_ref(),
+            inner: Vec::new(),
+            outer: None,
+            pos: 0,
+        }),
+        PlanNode::HashJoin {
+            left,
+            right,
+            left_keys,
+            right_keys,
+            condition,
+        } => Box::new(HashJoin {
+            left: build(left, storage)?,
+            right: build(right, storage)?,
+            left_keys,
+            right_keys,
+            condition: condition.as_ref(),
+            table: HashMap::new(),
+            outer: None,
+            matches: Vec::new(),
+            pos: 0,
+        }),
+        PlanNode::HashAggregate {
+            input,
+            group_by,
+            aggregates,
+        } => Box::new(HashAggregate {
+            input: build(input, storage)?,
+            group_by,
+            aggregates,
+            output: Vec::new(),
+        }),
+    })
+}
+
+// run `plan` to completion
+pub fn collect(plan: &Plan, storage: &Storage) -> Result<Vec<Row>> {
+    let mut op = build(plan, storage)?;
+    op.open()?;
+    let mut rows = Vec::new();
+    let result = loop {
+        match op.next() {
+            Ok(Some(row)) => rows.push(row),
+            Ok(None) => break Ok(rows),
+            Err(e) => break Err(e),
+        }
+    };
+    op.close();
+    result
+}
+
+pub fn eval(expr: &ScalarExpr, row: &Row) -> Result<Datum> {
+    Ok(match expr {
+        ScalarExpr::Column { index, .. } => row[*index].clone(),
+        ScalarExpr::Literal(datum) => datum.clone(),
+        ScalarExpr::Binary { op, left, right } => {
+            let l = eval(left, row)?;
+            // AND and OR follow three-valued logic and may skip the right side
+            match (op, &l) {
+                (BinaryOp::And, Datum::Bool(false)) => return Ok(Datum::Bool(false)),
+                (BinaryOp::Or, Datum::Bool(true)) => return Ok(Datum::Bool(true)),
+                _ => (),
+            }
+            let r = eval(right, row)?;
+            binary(*op, l, r)?
+        }
+        ScalarExpr::Unary { op, expr } => match (op, eval(expr, row)?) {
+            (_, Datum::Null) => Datum::Null,
+            (UnaryOp::Not, Datum::Bool(b)) => Datum::Bool(!b),
+            (UnaryOp::Minus, Datum::Int(i)) => Datum::Int(i.checked_neg().ok_or_else(out_of_range)?),
+            (UnaryOp::Not, d) => return Err(Error::datatype_mismatch(format!("argument of NOT must be type boolean, not {}", d))),
+            (UnaryOp::Minus, d) => return Err(Error::new("42883", format!("operator does not exist: - {}", d))),
+        },
+        ScalarExpr::IsNull { expr, negated } => Datum::Bool((eval(expr, row)? == Datum::Null) != *negated),
+    })
+}
+
+fn binary(op: BinaryOp, l: Datum, r: Datum) -> Result<Datum> {
+    match op {
+        BinaryOp::And | BinaryOp::Or => {
+            let (l, r) = (truth(&l, op)?, truth(&r, op)?);
+            return Ok(match (op, l, r) {
+                (BinaryOp::And, Some(false), _) | (BinaryOp::And, _, Some(false)) => Datum::Bool(false),
+                (BinaryOp::Or, Some(true), _) | (BinaryOp::Or, _, Some(true)) => Datum::Bool(true),
+                (_, Some(l), Some(_)) => Datum::Bool(l),
+                _ => Datum::Null,
+            });
+        }
+        _ => (),
+    }
+    if l == Datum::Null || r == Datum::Null {
+        return Ok(Datum::Null);
+    }
+    if op.is_comparison() {
+        let same_type = matches!(
+            (&l, &r),
+            (Datum::Int(_), Datum::Int(_)) | (Datum::Text(_), Datum::Text(_)) | (Datum::Bool(_), Datum::Bool(_))
+        );
+        if !same_type {
+            return Err(Error::new(
+                "42883",
+                format!("operator does not exist: {} {} {}", type_name(&l), op, type_name(&r)),
+            ));
+        }
+        let ord = l.cmp(&r);
+        return Ok(Datum::Bool(match op {
+            BinaryOp::Eq => ord == Ordering::Equal,
+            BinaryOp::NotEq => ord != Ordering::Equal,
+            BinaryOp::Lt => ord == Ordering::Less,
+            BinaryOp::LtEq => ord != Ordering::Greater,
+            BinaryOp::Gt => ord == Ordering::Greater,
+            BinaryOp::GtEq => ord != Ordering::Less,
+            _ => unreachable!(),
+        }));
+    }
+    let (Datum::Int(a), Datum::Int(b)) = (&l, &r) else {
+        return Err(Error::new(
+            "42883",
+            format!("operator does not exist: {} {} {}", type_name(&l), op, type_name(&r)),
+        ));
+    };
+    let value = match op {
+        BinaryOp::Plus => a.checked_add(*b),
+        BinaryOp::Minus => a.checked_sub(*b),
+        BinaryOp::Multiply => a.checked_mul(*b),
+        BinaryOp::Divide => {
+            if *b == 0 {
+                return Err(Error::new("22012", "division by zero"));
+            }
+            a.checked_div(*b)
+        }
+        _ => unreachable!(),
+    };
+    value.map(Datum::Int).ok_or_else(out_of_range)
+}
+
+fn truth(datum: &Datum, op: BinaryOp) -> Result<Option<bool>> {
+    match datum {
+        Datum::Bool(b) => Ok(Some(*b)),
+        Datum::Null => Ok(None),
+        d => Err(Error::datatype_mismatch(format!(
+            "argument of {} must be type boolean, not type {}",
+            op,
+            type_name(d)
+        ))),
+    }
+}
+
+fn type_name(datum: &Datum) -> &'static str {
+    match datum {
+        Datum::Bool(_) => "boolean",
+        Datum::Int(_) => "integer",
+        Datum::Text(_) => "text",
+        Datum::Null => "unknown",
+    }
+}
+
+fn out_of_range() -> Error {
+    Error::new("22003", "integer out of range")
+}
+
+// whether a predicate lets a row through, NULL counts as false
+pub fn qualifies(predicate: Option<&ScalarExpr>, row: &Row) -> Result<bool> {
+    match predicate {
+        Some(predicate) => Ok(eval(predicate, row)? == Datum::Bool(true)),
+        None => Ok(true),
+    }
+}
+
+struct ResultOp {
+    done: bool,
+}
+
+impl Operator for ResultOp {
+    fn open(&mut self) -> Result<()> {
+        self.done = false;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+        Ok(Some(Vec::new()))
+    }
+
+    fn close(&mut self) {}
+}
+
+struct SeqScan<'a> {
+    table: &'a TableData,
+    filter: Option<&'a ScalarExpr>,
+    pos: usize,
+}
+
+impl Operator for SeqScan<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        loop {
+            let id = self.pos;
+            if id >= self.table.end() {
+                return Ok(None);
+            }
+            self.pos += 1;
+            if let Some(row) = self.table.get(id) {
+                if qualifies(self.filter, row)? {
+                    return Ok(Some(row.clone()));
+                }
+            }
+        }
+    }
+
+    fn close(&mut self) {}
+}
+
+// Looks the matching keys up in an index when opened and then
+// fetches the rows one at a time.
+struct IndexScan<'a> {
+    table: &'a TableData,
+    index: &'a str,
+    range: &'a KeyRange,
+    filter: Option<&'a ScalarExpr>,
+    rowids: Vec<usize>,
+    pos: usize,
+}
+
+impl Operator for IndexScan<'_> {
+    fn open(&mut self) -> Result<()> {
+        let index = self
+            .table
+            .indexes
+            .iter()
+            .find(|i| i.name == self.index)
+            .ok_or_else(|| Error::undefined_table(self.index))?;
+        self.rowids = index.scan(self.range);
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        while self.pos < self.rowids.len() {
+            let id = self.rowids[self.pos];
+            self.pos += 1;
+            if let Some(row) = self.table.get(id) {
+                if qualifies(self.filter, row)? {
+                    return Ok(Some(row.clone()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn close(&mut self) {
+        self.rowids = Vec::new();
+    }
+}
+
+struct Filter<'a> {
+    input: Box<dyn Operator + 'a>,
+    predicate: &'a ScalarExpr,
+}
+
+impl Operator for Filter<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.input.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        while let Some(row) = self.input.next()? {
+            if qualifies(Some(self.predicate), &row)? {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    fn close(&mut self) {
+        self.input.close();
+    }
+}
+
+struct Project<'a> {
+    input: Box<dyn Operator + 'a>,
+    exprs: &'a [ScalarExpr],
+}
+
+impl Operator for Project<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.input.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        match self.input.next()? {
+            Some(row) => {
+                let mut out = Vec::with_capacity(self.exprs.len());
+                for expr in self.exprs {
+                    out.push(eval(expr, &row)?);
+                }
+                Ok(Some(out))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn close(&mut self) {
+        self.input.close();
+    }
+}
+
+// Reads its whole input when opened. NULLs sort last in ascending and
+// first in descending order, as in PostgreSQL.
+struct Sort<'a> {
+    input: Box<dyn Operator + 'a>,
+    keys: &'a [(ScalarExpr, SortOrder)],
+    rows: Vec<Row>,
+}
+
+impl Operator for Sort<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.input.open()?;
+        let mut keyed = Vec::new();
+        while let Some(row) = self.input.next()? {
+            let mut key = Vec::with_capacity(self.keys.len());
+            for (expr, _) in self.keys {
+                key.push(eval(expr, &row)?);
+            }
+            keyed.push((key, row));
+        }
+        self.input.close();
+        keyed.sort_by(|(a, _), (b, _)| {
+            for (i, (_, order)) in self.keys.iter().enumerate() {
+                let ord = order.apply(a[i].cmp(&b[i]));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            Ordering::Equal
+        });
+        // pop from the back in `next`
+        self.rows = keyed.into_iter().rev().map(|(_, row)| row).collect();
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        Ok(self.rows.pop())
+    }
+
+    fn close(&mut self) {
+        self.rows = Vec::new();
+    }
+}
+
+// Skips the first `offset` rows of its input, then stops after `count`.
+struct Limit<'a> {
+    input: Box<dyn Operator + 'a>,
+    count: Option<u64>,
+    offset: u64,
+    // the rows returned so far
+    seen: u64,
+}
+
+impl Operator for Limit<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.seen = 0;
+        self.input.open()?;
+        for _ in 0..self.offset {
+            if self.input.next()?.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        if self.count.is_some_and(|count| self.seen >= count) {
+            return Ok(None);
+        }
+        self.seen += 1;
+        self.input.next()
+    }
+
+    fn close(&mut self) {
+        self.input.close();
+    }
+}
+
+fn concat(left: &Row, right: &Row) -> Row {
+    let mut row = Vec::with_capacity(left.len() + right.len());
+    row.extend(left.iter().cloned());
+    row.extend(right.iter().cloned());
+    row
+}
+
+// Materializes the right input once and loops over it for every left row.
+struct NestedLoopJoin<'a> {
+    left: Box<dyn Operator + 'a>,
+    right: Box<dyn Operator + 'a>,
+    condition: Option<&'a ScalarExpr>,
+    inner: Vec<Row>,
+    outer: Option<Row>,
+    pos: usize,
+}
+
+impl Operator for NestedLoopJoin<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.right.open()?;
+        self.inner.clear();
+        while let Some(row) = self.right.next()? {
+            self.inner.push(row);
+        }
+        self.right.close();
+        self.outer = None;
+        self.left.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        loop {
+            if self.outer.is_none() || self.pos >= self.inner.len() {
+                match self.left.next()? {
+                    Some(row) => {
+                        self.outer = Some(row);
+                        self.pos = 0;
+                    }
+                    None => return Ok(None),
+                }
+            }
+            let outer = self.outer.as_ref().unwrap();
+            while self.pos < self.inner.len() {
+                let row = concat(outer, &self.inner[self.pos]);
+                self.pos += 1;
+                if qualifies(self.condition, &row)? {
+                    return Ok(Some(row));
+                }
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.left.close();
+        self.inner = Vec::new();
+        self.outer = None;
+    }
+}
+
+// Builds a hash table on the right input when opened and probes it with
+// every left row. Keys containing NULL never match.
+struct HashJoin<'a> {
+    left: Box<dyn Operator + 'a>,
+    right: Box<dyn Operator + 'a>,
+    left_keys: &'a [ScalarExpr],
+    right_keys: &'a [ScalarExpr],
+    condition: Option<&'a ScalarExpr>,
+    table: HashMap<Vec<Datum>, Vec<Row>>,
+    outer: Option<Row>,
+    // the right rows matching `outer`
+    matches: Vec<Row>,
+    pos: usize,
+}
+
+fn hash_key(exprs: &[ScalarExpr], row: &Row) -> Result<Option<Vec<Datum>>> {
+    let mut key = Vec::with_capacity(exprs.len());
+    for expr in exprs {
+        let datum = eval(expr, row)?;
+        if datum == Datum::Null {
+            return Ok(None);
+        }
+        key.push(datum);
+    }
+    Ok(Some(key))
+}
+
+impl Operator for HashJoin<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.right.open()?;
+        self.table.clear();
+        while let Some(row) = self.right.next()? {
+            if let Some(key) = hash_key(self.right_keys, &row)? {
+                self.table.entry(key).or_default().push(row);
+            }
+        }
+        self.right.close();
+        self.outer = None;
+        self.matches.clear();
+        self.left.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        loop {
+            while self.pos < self.matches.len() {
+                let row = concat(self.outer.as_ref().unwrap(), &self.matches[self.pos]);
+                self.pos += 1;
+                if qualifies(self.condition, &row)? {
+                    return Ok(Some(row));
+                }
+            }
+            let Some(row) = self.left.next()? else {
+                return Ok(None);
+            };
+            self.matches = match hash_key(self.left_keys, &row)? {
+                Some(key) => self.table.get(&key).cloned().unwrap_or_default(),
+                None => Vec::new(),
+            };
+            self.outer = Some(row);
+            self.pos = 0;
+        }
+    }
+
+    fn close(&mut self) {
+        self.left.close();
+        self.table = HashMap::new();
+        self.matches = Vec::new();
+        self.outer = None;
+    }
+}
+
+#[derive(Clone)]
+enum AggState {
+    Count(i64),
+    Sum(Option<i64>),
+    Min(Option<Datum>),
+    Max(Option<Datum>),
+}
+
+impl AggState {
+    fn new(func: AggFunc) -> Self {
+        match func {
+            AggFunc::CountStar | AggFunc::Count => AggState::Count(0),
+            AggFunc::Sum => AggState::Sum(None),
+            AggFunc::Min => AggState::Min(None),
+            AggFunc::Max => AggState::Max(None),
+        }
+    }
+
+    fn update(&mut self, call: &AggCall, row: &Row) -> Result<()> {
+        let value = match &call.arg {
+            Some(arg) => eval(arg, row)?,
+            None => Datum::Bool(true),
+        };
+        // aggregates other than count(*) skip NULL inputs
+        if value == Datum::Null {
+            return Ok(());
+        }
+        match self {
+            AggState::Count(n) => *n += 1,
+            AggState::Sum(sum) => {
+                let Datum::Int(v) = value else {
+                    return Err(Error::new("42883", format!("function sum({}) does not exist", type_name(&value))));
+                };
+                *sum = Some(sum.unwrap_or(0).checked_add(v).ok_or_else(out_of_range)?);
+            }
+            AggState::Min(min) => {
+                if min.as_ref().is_none_or(|m| value < *m) {
+                    *min = Some(value);
+                }
+            }
+            AggState::Max(max) => {
+                if max.as_ref().is_none_or(|m| value > *m) {
+                    *max = Some(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Datum {
+        match self {
+            AggState::Count(n) => Datum::Int(n),
+            AggState::Sum(v) => v.map(Datum::Int).unwrap_or(Datum::Null),
+            AggState::Min(v) | AggState::Max(v) => v.unwrap_or(Datum::Null),
+        }
+    }
+}
+
+// Groups its whole input when opened. Without GROUP BY there is exactly
+// one output row, even for an empty input.
+struct HashAggregate<'a> {
+    input: Box<dyn Operator + 'a>,
+    group_by: &'a [ScalarExpr],
+    aggregates: &'a [AggCall],
+    output: Vec<Row>,
+}
+
+impl Operator for HashAggregate<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.input.open()?;
+        let init: Vec<AggState> = self.aggregates.iter().map(|a| AggState::new(a.func)).collect();
+        let mut groups: HashMap<Vec<Datum>, Vec<AggState>> = HashMap::new();
+        // remember the order groups were first seen in
+        let mut order = Vec::new();
+        if self.group_by.is_empty() {
+            groups.insert(Vec::new(), init.clone());
+            order.push(Vec::new());
+        }
+        while let Some(row) = self.input.next()? {
+            let mut key = Vec::with_capacity(self.group_by.len());
+            for expr in self.group_by {
+                key.push(eval(expr, &row)?);
+            }
+            let states = match groups.get_mut(&key) {
+                Some(states) => states,
+                None => {
+                    order.push(key.clone());
+                    groups.entry(key).or_insert_with(|| init.clone())
+                }
+            };
+            for (state, call) in states.iter_mut().zip(self.aggregates) {
+                state.update(call, &row)?;
+            }
+        }
+        self.input.close();
+        self.output = order
+            .into_iter()
+            .rev()
+            .map(|key| {
+                let states = groups.remove(&key).unwrap();
+                let mut row = key;
+                row.extend(states.into_iter().map(AggState::finish));
+                row
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        Ok(self.output.pop())
+    }
+
+    fn close(&mut self) {
+        self.output = Vec::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::key::SortOrder;
+    use crate::catalog::{IndexInfo, IndexMethod, IndexStats};
+
+    // rows from memory
+    struct Values {
+        rows: Vec<Row>,
+        pos: usize,
+    }
+
+    impl Operator for Values {
+        fn open(&mut self) -> Result<()> {
+            self.pos = 0;
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<Option<Row>> {
+            self.pos += 1;
+            Ok(self.rows.get(self.pos - 1).cloned())
+        }
+
+        fn close(&mut self) {}
+    }
+
+    fn values<'a>(rows: &[&[Datum]]) -> Box<dyn Operator + 'a> {
+        let rows = rows.iter().map(|r| r.to_vec()).collect();
+        Box::new(Values { rows, pos: 0 })
+    }
+
+    fn int(i: i64) -> Datum {
+        Datum::Int(i)
+    }
+
+    fn col(index: usize) -> ScalarExpr {
+        ScalarExpr::Column {
+            index,
+            name: format!("c{}", index),
+        }
+    }
+
+    fn binary(op: BinaryOp, left: ScalarExpr, right: ScalarExpr) -> ScalarExpr {
+        ScalarExpr::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    // open, drain and close `op`, twice, checking a reopened operator
+    // returns the same rows
+    fn run(op: &mut dyn Operator) -> Vec<Row> {
+        let drain = |op: &mut dyn Operator| {
+            op.open().unwrap();
+            let mut rows = Vec::new();
+            while let Some(row) = op.next().unwrap() {
+                rows.push(row);
+            }
+            // and stays done
+            assert_eq!(op.next().unwrap(), None);
+            op.close();
+            rows
+        };
+        let rows = drain(op);
+        assert_eq!(drain(op), rows, "different rows once reopened");
+        rows
+    }
+
+    #[test]
+    fn test_scans() {
+        let mut table = TableData::default();
+        for i in 0..10 {
+            table.insert(vec![int(i % 4), int(i)]).unwrap();
+        }
+        table.delete(5);
+        let info = IndexInfo {
+            name: "t_a".to_string(),
+            columns: vec![(0, SortOrder::Asc)],
+            unique: false,
+            method: IndexMethod::Btree,
+            stats: IndexStats::default(),
+        };
+        table.create_index(&info).unwrap();
+
+        let filter = binary(BinaryOp::Gt, col(1), ScalarExpr::Literal(int(6)));
+        let mut scan = SeqScan {
+            table: &table,
+            filter: Some(&filter),
+            pos: 0,
+        };
+        assert_eq!(run(&mut scan), [vec![int(3), int(7)], vec![int(0), int(8)], vec![int(1), int(9)]]);
+
+        // the deleted row is left out
+        let range = KeyRange::prefix(vec![int(1)]);
+        let mut scan = IndexScan {
+            table: &table,
+            index: "t_a",
+            range: &range,
+            filter: None,
+            rowids: Vec::new(),
+            pos: 0,
+        };
+        assert_eq!(run(&mut scan), [vec![int(1), int(1)], vec![int(1), int(9)]]);
+        let mut scan = IndexScan { index: "nope", ..scan };
+        assert_eq!(scan.open().unwrap_err().code, "42P01");
+    }
+
+    #[test]
+    fn test_filter_project_sort() {
+        let input: &[&[Datum]] = &[&[int(2), Datum::Null], &[int(3), int(1)], &[int(1), int(5)], &[int(4), Datum::Null]];
+        // NULL is not true
+        let predicate = binary(BinaryOp::Lt, col(1), ScalarExpr::Literal(int(3)));
+        let mut filter = Filter {
+            input: values(input),
+            predicate: &predicate,
+        };
+        assert_eq!(run(&mut filter), [vec![int(3), int(1)]]);
+
+        let exprs = [binary(BinaryOp::Multiply, col(0), ScalarExpr::Literal(int(10)))];
+        let mut project = Project {
+            input: values(input),
+            exprs: &exprs,
+        };
+        assert_eq!(run(&mut project), [[int(20)], [int(30)], [int(10)], [int(40)]]);
+
+        // NULLs last ascending, first descending, then by the second key
+        let keys = [(col(1), SortOrder::Asc), (col(0), SortOrder::Desc)];
+        let mut sort = Sort {
+            input: values(input),
+            keys: &keys,
+            rows: Vec::new(),
+        };
+        let firsts = |rows: Vec<Row>| rows.into_iter().map(|r| r[0].clone()).collect::<Vec<_>>();
+        assert_eq!(firsts(run(&mut sort)), [int(3), int(1), int(4), int(2)]);
+        let keys = [(col(1), SortOrder::Desc), (col(0), SortOrder::Asc)];
+        let mut sort = Sort { keys: &keys, ..sort };
+        assert_eq!(firsts(run(&mut sort)), [int(2), int(4), int(1), int(3)]);
+    }
+
+    #[test]
+    fn test_limit() {
+        let input: Vec<Vec<Datum>> = (0..5).map(|i| vec![int(i)]).collect();
+        let input: Vec<&[Datum]> = input.iter().map(|r| r.as_slice()).collect();
+        let cases: [(Option<u64>, u64, &[i64]); 8] = [
+            (Some(0), 0, &[]),
+            (Some(2), 0, &[0, 1]),
+            (Some(5), 0, &[0, 1, 2, 3, 4]),
+            (Some(9), 0, &[0, 1, 2, 3, 4]),
+            (None, 3, &[3, 4]),
+            (Some(3), 1, &[1, 2, 3]),
+            (Some(2), 4, &[4]),
+            (None, 5, &[]),
+        ];
+        for (count, offset, want) in cases {
+            let mut limit = Limit {
+                input: values(&input),
+                count,
+                offset,
+                seen: 0,
+            };
+            let want: Vec<Row> = want.iter().map(|&i| vec![int(i)]).collect();
+            assert_eq!(run(&mut limit), want, "limit {:?} offset {}", count, offset);
+        }
+        let mut limit = Limit {
+            input: values(&[]),
+            count: Some(1),
+            offset: 9,
+            seen: 0,
+        };
+        assert!(run(&mut limit).is_empty());
+    }
+
+    #[test]
+    fn test_joins_skip_null_keys() {
+        let left: &[&[Datum]] = &[&[int(1)], &[Datum::Null], &[int(2)]];
+        let right: &[&[Datum]] = &[&[int(1), int(10)], &[Datum::Null, int(20)], &[int(3), int(30)], &[int(1), int(40)]];
+        let want = [vec![int(1), int(1), int(10)], vec![int(1), int(1), int(40)]];
+
+        let (left_keys, right_keys) = ([col(0)], [col(0)]);
+        let mut join = HashJoin {
+            left: values(left),
+            right: values(right),
+            left_keys: &left_keys,
+            right_keys: &right_keys,
+            condition: None,
+            table: HashMap::new(),
+            outer: None,
+            matches: Vec::new(),
+            pos: 0,
+        };
+        assert_eq!(run(&mut join), want);
+
+        // NULL = NULL is not true either
+        let condition = binary(BinaryOp::Eq, col(0), col(1));
+        let mut join = NestedLoopJoin {
+            left: values(left),
+            right: values(right),
+            condition: Some(&condition),
+            inner: Vec::new(),
+            outer: None,
+            pos: 0,
+        };
+        assert_eq!(run(&mut join), want);
+        let mut join = NestedLoopJoin { condition: None, ..join };
+        assert_eq!(run(&mut join).len(), 12);
+
+        // an empty side joins nothing
+        let mut join = NestedLoopJoin {
+            left: values(left),
+            right: values(&[]),
+            condition: None,
+            inner: Vec::new(),
+            outer: None,
+            pos: 0,
+        };
+        assert!(run(&mut join).is_empty());
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let calls: Vec<AggCall> = [
+            (AggFunc::CountStar, None),
+            (AggFunc::Count, Some(col(1))),
+            (AggFunc::Sum, Some(col(1))),
+            (AggFunc::Min, Some(col(1))),
+            (AggFunc::Max, Some(col(1))),
+        ]
+        .into_iter()
+        .map(|(func, arg)| AggCall { func, arg })
+        .collect();
+
+        // one row without GROUP BY, none with it
+        let mut agg = HashAggregate {
+            input: values(&[]),
+            group_by: &[],
+            aggregates: &calls,
+            output: Vec::new(),
+        };
+        assert_eq!(run(&mut agg), [vec![int(0), int(0), Datum::Null, Datum::Null, Datum::Null]]);
+        let group_by = [col(0)];
+        let mut agg = HashAggregate {
+            input: values(&[]),
+            group_by: &group_by,
+            aggregates: &calls,
+            output: Vec::new(),
+        };
+        assert!(run(&mut agg).is_empty());
+
+        // groups in the order first seen, NULL a group of its own
+        let input: &[&[Datum]] = &[&[int(2), int(5)], &[Datum::Null, int(1)], &[int(2), Datum::Null], &[int(2), int(-3)]];
+        let mut agg = HashAggregate {
+            input: values(input),
+            group_by: &group_by,
+            aggregates: &calls,
+            output: Vec::new(),
+        };
+        assert_eq!(
+            run(&mut agg),
+            [
+                vec![int(2), int(3), int(2), int(2), int(-3), int(5)],
+                vec![Datum::Null, int(1), int(1), int(1), int(1), int(1)],
+            ]
+        );
+    }
+}
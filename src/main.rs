@@ -1,5 +1,56 @@
 mod btree;
+mod catalog;
+mod engine;
+mod error;
+mod executor;
+mod planner;
+mod repl;
+mod server;
+mod sql;
+mod storage;
+
+use std::io::IsTerminal;
+use std::net::TcpListener;
+use std::sync::{Arc, RwLock};
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:5432";
+
+fn usage() -> ! {
+    eprintln!("usage: minipg [--listen ADDR | --repl]");
+    std::process::exit(2);
+}
 
 fn main() {
-    println!("Hello, world!");
+    let mut listen = DEFAULT_LISTEN.to_string();
+    let mut repl = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" | "-l" => listen = args.next().unwrap_or_else(|| usage()),
+            "--repl" => repl = true,
+            _ => usage(),
+        }
+    }
+    if repl {
+        let stdin = std::io::stdin();
+        let prompt = stdin.is_terminal();
+        if let Err(e) = repl::Repl::new(std::io::stdout(), prompt).run(stdin.lock()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let listener = match TcpListener::bind(&listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("could not listen on {}: {}", listen, e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("listening on {}", listen);
+    let engine = Arc::new(RwLock::new(engine::Engine::new()));
+    if let Err(e) = server::serve(listener, engine) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,1358 @@
+#![allow(dead_code)]
+
+pub mod plan;
+
+use std::collections::HashMap;
+use std::ops::Bound;
+
+use crate::btree::key::{Datum, KeyRange, SortOrder};
+use crate::catalog::{Catalog, ColumnStats, IndexInfo, IndexMethod, Table};
+use crate::error::{Error, Result};
+use crate::sql::ast::*;
+use plan::*;
+
+// cost units, the same as PostgreSQL's defaults
+const SEQ_PAGE_COST: f64 = 1.0;
+const RANDOM_PAGE_COST: f64 = 4.0;
+const CPU_TUPLE_COST: f64 = 0.01;
+const CPU_INDEX_TUPLE_COST: f64 = 0.005;
+const CPU_OPERATOR_COST: f64 = 0.0025;
+// how many rows an (imaginary) heap page holds
+const ROWS_PER_PAGE: f64 = 50.0;
+
+// selectivities used when nothing better is known, see PostgreSQL's selfuncs.h
+const DEFAULT_EQ_SEL: f64 = 0.005;
+const DEFAULT_INEQ_SEL: f64 = 1.0 / 3.0;
+const DEFAULT_SEL: f64 = 0.5;
+const DEFAULT_NUM_DISTINCT: f64 = 200.0;
+
+// join orders are searched exhaustively up to this many tables,
+// larger joins are done in FROM order
+const MAX_DP_TABLES: usize = 10;
+
+struct Rel<'a> {
+    tref: TableRef,
+    table: &'a Table,
+}
+
+// a WHERE or ON predicate and the set of tables(bit i for `rels[i]`) it uses
+struct Conjunct {
+    expr: Expr,
+    rels: u32,
+}
+
+// the `GROUP BY` expressions and aggregate calls of a query, the output
+// of `HashAggregate` is the group by columns followed by the aggregates
+struct Grouping {
+    group_by: Vec<Expr>,
+    bound: Vec<ScalarExpr>,
+    aggs: Vec<(Expr, AggCall)>,
+}
+
+struct Planner<'a> {
+    catalog: &'a Catalog,
+    rels: Vec<Rel<'a>>,
+    // qualify column names in EXPLAIN, only done for joins
+    qualify: bool,
+}
+
+pub fn plan_select(catalog: &Catalog, select: &Select) -> Result<Plan> {
+    let mut planner = Planner {
+        catalog,
+        rels: Vec::new(),
+        qualify: false,
+    };
+    planner.plan(select)
+}
+
+// the output of `EXPLAIN <stmt>`
+pub fn explain(catalog: &Catalog, stmt: &Statement) -> Result<Vec<String>> {
+    match stmt {
+        Statement::Select(select) => Ok(plan_select(catalog, select)?.explain()),
+        _ => Err(Error::not_supported("EXPLAIN is only supported for SELECT")),
+    }
+}
+
+// bind an expression of INSERT or DELETE, which may only refer to the
+// columns of `table`(if any)
+pub fn bind_expr(catalog: &Catalog, table: Option<&str>, expr: &Expr) -> Result<ScalarExpr> {
+    let mut planner = Planner {
+        catalog,
+        rels: Vec::new(),
+        qualify: false,
+    };
+    let mut scope = Vec::new();
+    if let Some(name) = table {
+        let table = catalog.table(name)?;
+        planner.rels.push(Rel {
+            tref: TableRef {
+                name: name.to_string(),
+                alias: None,
+            },
+            table,
+        });
+        scope = table
+            .columns
+            .iter()
+            .map(|c| OutputColumn {
+                table: Some(name.to_string()),
+                name: c.name.clone(),
+                ty: c.ty,
+            })
+            .collect();
+    }
+    if expr.contains_aggregate() {
+        return Err(Error::grouping("aggregate functions are not allowed here"));
+    }
+    planner.bind(expr, &scope)
+}
+
+// The types of the `$n` parameters of `stmt`, `None` when nothing tells.
+// A parameter takes the type of the column it is compared with or
+// inserted into, operands of arithmetic are integers and of AND, OR
+// and NOT booleans.
+pub fn param_types(catalog: &Catalog, stmt: &Statement) -> Vec<Option<DataType>> {
+    let mut trefs = Vec::new();
+    let mut inner = stmt;
+    while let Statement::Explain(stmt) = inner {
+        inner = stmt;
+    }
+    let mut types: Vec<Option<DataType>> = Vec::new();
+    let set = |n: usize, ty: Option<DataType>, types: &mut Vec<Option<DataType>>| {
+        if types.len() < n {
+            types.resize(n, None);
+        }
+        if types[n - 1].is_none() {
+            types[n - 1] = ty;
+        }
+    };
+    match inner {
+        Statement::Select(select) => {
+            let mut items: Vec<&FromItem> = select.from.iter().collect();
+            while let Some(item) = items.pop() {
+                match item {
+                    FromItem::Table(tref) => trefs.push(tref.clone()),
+                    FromItem::Join { left, right, .. } => {
+                        items.push(left);
+                        trefs.push(right.clone());
+                    }
+                }
+            }
+        }
+        Statement::Delete(delete) => trefs.push(TableRef {
+            name: delete.table.clone(),
+            alias: None,
+        }),
+        Statement::Insert(insert) => {
+            if let Ok(table) = catalog.table(&insert.table) {
+                for row in &insert.rows {
+                    for (i, value) in row.iter().enumerate() {
+                        let column = match &insert.columns {
+                            Some(names) => names.get(i).and_then(|name| table.column_index(name)),
+                            None => Some(i),
+                        };
+                        if let (Expr::Param(n), Some(c)) = (value, column) {
+                            set(*n, table.columns.get(c).map(|c| c.ty), &mut types);
+                        }
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+    let column_type = |table: &Option<String>, name: &str| {
+        trefs
+            .iter()
+            .filter(|tref| table.as_ref().is_none_or(|t| t == tref.qualifier()))
+            .filter_map(|tref| catalog.table(&tref.name).ok())
+            .find_map(|t| t.column_index(name).map(|c| t.columns[c].ty))
+    };
+    let mut stmt = inner.clone();
+    stmt.visit_exprs_mut(&mut |expr| {
+        let operand_type = |other: &Expr| match other {
+            Expr::Column { table, name } => column_type(table, name),
+            Expr::Literal(Datum::Int(_)) => Some(DataType::Int),
+            Expr::Literal(Datum::Bool(_)) => Some(DataType::Bool),
+            Expr::Literal(Datum::Text(_)) => Some(DataType::Text),
+            _ => None,
+        };
+        match expr {
+            Expr::Binary { op, left, right } => {
+                let op = *op;
+                for (param, other) in [(&**left, &**right), (&**right, &**left)] {
+                    let Expr::Param(n) = param else {
+                        continue;
+                    };
+                    let ty = match op {
+                        BinaryOp::And | BinaryOp::Or => Some(DataType::Bool),
+                        op if op.is_comparison() => operand_type(other),
+                        _ => Some(DataType::Int),
+                    };
+                    set(*n, ty, &mut types);
+                }
+            }
+            Expr::Unary { op, expr } => {
+                if let Expr::Param(n) = &**expr {
+                    let ty = match op {
+                        UnaryOp::Not => DataType::Bool,
+                        UnaryOp::Minus => DataType::Int,
+                    };
+                    set(*n, Some(ty), &mut types);
+                }
+            }
+            Expr::Param(n) => set(*n, None, &mut types),
+            _ => (),
+        }
+    });
+    types
+}
+
+impl<'a> Planner<'a> {
+    fn plan(&mut self, select: &Select) -> Result<Plan> {
+        let mut conds = Vec::new();
+        for item in &select.from {
+            self.collect_from(item, &mut conds)?;
+        }
+        if self.rels.len() > 32 {
+            return Err(Error::not_supported("too many tables in FROM"));
+        }
+        self.qualify = self.rels.len() > 1;
+        if let Some(selection) = &select.selection {
+            if selection.contains_aggregate() {
+                return Err(Error::grouping("aggregate functions are not allowed in WHERE"));
+            }
+            conds.extend(selection.clone().conjuncts());
+        }
+        let mut conjuncts = Vec::new();
+        for expr in conds {
+            let rels = self.rels_of(&expr)?;
+            conjuncts.push(Conjunct { expr, rels });
+        }
+
+        // expand `*` so that ORDER BY positions and the projection agree
+        let mut items = Vec::new();
+        for item in &select.items {
+            match item {
+                SelectItem::Wildcard => {
+                    if self.rels.is_empty() {
+                        return Err(Error::syntax("SELECT * with no tables specified is not valid"));
+                    }
+                    for rel in &self.rels {
+                        for column in &rel.table.columns {
+                            let expr = Expr::Column {
+                                table: Some(rel.tref.qualifier().to_string()),
+                                name: column.name.clone(),
+                            };
+                            items.push((expr, column.name.clone()));
+                        }
+                    }
+                }
+                SelectItem::Expr { expr, alias } => {
+                    let name = match (alias, expr) {
+                        (Some(alias), _) => alias.clone(),
+                        (None, Expr::Column { name, .. }) => name.clone(),
+                        (None, Expr::Function { name, .. }) => name.clone(),
+                        (None, _) => "?column?".to_string(),
+                    };
+                    items.push((expr.clone(), name));
+                }
+            }
+        }
+        let mut order_by = Vec::new();
+        for item in &select.order_by {
+            order_by.push((self.resolve_order_by(&item.expr, &items)?, item.order));
+        }
+
+        let has_agg = !select.group_by.is_empty()
+            || items.iter().any(|(e, _)| e.contains_aggregate())
+            || select.having.is_some()
+            || order_by.iter().any(|(e, _)| e.contains_aggregate());
+
+        // a single table query may get its ordering from an index
+        let wanted = if self.rels.len() == 1 && !has_agg && !order_by.is_empty() {
+            self.wanted_order(&order_by)
+        } else {
+            None
+        };
+
+        let (mut plan, mut sorted) = self.plan_joins(&conjuncts, wanted.as_deref())?;
+
+        let consts: Vec<&Conjunct> = conjuncts.iter().filter(|c| c.rels == 0).collect();
+        if !consts.is_empty() {
+            let mut predicates = Vec::new();
+            for c in consts {
+                predicates.push(self.bind(&c.expr, &plan.columns)?);
+            }
+            let predicate = ScalarExpr::conjunction(predicates).unwrap();
+            plan = self.filter(plan, predicate);
+        }
+
+        // from here on expressions are bound against the pre-projection scope
+        let mut grouping = None;
+        if has_agg {
+            let mut g = Grouping {
+                group_by: select.group_by.clone(),
+                bound: Vec::new(),
+                aggs: Vec::new(),
+            };
+            for expr in &select.group_by {
+                if expr.contains_aggregate() {
+                    return Err(Error::grouping("aggregate functions are not allowed in GROUP BY"));
+                }
+                g.bound.push(self.bind(expr, &plan.columns)?);
+            }
+            // register every aggregate before the aggregate node is built
+            for (expr, _) in &items {
+                self.bind_grouped(expr, &plan.columns, &mut g)?;
+            }
+            if let Some(having) = &select.having {
+                self.bind_grouped(having, &plan.columns, &mut g)?;
+            }
+            for (expr, _) in &order_by {
+                self.bind_grouped(expr, &plan.columns, &mut g)?;
+            }
+            let input_columns = plan.columns.clone();
+            plan = self.aggregate(plan, &g)?;
+            if let Some(having) = &select.having {
+                let predicate = self.bind_grouped(having, &input_columns, &mut g)?;
+                plan = self.filter(plan, predicate);
+            }
+            grouping = Some((g, input_columns));
+        }
+
+        let mut bind_output = |planner: &Self, expr: &Expr, scope: &[OutputColumn]| match &mut grouping {
+            Some((g, input_columns)) => planner.bind_grouped(expr, input_columns, g),
+            None => planner.bind(expr, scope),
+        };
+
+        if !order_by.is_empty() && !sorted {
+            let mut keys = Vec::new();
+            for (expr, order) in &order_by {
+                keys.push((bind_output(self, expr, &plan.columns)?, *order));
+            }
+            let cost = plan.cost + sort_cost(plan.rows);
+            plan = Plan {
+                rows: plan.rows,
+                cost,
+                columns: plan.columns.clone(),
+                node: PlanNode::Sort {
+                    input: Box::new(plan),
+                    keys,
+                },
+            };
+            sorted = true;
+        }
+        debug_assert!(sorted || order_by.is_empty());
+
+        let mut exprs = Vec::new();
+        let mut columns = Vec::new();
+        for (expr, name) in &items {
+            let bound = bind_output(self, expr, &plan.columns)?;
+            columns.push(OutputColumn {
+                table: None,
+                name: name.clone(),
+                ty: type_of(&bound, &plan.columns),
+            });
+            exprs.push(bound);
+        }
+        let cost = plan.cost + plan.rows * CPU_OPERATOR_COST * exprs.len() as f64;
+        plan = Plan {
+            rows: plan.rows,
+            cost,
+            columns,
+            node: PlanNode::Project {
+                input: Box::new(plan),
+                exprs,
+            },
+        };
+
+        if select.limit.is_some() || select.offset.is_some() {
+            let (count, offset) = (select.limit, select.offset.unwrap_or(0));
+            let rows = (plan.rows - offset as f64).max(0.0);
+            plan = Plan {
+                rows: count.map_or(rows, |count| rows.min(count as f64)),
+                cost: plan.cost,
+                columns: plan.columns.clone(),
+                node: PlanNode::Limit {
+                    input: Box::new(plan),
+                    count,
+                    offset,
+                },
+            };
+        }
+        Ok(plan)
+    }
+
+    fn collect_from(&mut self, item: &FromItem, conds: &mut Vec<Expr>) -> Result<()> {
+        match item {
+            FromItem::Table(tref) => {
+                let table = self.catalog.table(&tref.name)?;
+                if self.rels.iter().any(|r| r.tref.qualifier() == tref.qualifier()) {
+                    return Err(Error::new(
+                        "42712",
+                        format!("table name \"{}\" specified more than once", tref.qualifier()),
+                    ));
+                }
+                self.rels.push(Rel {
+                    tref: tref.clone(),
+                    table,
+                });
+            }
+            FromItem::Join { left, right, on } => {
+                self.collect_from(left, conds)?;
+                self.collect_from(&FromItem::Table(right.clone()), conds)?;
+                if let Some(on) = on {
+                    if on.contains_aggregate() {
+                        return Err(Error::grouping("aggregate functions are not allowed in JOIN conditions"));
+                    }
+                    conds.extend(on.clone().conjuncts());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // find the table and column number a column reference points to
+    fn resolve(&self, table: Option<&str>, name: &str) -> Result<(usize, usize)> {
+        if let Some(table) = table {
+            let r = self
+                .rels
+                .iter()
+                .position(|r| r.tref.qualifier() == table)
+                .ok_or_else(|| Error::new("42P01", format!("missing FROM-clause entry for table \"{}\"", table)))?;
+            let c = self.rels[r]
+                .table
+                .column_index(name)
+                .ok_or_else(|| Error::undefined_column(&format!("{}.{}", table, name)))?;
+            return Ok((r, c));
+        }
+        let mut found = None;
+        for (r, rel) in self.rels.iter().enumerate() {
+            if let Some(c) = rel.table.column_index(name) {
+                if found.is_some() {
+                    return Err(Error::ambiguous_column(name));
+                }
+                found = Some((r, c));
+            }
+        }
+        found.ok_or_else(|| Error::undefined_column(name))
+    }
+
+    fn rels_of(&self, expr: &Expr) -> Result<u32> {
+        Ok(match expr {
+            Expr::Column { table, name } => 1 << self.resolve(table.as_deref(), name)?.0,
+            Expr::Literal(_) | Expr::Param(_) => 0,
+            Expr::Binary { left, right, .. } => self.rels_of(left)? | self.rels_of(right)?,
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } => self.rels_of(expr)?,
+            Expr::Function { args, .. } => {
+                let mut rels = 0;
+                for arg in args {
+                    rels |= self.rels_of(arg)?;
+                }
+                rels
+            }
+        })
+    }
+
+    // ORDER BY may name an output column or its position
+    fn resolve_order_by(&self, expr: &Expr, items: &[(Expr, String)]) -> Result<Expr> {
+        match expr {
+            Expr::Literal(Datum::Int(n)) => {
+                if *n < 1 || *n as usize > items.len() {
+                    return Err(Error::new(
+                        "42P10",
+                        format!("ORDER BY position {} is not in select list", n),
+                    ));
+                }
+                Ok(items[*n as usize - 1].0.clone())
+            }
+            Expr::Column { table: None, name } => {
+                let mut matches = items.iter().filter(|(_, n)| n == name);
+                match (matches.next(), matches.next()) {
+                    (Some((item, _)), None) => Ok(item.clone()),
+                    (Some(_), Some(_)) => Err(Error::ambiguous_column(name)),
+                    _ => Ok(expr.clone()),
+                }
+            }
+            _ => Ok(expr.clone()),
+        }
+    }
+
+    // the ORDER BY as (column number, order) if it only uses plain columns
+    fn wanted_order(&self, order_by: &[(Expr, SortOrder)]) -> Option<Vec<(usize, SortOrder)>> {
+        let mut wanted = Vec::new();
+        for (expr, order) in order_by {
+            match expr {
+                Expr::Column { table, name } => {
+                    let (_, c) = self.resolve(table.as_deref(), name).ok()?;
+                    wanted.push((c, *order));
+                }
+                _ => return None,
+            }
+        }
+        Some(wanted)
+    }
+
+    fn column_name(&self, column: &OutputColumn) -> String {
+        match &column.table {
+            Some(table) if self.qualify => format!("{}.{}", table, column.name),
+            _ => column.name.clone(),
+        }
+    }
+
+    // resolve the column references of `expr` to positions in `scope`
+    fn bind(&self, expr: &Expr, scope: &[OutputColumn]) -> Result<ScalarExpr> {
+        Ok(match expr {
+            Expr::Column { table, name } => {
+                let mut found = None;
+                for (i, column) in scope.iter().enumerate() {
+                    if column.name == *name && (table.is_none() || column.table == *table) {
+                        if found.is_some() {
+                            return Err(Error::ambiguous_column(name));
+                        }
+                        found = Some(i);
+                    }
+                }
+                match found {
+                    Some(index) => ScalarExpr::Column {
+                        index,
+                        name: self.column_name(&scope[index]),
+                    },
+                    // report the same error as `resolve` would
+                    None => {
+                        self.resolve(table.as_deref(), name)?;
+                        return Err(Error::undefined_column(name));
+                    }
+                }
+            }
+            Expr::Literal(datum) => ScalarExpr::Literal(datum.clone()),
+            // parameters are replaced by their values before planning
+            Expr::Param(n) => return Err(Error::new("42P02", format!("there is no parameter ${}", n))),
+            Expr::Binary { op, left, right } => ScalarExpr::Binary {
+                op: *op,
+                left: Box::new(self.bind(left, scope)?),
+                right: Box::new(self.bind(right, scope)?),
+            },
+            Expr::Unary { op, expr } => ScalarExpr::Unary {
+                op: *op,
+                expr: Box::new(self.bind(expr, scope)?),
+            },
+            Expr::IsNull { expr, negated } => ScalarExpr::IsNull {
+                expr: Box::new(self.bind(expr, scope)?),
+                negated: *negated,
+            },
+            Expr::Function { name, .. } => {
+                if expr.is_aggregate() {
+                    return Err(Error::grouping("aggregate functions are not allowed here"));
+                }
+                return Err(Error::new("42883", format!("function {}() does not exist", name)));
+            }
+        })
+    }
+
+    // bind `expr` against the output of the aggregate node of `g`,
+    // `input` is the scope of the aggregate's input
+    fn bind_grouped(&self, expr: &Expr, input: &[OutputColumn], g: &mut Grouping) -> Result<ScalarExpr> {
+        if let Some(i) = g.group_by.iter().position(|e| e == expr) {
+            return Ok(ScalarExpr::Column {
+                index: i,
+                name: format!("{}", g.bound[i]),
+            });
+        }
+        if expr.is_aggregate() {
+            let index = match g.aggs.iter().position(|(e, _)| e == expr) {
+                Some(j) => j,
+                None => {
+                    let call = self.agg_call(expr, input)?;
+                    g.aggs.push((expr.clone(), call));
+                    g.aggs.len() - 1
+                }
+            };
+            return Ok(ScalarExpr::Column {
+                index: g.group_by.len() + index,
+                name: format!("{}", g.aggs[index].1),
+            });
+        }
+        Ok(match expr {
+            Expr::Column { .. } => {
+                let bound = self.bind(expr, input)?;
+                return Err(Error::grouping(format!(
+                    "column \"{}\" must appear in the GROUP BY clause or be used in an aggregate function",
+                    bound
+                )));
+            }
+            Expr::Literal(datum) => ScalarExpr::Literal(datum.clone()),
+            Expr::Binary { op, left, right } => ScalarExpr::Binary {
+                op: *op,
+                left: Box::new(self.bind_grouped(left, input, g)?),
+                right: Box::new(self.bind_grouped(right, input, g)?),
+            },
+            Expr::Unary { op, expr } => ScalarExpr::Unary {
+                op: *op,
+                expr: Box::new(self.bind_grouped(expr, input, g)?),
+            },
+            Expr::IsNull { expr, negated } => ScalarExpr::IsNull {
+                expr: Box::new(self.bind_grouped(expr, input, g)?),
+                negated: *negated,
+            },
+            Expr::Function { .. } | Expr::Param(_) => return self.bind(expr, input),
+        })
+    }
+
+    fn agg_call(&self, expr: &Expr, input: &[OutputColumn]) -> Result<AggCall> {
+        let Expr::Function { name, args, star } = expr else {
+            unreachable!()
+        };
+        if *star {
+            if name != "count" {
+                return Err(Error::syntax(format!("{}(*) is not allowed", name)));
+            }
+            return Ok(AggCall {
+                func: AggFunc::CountStar,
+                arg: None,
+            });
+        }
+        if args.len() != 1 {
+            return Err(Error::new("42883", format!("function {} takes exactly one argument", name)));
+        }
+        if args[0].contains_aggregate() {
+            return Err(Error::grouping("aggregate function calls cannot be nested"));
+        }
+        let func = match name.as_str() {
+            "count" => AggFunc::Count,
+            "sum" => AggFunc::Sum,
+            "min" => AggFunc::Min,
+            "max" => AggFunc::Max,
+            _ => unreachable!(),
+        };
+        Ok(AggCall {
+            func,
+            arg: Some(self.bind(&args[0], input)?),
+        })
+    }
+
+    fn column_stats(&self, column: &OutputColumn) -> Option<&ColumnStats> {
+        let table = column.table.as_deref()?;
+        let rel = self.rels.iter().find(|r| r.tref.qualifier() == table)?;
+        let c = rel.table.column_index(&column.name)?;
+        rel.table.stats.columns.get(c).filter(|s| s.ndistinct > 0.0)
+    }
+
+    fn ndistinct(&self, expr: &ScalarExpr, scope: &[OutputColumn]) -> f64 {
+        match expr {
+            ScalarExpr::Column { index, .. } => match self.column_stats(&scope[*index]) {
+                Some(stats) => stats.ndistinct,
+                None => DEFAULT_NUM_DISTINCT,
+            },
+            _ => DEFAULT_NUM_DISTINCT,
+        }
+    }
+
+    // the estimated fraction of rows of `scope` satisfying `expr`
+    fn selectivity(&self, expr: &ScalarExpr, scope: &[OutputColumn]) -> f64 {
+        match expr {
+            ScalarExpr::Binary { op, left, right } => match op {
+                BinaryOp::And => self.selectivity(left, scope) * self.selectivity(right, scope),
+                BinaryOp::Or => {
+                    let (s1, s2) = (self.selectivity(left, scope), self.selectivity(right, scope));
+                    s1 + s2 - s1 * s2
+                }
+                BinaryOp::Eq | BinaryOp::NotEq => {
+                    let eq = match (left.as_ref(), right.as_ref()) {
+                        (_, ScalarExpr::Literal(Datum::Null)) | (ScalarExpr::Literal(Datum::Null), _) => 0.0,
+                        (e, ScalarExpr::Literal(_)) | (ScalarExpr::Literal(_), e) => 1.0 / self.ndistinct(e, scope),
+                        (l, r) => 1.0 / self.ndistinct(l, scope).max(self.ndistinct(r, scope)),
+                    };
+                    if *op == BinaryOp::Eq {
+                        eq
+                    } else {
+                        1.0 - eq
+                    }
+                }
+                BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => DEFAULT_INEQ_SEL,
+                _ => DEFAULT_SEL,
+            },
+            ScalarExpr::Unary { op: UnaryOp::Not, expr } => 1.0 - self.selectivity(expr, scope),
+            ScalarExpr::IsNull { expr, negated } => {
+                let null_frac = match expr.as_ref() {
+                    ScalarExpr::Column { index, .. } => match self.column_stats(&scope[*index]) {
+                        Some(stats) => stats.null_frac,
+                        None => DEFAULT_EQ_SEL,
+                    },
+                    _ => DEFAULT_EQ_SEL,
+                };
+                if *negated {
+                    1.0 - null_frac
+                } else {
+                    null_frac
+                }
+            }
+            ScalarExpr::Literal(Datum::Bool(true)) => 1.0,
+            ScalarExpr::Literal(_) => 0.0,
+            _ => DEFAULT_SEL,
+        }
+    }
+
+    fn filter(&self, input: Plan, predicate: ScalarExpr) -> Plan {
+        let rows = clamp_rows(input.rows * self.selectivity(&predicate, &input.columns));
+        Plan {
+            rows,
+            cost: input.cost + input.rows * CPU_OPERATOR_COST,
+            columns: input.columns.clone(),
+            node: PlanNode::Filter {
+                input: Box::new(input),
+                predicate,
+            },
+        }
+    }
+
+    fn aggregate(&self, input: Plan, g: &Grouping) -> Result<Plan> {
+        let mut columns = Vec::new();
+        let mut groups = 1.0;
+        for bound in &g.bound {
+            columns.push(OutputColumn {
+                table: None,
+                name: format!("{}", bound),
+                ty: type_of(bound, &input.columns),
+            });
+            groups *= self.ndistinct(bound, &input.columns);
+        }
+        for (_, call) in &g.aggs {
+            let ty = match (&call.func, &call.arg) {
+                (AggFunc::Min | AggFunc::Max, Some(arg)) => type_of(arg, &input.columns),
+                _ => DataType::Int,
+            };
+            columns.push(OutputColumn {
+                table: None,
+                name: format!("{}", call),
+                ty,
+            });
+        }
+        let rows = if g.bound.is_empty() {
+            1.0
+        } else {
+            clamp_rows(groups.min(input.rows))
+        };
+        let per_row = (g.bound.len() + g.aggs.len()) as f64 * CPU_OPERATOR_COST;
+        Ok(Plan {
+            rows,
+            cost: input.cost + input.rows * per_row + rows * CPU_TUPLE_COST,
+            columns,
+            node: PlanNode::HashAggregate {
+                input: Box::new(input),
+                group_by: g.bound.clone(),
+                aggregates: g.aggs.iter().map(|(_, call)| call.clone()).collect(),
+            },
+        })
+    }
+
+    // the cheapest plan joining every table, and whether it already
+    // returns rows in the `wanted` order
+    fn plan_joins(&self, conjuncts: &[Conjunct], wanted: Option<&[(usize, SortOrder)]>) -> Result<(Plan, bool)> {
+        if self.rels.is_empty() {
+            let plan = Plan {
+                rows: 1.0,
+                cost: 0.0,
+                columns: Vec::new(),
+                node: PlanNode::Result,
+            };
+            return Ok((plan, false));
+        }
+        let mut singles = Vec::new();
+        for r in 0..self.rels.len() {
+            let paths = self.access_paths(r, conjuncts)?;
+            let best = match wanted {
+                Some(wanted) => paths
+                    .into_iter()
+                    .map(|(plan, index)| {
+                        let ordered = index.is_some_and(|(index, prefix)| provides_order(index, prefix, wanted));
+                        let cost = if ordered {
+                            plan.cost
+                        } else {
+                            plan.cost + sort_cost(plan.rows)
+                        };
+                        (plan, ordered, cost)
+                    })
+                    .min_by(|a, b| a.2.total_cmp(&b.2))
+                    .map(|(plan, ordered, _)| (plan, ordered)),
+                None => paths
+                    .into_iter()
+                    .min_by(|a, b| a.0.cost.total_cmp(&b.0.cost))
+                    .map(|(plan, _)| (plan, false)),
+            };
+            singles.push(best.unwrap());
+        }
+        if singles.len() == 1 {
+            return Ok(singles.pop().unwrap());
+        }
+        let singles: Vec<Plan> = singles.into_iter().map(|(plan, _)| plan).collect();
+        let n = self.rels.len();
+        if n > MAX_DP_TABLES {
+            let mut plan = singles[0].clone();
+            for (t, single) in singles.iter().enumerate().skip(1) {
+                plan = self.cheapest_join(&plan, (1 << t) - 1, single, 1 << t, conjuncts)?;
+            }
+            return Ok((plan, false));
+        }
+
+        // dynamic programming over subsets of tables, building left-deep
+        // joins and avoiding cross joins whenever the query allows
+        let mut best: HashMap<u32, Plan> = HashMap::new();
+        for (t, single) in singles.iter().enumerate() {
+            best.insert(1 << t, single.clone());
+        }
+        for size in 2..=n {
+            for mask in 1u32..(1 << n) {
+                if mask.count_ones() as usize != size {
+                    continue;
+                }
+                let members: Vec<usize> = (0..n).filter(|t| mask & (1 << t) != 0).collect();
+                let connected: Vec<usize> = members
+                    .iter()
+                    .copied()
+                    .filter(|t| best.contains_key(&(mask ^ (1 << t))))
+                    .filter(|t| self.connected(conjuncts, mask ^ (1 << t), 1 << t))
+                    .collect();
+                let candidates = if connected.is_empty() {
+                    members
+                } else {
+                    connected
+                };
+                let mut cheapest: Option<Plan> = None;
+                for t in candidates {
+                    let rest = mask ^ (1 << t);
+                    let Some(left) = best.get(&rest) else {
+                        continue;
+                    };
+                    let plan = self.cheapest_join(left, rest, &singles[t], 1 << t, conjuncts)?;
+                    if cheapest.as_ref().is_none_or(|c| plan.cost < c.cost) {
+                        cheapest = Some(plan);
+                    }
+                }
+                if let Some(plan) = cheapest {
+                    best.insert(mask, plan);
+                }
+            }
+        }
+        Ok((best.remove(&((1 << n) - 1)).unwrap(), false))
+    }
+
+    fn connected(&self, conjuncts: &[Conjunct], left: u32, right: u32) -> bool {
+        conjuncts
+            .iter()
+            .any(|c| c.rels & !(left | right) == 0 && c.rels & left != 0 && c.rels & right != 0)
+    }
+
+    // the cheapest of joining `left` and `right` either way round, with
+    // either a nested loop or a hash join
+    fn cheapest_join(&self, left: &Plan, lmask: u32, right: &Plan, rmask: u32, conjuncts: &[Conjunct]) -> Result<Plan> {
+        let mut plans = self.join(left, lmask, right, rmask, conjuncts)?;
+        plans.extend(self.join(right, rmask, left, lmask, conjuncts)?);
+        Ok(plans.into_iter().min_by(|a, b| a.cost.total_cmp(&b.cost)).unwrap())
+    }
+
+    fn join(&self, left: &Plan, lmask: u32, right: &Plan, rmask: u32, conjuncts: &[Conjunct]) -> Result<Vec<Plan>> {
+        let mut columns = left.columns.clone();
+        columns.extend(right.columns.iter().cloned());
+
+        let mut conds = Vec::new();
+        let mut left_keys = Vec::new();
+        let mut right_keys = Vec::new();
+        let mut residual = Vec::new();
+        for c in conjuncts {
+            if c.rels & !(lmask | rmask) != 0 || c.rels & lmask == 0 || c.rels & rmask == 0 {
+                continue;
+            }
+            conds.push(self.bind(&c.expr, &columns)?);
+            if let Expr::Binary {
+                op: BinaryOp::Eq,
+                left: l,
+                right: r,
+            } = &c.expr
+            {
+                let (lr, rr) = (self.rels_of(l)?, self.rels_of(r)?);
+                let side = |m: u32, mask: u32| m != 0 && m & !mask == 0;
+                if side(lr, lmask) && side(rr, rmask) {
+                    left_keys.push(self.bind(l, &left.columns)?);
+                    right_keys.push(self.bind(r, &right.columns)?);
+                    continue;
+                }
+                if side(rr, lmask) && side(lr, rmask) {
+                    left_keys.push(self.bind(r, &left.columns)?);
+                    right_keys.push(self.bind(l, &right.columns)?);
+                    continue;
+                }
+            }
+            residual.push(self.bind(&c.expr, &columns)?);
+        }
+        let sel: f64 = conds.iter().map(|c| self.selectivity(c, &columns)).product();
+        let rows = clamp_rows(left.rows * right.rows * sel);
+
+        let mut plans = Vec::new();
+        let ncond = conds.len().max(1) as f64;
+        plans.push(Plan {
+            rows,
+            cost: left.cost
+                + right.cost
+                + left.rows * right.rows * ncond * CPU_OPERATOR_COST
+                + rows * CPU_TUPLE_COST,
+            columns: columns.clone(),
+            node: PlanNode::NestedLoopJoin {
+                left: Box::new(left.clone()),
+                right: Box::new(right.clone()),
+                condition: ScalarExpr::conjunction(conds),
+            },
+        });
+        if !left_keys.is_empty() {
+            let nkeys = left_keys.len() as f64;
+            plans.push(Plan {
+                rows,
+                cost: left.cost
+                    + right.cost
+                    + right.rows * (nkeys * CPU_OPERATOR_COST + CPU_TUPLE_COST)
+                    + left.rows * nkeys * CPU_OPERATOR_COST
+                    + rows * (CPU_TUPLE_COST + residual.len() as f64 * CPU_OPERATOR_COST),
+                columns,
+                node: PlanNode::HashJoin {
+                    left: Box::new(left.clone()),
+                    right: Box::new(right.clone()),
+                    left_keys,
+                    right_keys,
+                    condition: ScalarExpr::conjunction(residual),
+                },
+            });
+        }
+        Ok(plans)
+    }
+
+    // every way to read `rels[r]`: a sequential scan and one scan per
+    // usable index, the latter with the index and its equality prefix length
+    #[allow(clippy::type_complexity)]
+    fn access_paths(&self, r: usize, conjuncts: &[Conjunct]) -> Result<Vec<(Plan, Option<(&'a IndexInfo, usize)>)>> {
+        let rel = &self.rels[r];
+        let table = rel.table;
+        let columns: Vec<OutputColumn> = table
+            .columns
+            .iter()
+            .map(|c| OutputColumn {
+                table: Some(rel.tref.qualifier().to_string()),
+                name: c.name.clone(),
+                ty: c.ty,
+            })
+            .collect();
+        let mut quals = Vec::new();
+        for c in conjuncts.iter().filter(|c| c.rels == 1 << r) {
+            quals.push(self.bind(&c.expr, &columns)?);
+        }
+        let sel: f64 = quals.iter().map(|q| self.selectivity(q, &columns)).product();
+        let table_rows = table.stats.rows;
+        let pages = (table_rows / ROWS_PER_PAGE).ceil();
+        let rows = clamp_rows(table_rows * sel);
+        let alias = rel.tref.alias.clone();
+
+        let mut paths = vec![(
+            Plan {
+                rows,
+                cost: pages * SEQ_PAGE_COST + table_rows * (CPU_TUPLE_COST + quals.len() as f64 * CPU_OPERATOR_COST),
+                columns: columns.clone(),
+                node: PlanNode::SeqScan {
+                    table: table.name.clone(),
+                    alias: alias.clone(),
+                    filter: ScalarExpr::conjunction(quals.clone()),
+                },
+            },
+            None,
+        )];
+
+        for index in &table.indexes {
+            let (range, used, prefix) = match index.method {
+                IndexMethod::Btree => match_index(index, &quals, table),
+                // of no use but for an equality
+                IndexMethod::Hash => match match_hash(index, &quals, table) {
+                    Some(matched) => matched,
+                    None => continue,
+                },
+            };
+            let index_cond: Vec<ScalarExpr> = used.iter().map(|&i| quals[i].clone()).collect();
+            let filter: Vec<ScalarExpr> = (0..quals.len())
+                .filter(|i| !used.contains(i))
+                .map(|i| quals[i].clone())
+                .collect();
+            let index_sel: f64 = index_cond.iter().map(|q| self.selectivity(q, &columns)).product();
+            let matched = clamp_rows(table_rows * index_sel).min(table_rows.max(1.0));
+            // once analyzed, the index leaves holding the matched keys are
+            // read too, like genericcostestimate counts index pages
+            let index_pages = match index.stats.height > 0.0 {
+                true => (matched * index.stats.leaf_pages / index.stats.keys.max(1.0)).ceil(),
+                false => 0.0,
+            };
+            // descending the tree against hashing the value once
+            let lookup = match index.method {
+                IndexMethod::Btree => (table_rows + 1.0).log2().ceil() * CPU_OPERATOR_COST,
+                IndexMethod::Hash => CPU_OPERATOR_COST,
+            };
+            let cost = lookup
+                + index_pages * RANDOM_PAGE_COST
+                + matched.min(pages.max(1.0)) * RANDOM_PAGE_COST
+                + matched * (CPU_INDEX_TUPLE_COST + CPU_TUPLE_COST + filter.len() as f64 * CPU_OPERATOR_COST);
+            paths.push((
+                Plan {
+                    rows,
+                    cost,
+                    columns: columns.clone(),
+                    node: PlanNode::IndexScan {
+                        table: table.name.clone(),
+                        alias: alias.clone(),
+                        index: index.name.clone(),
+                        range,
+                        index_cond,
+                        filter: ScalarExpr::conjunction(filter),
+                    },
+                },
+                // a hash index returns rows in no useful order
+                (index.method == IndexMethod::Btree).then_some((index, prefix)),
+            ));
+        }
+        Ok(paths)
+    }
+}
+
+// `column op literal` of a scan qual, with the literal on the right
+fn simple_comparison(qual: &ScalarExpr, table: &Table) -> Option<(usize, BinaryOp, Datum)> {
+    let ScalarExpr::Binary { op, left, right } = qual else {
+        return None;
+    };
+    if !op.is_comparison() || *op == BinaryOp::NotEq {
+        return None;
+    }
+    let (index, op, datum) = match (left.as_ref(), right.as_ref()) {
+        (ScalarExpr::Column { index, .. }, ScalarExpr::Literal(datum)) => (*index, *op, datum),
+        (ScalarExpr::Literal(datum), ScalarExpr::Column { index, .. }) => (*index, op.commute(), datum),
+        _ => return None,
+    };
+    // comparing across types would not follow the index order
+    let same_type = matches!(
+        (table.columns[index].ty, datum),
+        (DataType::Int, Datum::Int(_)) | (DataType::Text, Datum::Text(_)) | (DataType::Bool, Datum::Bool(_))
+    );
+    if !same_type {
+        return None;
+    }
+    Some((index, op, datum.clone()))
+}
+
+// turn the quals usable by `index` into a key range: equalities on a
+// prefix of the index columns and bounds on the column after it.
+// returns the range, the positions of the used quals and the prefix length
+fn match_index(index: &IndexInfo, quals: &[ScalarExpr], table: &Table) -> (KeyRange, Vec<usize>, usize) {
+    let comparisons: Vec<Option<(usize, BinaryOp, Datum)>> =
+        quals.iter().map(|q| simple_comparison(q, table)).collect();
+    let mut prefix = Vec::new();
+    let mut used = Vec::new();
+    let mut lower = Bound::Unbounded;
+    let mut upper = Bound::Unbounded;
+    for (column, _) in &index.columns {
+        let eq = comparisons
+            .iter()
+            .position(|c| matches!(c, Some((i, BinaryOp::Eq, _)) if i == column));
+        if let Some(q) = eq {
+            prefix.push(comparisons[q].as_ref().unwrap().2.clone());
+            used.push(q);
+            continue;
+        }
+        for (q, c) in comparisons.iter().enumerate() {
+            let Some((i, op, datum)) = c else {
+                continue;
+            };
+            if i != column {
+                continue;
+            }
+            match op {
+                BinaryOp::Gt if matches!(lower, Bound::Unbounded) => lower = Bound::Excluded(datum.clone()),
+                BinaryOp::GtEq if matches!(lower, Bound::Unbounded) => lower = Bound::Included(datum.clone()),
+                BinaryOp::Lt if matches!(upper, Bound::Unbounded) => upper = Bound::Excluded(datum.clone()),
+                BinaryOp::LtEq if matches!(upper, Bound::Unbounded) => upper = Bound::Included(datum.clone()),
+                _ => continue,
+            }
+            used.push(q);
+        }
+        break;
+    }
+    let len = prefix.len();
+    (KeyRange::prefix(prefix).lower(lower).upper(upper), used, len)
+}
+
+// the key range of a hash index: an equality on its column, with the
+// position of the used qual and the prefix length
+fn match_hash(index: &IndexInfo, quals: &[ScalarExpr], table: &Table) -> Option<(KeyRange, Vec<usize>, usize)> {
+    let column = index.columns[0].0;
+    quals.iter().enumerate().find_map(|(q, qual)| match simple_comparison(qual, table) {
+        Some((i, BinaryOp::Eq, datum)) if i == column => Some((KeyRange::prefix(vec![datum]), vec![q], 1)),
+        _ => None,
+    })
+}
+
+// does a scan of `index` with `prefix` equality columns return rows in
+// the `wanted` order
+fn provides_order(index: &IndexInfo, prefix: usize, wanted: &[(usize, SortOrder)]) -> bool {
+    let mut j = 0;
+    for (pos, column) in index.columns.iter().enumerate() {
+        if j < wanted.len() && wanted[j] == *column {
+            j += 1;
+        } else if pos >= prefix {
+            break;
+        }
+    }
+    j == wanted.len()
+}
+
+fn sort_cost(rows: f64) -> f64 {
+    rows * rows.max(2.0).log2() * 2.0 * CPU_OPERATOR_COST
+}
+
+fn clamp_rows(rows: f64) -> f64 {
+    rows.max(1.0).round()
+}
+
+pub fn type_of(expr: &ScalarExpr, input: &[OutputColumn]) -> DataType {
+    match expr {
+        ScalarExpr::Column { index, .. } => input[*index].ty,
+        ScalarExpr::Literal(Datum::Int(_)) => DataType::Int,
+        ScalarExpr::Literal(Datum::Bool(_)) => DataType::Bool,
+        ScalarExpr::Literal(_) => DataType::Text,
+        ScalarExpr::Binary { op, .. } => match op {
+            BinaryOp::Plus | BinaryOp::Minus | BinaryOp::Multiply | BinaryOp::Divide => DataType::Int,
+            _ => DataType::Bool,
+        },
+        ScalarExpr::Unary { op: UnaryOp::Minus, .. } => DataType::Int,
+        ScalarExpr::Unary { op: UnaryOp::Not, .. } | ScalarExpr::IsNull { .. } => DataType::Bool,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{IndexStats, TableStats};
+    use crate::sql::parser::parse;
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::new();
+        let stmts = parse(
+            "create table users (id int, name text, age int);
+             create table orders (id int, user_id int, total int);
+             create table items (order_id int, sku text);
+             create index users_id on users (id);
+             create index orders_user on orders (user_id, total desc);",
+        )
+        .unwrap();
+        for stmt in stmts {
+            match stmt {
+                Statement::CreateTable(t) => catalog.create_table(&t).unwrap(),
+                Statement::CreateIndex(i) => {
+                    catalog.create_index(&i).unwrap();
+                }
+                _ => unreachable!(),
+            }
+        }
+        set_stats(&mut catalog, "users", 10000.0, &[10000.0, 9000.0, 80.0]);
+        set_stats(&mut catalog, "orders", 100000.0, &[100000.0, 10000.0, 5000.0]);
+        set_stats(&mut catalog, "items", 300000.0, &[100000.0, 1000.0]);
+        catalog
+    }
+
+    fn set_stats(catalog: &mut Catalog, table: &str, rows: f64, ndistinct: &[f64]) {
+        let table = catalog.table_mut(table).unwrap();
+        table.stats = TableStats {
+            rows,
+            columns: ndistinct
+                .iter()
+                .map(|&n| ColumnStats {
+                    ndistinct: n,
+                    null_frac: 0.0,
+                })
+                .collect(),
+        };
+    }
+
+    fn explain_sql(catalog: &Catalog, sql: &str) -> String {
+        let stmt = parse(sql).unwrap().remove(0);
+        explain(catalog, &stmt).unwrap().join("\n")
+    }
+
+    fn plan_sql(catalog: &Catalog, sql: &str) -> Result<Plan> {
+        let Statement::Select(select) = parse(sql).unwrap().remove(0) else {
+            panic!("not a select")
+        };
+        plan_select(catalog, &select)
+    }
+
+    #[test]
+    fn test_index_scan_for_selective_predicate() {
+        let catalog = catalog();
+        let out = explain_sql(&catalog, "select name from users where id = 42");
+        let exp = r#"
+Index Scan using users_id on users  (cost=4.05 rows=1)
+  Index Cond: (id = 42)"#;
+        assert_eq!(out, exp.trim_start());
+    }
+
+    #[test]
+    fn test_index_pages_once_analyzed() {
+        let mut catalog = catalog();
+        catalog.table_mut("users").unwrap().indexes[0].stats = IndexStats {
+            height: 3.0,
+            leaf_pages: 3000.0,
+            keys: 10000.0,
+        };
+        // one more page for the leaf holding the key
+        let out = explain_sql(&catalog, "select name from users where id = 42");
+        assert!(out.starts_with("Index Scan using users_id on users  (cost=8.05 rows=1)"), "{}", out);
+    }
+
+    #[test]
+    fn test_seq_scan_for_unselective_predicate() {
+        let catalog = catalog();
+        let out = explain_sql(&catalog, "select name from users where id > 42 and age = 30");
+        let exp = r#"
+Seq Scan on users  (cost=350.00 rows=42)
+  Filter: ((id > 42) AND (age = 30))"#;
+        assert_eq!(out, exp.trim_start());
+    }
+
+    #[test]
+    fn test_index_prefix_range() {
+        let catalog = catalog();
+        let plan = plan_sql(&catalog, "select * from orders where user_id = 7 and total > 100 and id <> 3").unwrap();
+        let PlanNode::Project { input, .. } = &plan.node else {
+            panic!("no projection")
+        };
+        let PlanNode::IndexScan {
+            index,
+            index_cond,
+            filter,
+            range,
+            ..
+        } = &input.node
+        else {
+            panic!("not an index scan: {:?}", input.node)
+        };
+        assert_eq!(index, "orders_user");
+        assert_eq!(index_cond.len(), 2);
+        assert_eq!(format!("{}", filter.as_ref().unwrap()), "(id <> 3)");
+        let range = format!("{:?}", range);
+        assert!(range.contains("prefix: [Int(7)]"), "{}", range);
+        assert!(range.contains("lower: Excluded(Int(100))"), "{}", range);
+        assert_eq!(plan.columns.len(), 3);
+    }
+
+    #[test]
+    fn test_index_provides_order() {
+        let catalog = catalog();
+        let out = explain_sql(&catalog, "select * from orders where user_id = 3 order by total desc limit 5");
+        assert!(!out.contains("Sort"), "{}", out);
+        assert!(out.contains("Index Scan using orders_user"), "{}", out);
+
+        let out = explain_sql(&catalog, "select * from orders where user_id = 3 order by total limit 5");
+        assert!(out.contains("Sort Key: total"), "{}", out);
+    }
+
+    #[test]
+    fn test_hash_index() {
+        let mut catalog = catalog();
+        let Statement::CreateIndex(create) = parse("create index users_name on users using hash (name)").unwrap().remove(0) else {
+            unreachable!()
+        };
+        catalog.create_index(&create).unwrap();
+        let out = explain_sql(&catalog, "select id from users where name = 'bob' and age > 30");
+        assert!(out.starts_with("Index Scan using users_name on users"), "{}", out);
+        assert!(out.contains("Index Cond: (name = 'bob')") && out.contains("Filter: (age > 30)"), "{}", out);
+
+        // no ranges and no order
+        let out = explain_sql(&catalog, "select id from users where name > 'bob' and name < 'bobby'");
+        assert!(!out.contains("users_name"), "{}", out);
+        let out = explain_sql(&catalog, "select name from users where name = 'bob' order by name limit 3");
+        assert!(out.contains("Sort Key: name"), "{}", out);
+    }
+
+    #[test]
+    fn test_hash_join() {
+        let catalog = catalog();
+        let out = explain_sql(
+            &catalog,
+            "select u.name, o.total from users u join orders o on u.id = o.user_id where u.age > 30",
+        );
+        let exp = r#"
+Hash Join  (cost=3949.96 rows=33330)
+  Hash Cond: (o.user_id = u.id)
+  ->  Seq Scan on orders o  (cost=3000.00 rows=100000)
+  ->  Hash  (cost=325.00 rows=3333)
+        ->  Seq Scan on users u  (cost=325.00 rows=3333)
+              Filter: (u.age > 30)"#;
+        assert_eq!(out, exp.trim_start());
+    }
+
+    #[test]
+    fn test_nested_loop_for_non_equi_join() {
+        let catalog = catalog();
+        let out = explain_sql(&catalog, "select * from users a, users b where a.id = 1 and a.age < b.age");
+        assert!(out.starts_with("Nested Loop"), "{}", out);
+        assert!(out.contains("Join Filter: (a.age < b.age)"), "{}", out);
+        assert!(out.contains("Index Scan using users_id on users a"), "{}", out);
+    }
+
+    #[test]
+    fn test_join_order_avoids_cross_join() {
+        let catalog = catalog();
+        let plan = plan_sql(
+            &catalog,
+            "select * from users, items, orders where users.id = orders.user_id and orders.id = items.order_id",
+        )
+        .unwrap();
+        let lines = plan.explain();
+        assert!(!lines.iter().any(|l| l.contains("Nested Loop")), "{:#?}", lines);
+        assert_eq!(lines.iter().filter(|l| l.contains("Hash Join")).count(), 2);
+        // the projection puts the columns back in FROM order
+        let names: Vec<&str> = plan.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["id", "name", "age", "order_id", "sku", "id", "user_id", "total"]);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let catalog = catalog();
+        let out = explain_sql(
+            &catalog,
+            "select age, count(*) from users group by age having count(*) > 1 order by 2 desc",
+        );
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].starts_with("Sort "), "{}", out);
+        assert_eq!(lines[1], "  Sort Key: count(*) DESC");
+        assert!(lines[2].starts_with("  ->  Filter "), "{}", out);
+        assert!(lines[4].starts_with("        ->  HashAggregate  (cost=350.80 rows=80)"), "{}", out);
+        assert_eq!(lines[5], "              Group Key: age");
+    }
+
+    #[test]
+    fn test_errors() {
+        let catalog = catalog();
+        let err = plan_sql(&catalog, "select * from nope").unwrap_err();
+        assert_eq!(err.code, "42P01");
+        let err = plan_sql(&catalog, "select id from users, orders").unwrap_err();
+        assert_eq!(err.code, "42702");
+        let err = plan_sql(&catalog, "select name, count(*) from users").unwrap_err();
+        assert_eq!(err.code, "42803");
+        let err = plan_sql(&catalog, "select x.id from users").unwrap_err();
+        assert_eq!(err.code, "42P01");
+        let err = plan_sql(&catalog, "select * from users where count(*) > 1").unwrap_err();
+        assert_eq!(err.code, "42803");
+    }
+
+    #[test]
+    fn test_select_without_from() {
+        let catalog = catalog();
+        let plan = plan_sql(&catalog, "select 1 + 2 as three, 'x'").unwrap();
+        assert_eq!(plan.columns[0].name, "three");
+        assert_eq!(plan.columns[0].ty, DataType::Int);
+        assert_eq!(plan.columns[1].name, "?column?");
+        assert_eq!(plan.explain(), vec!["Result  (cost=0.00 rows=1)"]);
+    }
+}
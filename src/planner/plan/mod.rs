@@ -0,0 +1,296 @@
+#![allow(dead_code)]
+
+use std::fmt::Display;
+
+use crate::btree::key::{Datum, KeyRange, SortOrder};
+use crate::sql::ast::{BinaryOp, DataType, UnaryOp};
+
+// An expression whose column references have been resolved to positions
+// in the row produced by the input of the operator evaluating it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScalarExpr {
+    // `name` is only kept for EXPLAIN
+    Column { index: usize, name: String },
+    Literal(Datum),
+    Binary {
+        op: BinaryOp,
+        left: Box<ScalarExpr>,
+        right: Box<ScalarExpr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<ScalarExpr>,
+    },
+    IsNull {
+        expr: Box<ScalarExpr>,
+        negated: bool,
+    },
+}
+
+impl ScalarExpr {
+    // AND together a list of predicates, `None` if there is none
+    pub fn conjunction(mut exprs: Vec<ScalarExpr>) -> Option<ScalarExpr> {
+        let first = if exprs.is_empty() {
+            return None;
+        } else {
+            exprs.remove(0)
+        };
+        Some(exprs.into_iter().fold(first, |acc, e| ScalarExpr::Binary {
+            op: BinaryOp::And,
+            left: Box::new(acc),
+            right: Box::new(e),
+        }))
+    }
+}
+
+impl Display for ScalarExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScalarExpr::Column { name, .. } => write!(f, "{}", name),
+            ScalarExpr::Literal(datum) => write!(f, "{}", datum),
+            ScalarExpr::Binary { op, left, right } => write!(f, "({} {} {})", left, op, right),
+            ScalarExpr::Unary { op: UnaryOp::Not, expr } => write!(f, "(NOT {})", expr),
+            ScalarExpr::Unary { op: UnaryOp::Minus, expr } => write!(f, "(- {})", expr),
+            ScalarExpr::IsNull { expr, negated: false } => write!(f, "({} IS NULL)", expr),
+            ScalarExpr::IsNull { expr, negated: true } => write!(f, "({} IS NOT NULL)", expr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggFunc {
+    CountStar,
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggCall {
+    pub func: AggFunc,
+    pub arg: Option<ScalarExpr>,
+}
+
+impl Display for AggCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self.func {
+            AggFunc::CountStar => return write!(f, "count(*)"),
+            AggFunc::Count => "count",
+            AggFunc::Sum => "sum",
+            AggFunc::Min => "min",
+            AggFunc::Max => "max",
+        };
+        write!(f, "{}({})", name, self.arg.as_ref().unwrap())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputColumn {
+    // the table alias a column reference may be qualified with
+    pub table: Option<String>,
+    pub name: String,
+    pub ty: DataType,
+}
+
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub node: PlanNode,
+    pub columns: Vec<OutputColumn>,
+    // estimated number of output rows
+    pub rows: f64,
+    // estimated total cost, in the units of the cost constants of `planner`
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone)]
+pub enum PlanNode {
+    // a single row without columns, the input of `SELECT` without `FROM`
+    Result,
+    SeqScan {
+        table: String,
+        alias: Option<String>,
+        filter: Option<ScalarExpr>,
+    },
+    IndexScan {
+        table: String,
+        alias: Option<String>,
+        index: String,
+        range: KeyRange,
+        // the predicates turned into `range`, only kept for EXPLAIN
+        index_cond: Vec<ScalarExpr>,
+        filter: Option<ScalarExpr>,
+    },
+    Filter {
+        input: Box<Plan>,
+        predicate: ScalarExpr,
+    },
+    Project {
+        input: Box<Plan>,
+        exprs: Vec<ScalarExpr>,
+    },
+    Sort {
+        input: Box<Plan>,
+        keys: Vec<(ScalarExpr, SortOrder)>,
+    },
+    // skips `offset` rows, then returns up to `count`, all without one
+    Limit {
+        input: Box<Plan>,
+        count: Option<u64>,
+        offset: u64,
+    },
+    // `condition` is evaluated on the concatenation of a left and a right row
+    NestedLoopJoin {
+        left: Box<Plan>,
+        right: Box<Plan>,
+        condition: Option<ScalarExpr>,
+    },
+    // builds a hash table on `right` and probes it with `left`
+    HashJoin {
+        left: Box<Plan>,
+        right: Box<Plan>,
+        left_keys: Vec<ScalarExpr>,
+        right_keys: Vec<ScalarExpr>,
+        condition: Option<ScalarExpr>,
+    },
+    // outputs the group by columns followed by one column per aggregate
+    HashAggregate {
+        input: Box<Plan>,
+        group_by: Vec<ScalarExpr>,
+        aggregates: Vec<AggCall>,
+    },
+}
+
+impl Plan {
+    // the lines of EXPLAIN output, formatted like PostgreSQL does
+    pub fn explain(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.explain_node(0, &mut lines);
+        lines
+    }
+
+    fn explain_node(&self, depth: usize, lines: &mut Vec<String>) {
+        // a projection is part of its input node in PostgreSQL's output
+        if let PlanNode::Project { input, .. } = &self.node {
+            return input.explain_node(depth, lines);
+        }
+        let (header, detail) = if depth == 0 {
+            (String::new(), "  ".to_string())
+        } else {
+            (format!("{}->  ", " ".repeat(6 * (depth - 1) + 2)), " ".repeat(6 * depth + 2))
+        };
+        let name = match &self.node {
+            PlanNode::Result => "Result".to_string(),
+            PlanNode::SeqScan { table, alias, .. } => format!("Seq Scan on {}{}", table, alias_suffix(alias)),
+            PlanNode::IndexScan {
+                table, alias, index, ..
+            } => format!("Index Scan using {} on {}{}", index, table, alias_suffix(alias)),
+            PlanNode::Filter { .. } => "Filter".to_string(),
+            PlanNode::Project { .. } => unreachable!(),
+            PlanNode::Sort { .. } => "Sort".to_string(),
+            PlanNode::Limit { .. } => "Limit".to_string(),
+            PlanNode::NestedLoopJoin { .. } => "Nested Loop".to_string(),
+            PlanNode::HashJoin { .. } => "Hash Join".to_string(),
+            PlanNode::HashAggregate { group_by, .. } => {
+                if group_by.is_empty() {
+                    "Aggregate".to_string()
+                } else {
+                    "HashAggregate".to_string()
+                }
+            }
+        };
+        lines.push(format!("{}{}  (cost={:.2} rows={:.0})", header, name, self.cost, self.rows));
+        let mut children: Vec<&Plan> = Vec::new();
+        match &self.node {
+            PlanNode::Result => (),
+            PlanNode::SeqScan { filter, .. } => {
+                if let Some(filter) = filter {
+                    lines.push(format!("{}Filter: {}", detail, filter));
+                }
+            }
+            PlanNode::IndexScan {
+                index_cond, filter, ..
+            } => {
+                if let Some(cond) = ScalarExpr::conjunction(index_cond.clone()) {
+                    lines.push(format!("{}Index Cond: {}", detail, cond));
+                }
+                if let Some(filter) = filter {
+                    lines.push(format!("{}Filter: {}", detail, filter));
+                }
+            }
+            PlanNode::Filter { input, predicate } => {
+                lines.push(format!("{}Filter: {}", detail, predicate));
+                children.push(input);
+            }
+            PlanNode::Project { .. } => unreachable!(),
+            PlanNode::Sort { input, keys } => {
+                let keys: Vec<String> = keys
+                    .iter()
+                    .map(|(key, order)| match order {
+                        SortOrder::Asc => format!("{}", key),
+                        SortOrder::Desc => format!("{} DESC", key),
+                    })
+                    .collect();
+                lines.push(format!("{}Sort Key: {}", detail, keys.join(", ")));
+                children.push(input);
+            }
+            PlanNode::Limit { input, .. } => children.push(input),
+            PlanNode::NestedLoopJoin {
+                left,
+                right,
+                condition,
+            } => {
+                if let Some(condition) = condition {
+                    lines.push(format!("{}Join Filter: {}", detail, condition));
+                }
+                children.push(left);
+                children.push(right);
+            }
+            PlanNode::HashJoin {
+                left,
+                right,
+                left_keys,
+                right_keys,
+                condition,
+            } => {
+                let conds: Vec<String> = left_keys
+                    .iter()
+                    .zip(right_keys)
+                    .map(|(l, r)| format!("({} = {})", l, r))
+                    .collect();
+                lines.push(format!("{}Hash Cond: {}", detail, conds.join(" AND ")));
+                if let Some(condition) = condition {
+                    lines.push(format!("{}Join Filter: {}", detail, condition));
+                }
+                left.explain_node(depth + 1, lines);
+                // the build side is shown below a `Hash` node
+                lines.push(format!(
+                    "{}->  Hash  (cost={:.2} rows={:.0})",
+                    " ".repeat(6 * depth + 2),
+                    right.cost,
+                    right.rows
+                ));
+                right.explain_node(depth + 2, lines);
+            }
+            PlanNode::HashAggregate {
+                input, group_by, ..
+            } => {
+                if !group_by.is_empty() {
+                    let keys: Vec<String> = group_by.iter().map(|k| format!("{}", k)).collect();
+                    lines.push(format!("{}Group Key: {}", detail, keys.join(", ")));
+                }
+                children.push(input);
+            }
+        }
+        for child in children {
+            child.explain_node(depth + 1, lines);
+        }
+    }
+}
+
+fn alias_suffix(alias: &Option<String>) -> String {
+    match alias {
+        Some(alias) => format!(" {}", alias),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,341 @@
+#![allow(dead_code)]
+
+// A local interactive shell: SQL statements end with `;` and may span
+// lines, backslash commands take one line.
+//
+//   \tree INDEX [dot|json|stats]
+//                    print the btree of an index, as text, graphviz or json,
+//                    or its statistics
+//   \insert N...     insert integers into the scratch tree
+//   \delete N...     delete integers from the scratch tree
+//   \find N          look an integer up in the scratch tree
+//   \print [dot|json|stats]
+//                    print the scratch tree
+//   \dt              list the tables
+//   \q               quit
+//
+// The scratch tree is a `btree::v2::Node<i64>` of its own, handy for
+// watching splits and merges happen.
+
+use std::io::{self, BufRead, Write};
+
+use crate::btree::bytes::KeyTree;
+use crate::btree::key::Datum;
+use crate::btree::v2::Node;
+use crate::engine::{Engine, QueryResult};
+use crate::error::Error;
+use crate::sql::ast::DataType;
+use crate::sql::lexer::{tokenize, Token};
+
+const HELP: &str = r"\tree INDEX [dot|json|stats]
+                 print the btree of an index, as text, graphviz or json,
+                 or its statistics
+\insert N...     insert integers into the scratch tree
+\delete N...     delete integers from the scratch tree
+\find N          look an integer up in the scratch tree
+\print [dot|json|stats]
+                 print the scratch tree
+\dt              list the tables
+\q               quit";
+
+pub struct Repl<W: Write> {
+    engine: Engine,
+    tree: Box<Node<i64>>,
+    out: W,
+    // print prompts, only wanted when a terminal is attached
+    prompt: bool,
+}
+
+impl<W: Write> Repl<W> {
+    pub fn new(out: W, prompt: bool) -> Self {
+        Self {
+            engine: Engine::new(),
+            tree: Node::new_boxed(),
+            out,
+            prompt,
+        }
+    }
+
+    // read and run statements until the input ends or `\q`
+    pub fn run(&mut self, input: impl BufRead) -> io::Result<()> {
+        let mut buf = String::new();
+        let mut lines = input.lines();
+        loop {
+            if self.prompt {
+                write!(self.out, "{}", if buf.is_empty() { "minipg=> " } else { "minipg-> " })?;
+                self.out.flush()?;
+            }
+            let Some(line) = lines.next() else {
+                break;
+            };
+            let line = line?;
+            if buf.is_empty() && line.trim_start().starts_with('\\') {
+                if !self.command(line.trim())? {
+                    return Ok(());
+                }
+                continue;
+            }
+            buf.push_str(&line);
+            buf.push('\n');
+            if statement_complete(&buf) {
+                self.sql(&buf)?;
+                buf.clear();
+            }
+        }
+        // run what is left without a trailing `;`
+        if !buf.trim().is_empty() {
+            self.sql(&buf)?;
+        }
+        Ok(())
+    }
+
+    fn sql(&mut self, sql: &str) -> io::Result<()> {
+        let stmts = match crate::sql::parser::parse(sql) {
+            Ok(stmts) => stmts,
+            Err(e) => return self.error(&e),
+        };
+        for stmt in stmts {
+            match self.engine.execute(&stmt) {
+                Ok(result) => self.print_result(&result)?,
+                Err(e) => return self.error(&e),
+            }
+        }
+        Ok(())
+    }
+
+    fn error(&mut self, e: &Error) -> io::Result<()> {
+        writeln!(self.out, "{}", e)
+    }
+
+    // run a backslash command, returns false for `\q`
+    fn command(&mut self, line: &str) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        let numbers = || -> Option<Vec<i64>> { args.iter().map(|a| a.parse().ok()).collect() };
+        match (name, args.len()) {
+            ("\\q", _) => return Ok(false),
+            ("\\?", _) => writeln!(self.out, "{}", HELP)?,
+            ("\\dt", 0) => {
+                let names: Vec<String> = self.engine.catalog.tables().map(|t| t.name.clone()).collect();
+                let rows: Vec<Vec<Datum>> = names.into_iter().map(|name| vec![Datum::Text(name)]).collect();
+                self.print_rows(&[("name".to_string(), DataType::Text)], &rows)?;
+            }
+            ("\\tree", 1 | 2) => {
+                let index = self.engine.catalog.tables().find_map(|t| {
+                    let data = self.engine.storage.table(&t.name).ok()?;
+                    data.indexes.iter().find(|i| i.name == args[0])
+                });
+                match index.map(|i| i.tree()) {
+                    Some(Some(tree)) => match render_index(tree, args.get(1)) {
+                        Some(text) => writeln!(self.out, "{}", text)?,
+                        None => writeln!(self.out, "unknown format {}, try dot, json or stats", args[1])?,
+                    },
+                    Some(None) => writeln!(self.out, "index \"{}\" is not a btree", args[0])?,
+                    None => writeln!(self.out, "index \"{}\" does not exist", args[0])?,
+                }
+            }
+            ("\\insert" | "\\delete", 1..) => match numbers() {
+                Some(keys) => {
+                    for key in keys {
+                        // the tree keeps duplicates, a set is easier to follow
+                        let present = !self.tree.range(key..=key).is_empty();
+                        match (name, present) {
+                            ("\\insert", false) => self.tree.insert(key),
+                            ("\\delete", true) => self.tree.delete(key),
+                            _ => (),
+                        }
+                    }
+                    writeln!(self.out, "{}", self.tree.to_string().trim())?;
+                }
+                None => writeln!(self.out, "{} expects integers", name)?,
+            },
+            ("\\find", 1) => match numbers() {
+                Some(keys) => {
+                    let found = !self.tree.range(keys[0]..=keys[0]).is_empty();
+                    writeln!(self.out, "{}", if found { "found" } else { "not found" })?;
+                }
+                None => writeln!(self.out, "\\find expects an integer")?,
+            },
+            ("\\print", 0 | 1) => match render(&self.tree, args.first()) {
+                Some(text) => writeln!(self.out, "{}", text)?,
+                None => writeln!(self.out, "unknown format {}, try dot, json or stats", args[0])?,
+            },
+            _ => writeln!(self.out, "invalid command {}, try \\? for help", line)?,
+        }
+        Ok(true)
+    }
+
+    fn print_result(&mut self, result: &QueryResult) -> io::Result<()> {
+        if result.columns.is_empty() {
+            return writeln!(self.out, "{}", result.tag);
+        }
+        self.print_rows(&result.columns, &result.rows)
+    }
+
+    // print rows the way psql's aligned format does
+    fn print_rows(&mut self, columns: &[(String, DataType)], rows: &[Vec<Datum>]) -> io::Result<()> {
+        let cells: Vec<Vec<String>> = rows.iter().map(|row| row.iter().map(cell).collect()).collect();
+        let widths: Vec<usize> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| {
+                cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([name.chars().count()])
+                    .max()
+                    .unwrap()
+            })
+            .collect();
+
+        let header: Vec<String> = columns
+            .iter()
+            .zip(&widths)
+            .map(|((name, _), &w)| {
+                let pad = w - name.chars().count();
+                format!("{}{}{}", " ".repeat(pad / 2), name, " ".repeat(pad - pad / 2))
+            })
+            .collect();
+        writeln!(self.out, " {} ", header.join(" | "))?;
+        let rule: Vec<String> = widths.iter().map(|&w| "-".repeat(w + 2)).collect();
+        writeln!(self.out, "{}", rule.join("+"))?;
+        for row in &cells {
+            let line: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .zip(columns)
+                .map(|((value, &w), (_, ty))| match ty {
+                    DataType::Int => format!("{:>w$}", value),
+                    _ => format!("{:<w$}", value),
+                })
+                .collect();
+            writeln!(self.out, " {}", line.join(" | ").trim_end())?;
+        }
+        match rows.len() {
+            1 => writeln!(self.out, "(1 row)")?,
+            n => writeln!(self.out, "({} rows)", n)?,
+        }
+        writeln!(self.out)
+    }
+}
+
+// how a value is shown, NULL is left empty like psql does
+fn cell(datum: &Datum) -> String {
+    match datum {
+        Datum::Null => String::new(),
+        Datum::Text(s) => s.clone(),
+        Datum::Bool(b) => if *b { "t" } else { "f" }.to_string(),
+        Datum::Int(i) => i.to_string(),
+    }
+}
+
+// a tree as text, graphviz or json, or its statistics, None for an
+// unknown format
+fn render<K: std::fmt::Display + Ord + Clone + Default>(tree: &Node<K>, format: Option<&&str>) -> Option<String> {
+    match format.copied() {
+        None => Some(tree.to_string().trim().to_string()),
+        Some("dot") => Some(tree.to_dot().trim_end().to_string()),
+        Some("json") => Some(tree.to_json()),
+        Some("stats") => Some(tree.stats().to_string()),
+        Some(_) => None,
+    }
+}
+
+// the same for the tree of an index
+fn render_index(tree: &KeyTree, format: Option<&&str>) -> Option<String> {
+    match format.copied() {
+        None => Some(tree.to_string().trim().to_string()),
+        Some("dot") => Some(tree.to_dot().trim_end().to_string()),
+        Some("json") => Some(tree.to_json()),
+        Some("stats") => Some(tree.stats().to_string()),
+        Some(_) => None,
+    }
+}
+
+// whether `buf` ends with a `;` outside of any quotes
+fn statement_complete(buf: &str) -> bool {
+    match tokenize(buf) {
+        Ok(tokens) => tokens.last() == Some(&Token::Semicolon),
+        // an unterminated quote needs more lines, other errors are
+        // reported once the statement runs
+        Err(e) => !e.message.starts_with("unterminated"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &str) -> String {
+        let mut out = Vec::new();
+        Repl::new(&mut out, false).run(input.as_bytes()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_sql() {
+        let out = run("create table t (id int, name text);\n\
+                       insert into t values (1, 'ann'),\n  (20, null);\n\
+                       select * from t\n  order by id;\n\
+                       select nope from t;\n\
+                       insert into t values (3, 'multi\nline');");
+        let exp = "CREATE TABLE
+INSERT 0 2
+ id | name 
+----+------
+  1 | ann
+ 20 |
+(2 rows)
+
+ERROR:  column \"nope\" does not exist
+INSERT 0 1
+";
+        assert_eq!(out, exp);
+    }
+
+    #[test]
+    fn test_commands() {
+        let out = run("\\insert 5 8 11 16\n\\insert 21\n\\find 8\n\\delete 8 16\n\\find 8\n\\bogus\n\\q\n\\print");
+        let exp = "[5, 8, 11, 16],
+{
+ [5, 8],
+11,
+ [16, 21],
+}
+found
+[5, 11, 21],
+not found
+invalid command \\bogus, try \\? for help
+";
+        assert_eq!(out, exp);
+
+        let out = run("create table t (a int);\ninsert into t values (3), (1);\ncreate index t_a on t (a);\n\\tree t_a\n\\tree nope");
+        assert!(out.contains("[(1, 1), (3, 0)],"));
+        assert!(out.ends_with("index \"nope\" does not exist\n"));
+
+        let out = run("\\insert 5 8\n\\print dot\n\\print json\n\\print svg");
+        let exp = r#"[5, 8],
+digraph btree {
+  node [shape=record];
+  n0 [label="5|8"];
+}
+{"height": 1, "nodes": [{"id": 0, "level": 0, "leaf": true, "keys": ["5", "8"], "fill": 0.5, "children": []}]}
+unknown format svg, try dot, json or stats
+"#;
+        assert_eq!(out, exp);
+
+        let out = run("\\insert 5 8 11 16 21\n\\print stats");
+        let exp = "nodes per level   [1, 2]
+internal nodes    1
+leaf nodes        2
+keys              5
+internal fill     [0, 1, 0, 0, 0]
+leaf fill         [0, 0, 2, 0, 0]
+avg keys          1.67
+avg leaf density  50.00
+underfull nodes   0
+";
+        assert!(out.contains(exp), "{}", out);
+    }
+}
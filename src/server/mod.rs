@@ -0,0 +1,927 @@
+#![allow(dead_code)]
+
+// The PostgreSQL frontend/backend protocol, version 3.0.
+// see https://www.postgresql.org/docs/current/protocol-flow.html
+//
+// Every connection runs in its own thread, all of them share one `Engine`.
+// Only the text and the binary formats of integer, text and boolean values
+// are supported, there is no authentication, TLS or transaction.
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::btree::key::Datum;
+use crate::engine::{self, Engine, QueryResult};
+use crate::error::{Error, Result};
+use crate::planner;
+use crate::sql::ast::{DataType, Statement};
+use crate::sql::parser::parse;
+
+const PROTOCOL_VERSION: i32 = 196608; // 3.0
+const SSL_REQUEST: i32 = 80877103;
+const GSSENC_REQUEST: i32 = 80877104;
+const CANCEL_REQUEST: i32 = 80877102;
+
+// a message larger than this is taken as a broken client
+const MAX_MESSAGE_LEN: usize = 1 << 30;
+
+// type oids, see pg_type.dat
+const BOOL_OID: i32 = 16;
+const INT8_OID: i32 = 20;
+const INT2_OID: i32 = 21;
+const INT4_OID: i32 = 23;
+const TEXT_OID: i32 = 25;
+const UNKNOWN_OID: i32 = 705;
+const VARCHAR_OID: i32 = 1043;
+
+static NEXT_PID: AtomicI32 = AtomicI32::new(1);
+
+// accept connections until the listener fails
+pub fn serve(listener: TcpListener, engine: Arc<RwLock<Engine>>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let engine = engine.clone();
+        std::thread::spawn(move || {
+            let peer = stream.peer_addr().ok();
+            if let Err(e) = Connection::new(stream, engine).and_then(|mut c| c.run()) {
+                // a client going away is not worth reporting
+                if e.kind() != io::ErrorKind::UnexpectedEof {
+                    eprintln!("connection {:?}: {}", peer, e);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+fn oid(ty: DataType) -> i32 {
+    match ty {
+        DataType::Int => INT8_OID,
+        DataType::Text => TEXT_OID,
+        DataType::Bool => BOOL_OID,
+    }
+}
+
+fn type_len(ty: DataType) -> i16 {
+    match ty {
+        DataType::Int => 8,
+        DataType::Text => -1,
+        DataType::Bool => 1,
+    }
+}
+
+// encode a value in the text(0) or binary(1) format, `None` for NULL
+fn encode(datum: &Datum, format: i16) -> Option<Vec<u8>> {
+    Some(match (datum, format) {
+        (Datum::Null, _) => return None,
+        (Datum::Int(i), 1) => i.to_be_bytes().to_vec(),
+        (Datum::Int(i), _) => i.to_string().into_bytes(),
+        (Datum::Bool(b), 1) => vec![*b as u8],
+        (Datum::Bool(b), _) => if *b { "t" } else { "f" }.as_bytes().to_vec(),
+        (Datum::Text(s), _) => s.as_bytes().to_vec(),
+    })
+}
+
+// decode a parameter value of type `oid`
+fn decode(bytes: &[u8], format: i16, oid: i32) -> Result<Datum> {
+    let invalid = |ty: &str| {
+        Error::new(
+            "22P02",
+            format!("invalid input syntax for type {}: \"{}\"", ty, String::from_utf8_lossy(bytes)),
+        )
+    };
+    if format == 1 {
+        return match (oid, bytes.len()) {
+            (INT8_OID, 8) => Ok(Datum::Int(i64::from_be_bytes(bytes.try_into().unwrap()))),
+            (INT4_OID, 4) => Ok(Datum::Int(i32::from_be_bytes(bytes.try_into().unwrap()) as i64)),
+            (INT2_OID, 2) => Ok(Datum::Int(i16::from_be_bytes(bytes.try_into().unwrap()) as i64)),
+            (BOOL_OID, 1) => Ok(Datum::Bool(bytes[0] != 0)),
+            (TEXT_OID | VARCHAR_OID | UNKNOWN_OID | 0, _) => String::from_utf8(bytes.to_vec())
+                .map(Datum::Text)
+                .map_err(|_| Error::new("22021", "invalid byte sequence for encoding \"UTF8\"")),
+            _ => Err(Error::new("08P01", "incorrect binary data format in bind parameter")),
+        };
+    }
+    let text = std::str::from_utf8(bytes).map_err(|_| Error::new("22021", "invalid byte sequence for encoding \"UTF8\""))?;
+    match oid {
+        INT8_OID | INT4_OID | INT2_OID => text.trim().parse().map(Datum::Int).map_err(|_| invalid("bigint")),
+        BOOL_OID => match text.trim().to_ascii_lowercase().as_str() {
+            "t" | "true" | "yes" | "on" | "1" => Ok(Datum::Bool(true)),
+            "f" | "false" | "no" | "off" | "0" => Ok(Datum::Bool(false)),
+            _ => Err(invalid("boolean")),
+        },
+        _ => Ok(Datum::Text(text.to_string())),
+    }
+}
+
+// the format code of column `i`: no codes means text for all, a single
+// code applies to every column
+fn format_of(formats: &[i16], i: usize) -> i16 {
+    match formats.len() {
+        0 => 0,
+        1 => formats[0],
+        _ => formats.get(i).copied().unwrap_or(0),
+    }
+}
+
+// an outgoing message
+struct Message {
+    tag: u8,
+    body: Vec<u8>,
+}
+
+impl Message {
+    fn new(tag: u8) -> Self {
+        Self { tag, body: Vec::new() }
+    }
+
+    fn i16(mut self, v: i16) -> Self {
+        self.body.extend(v.to_be_bytes());
+        self
+    }
+
+    fn i32(mut self, v: i32) -> Self {
+        self.body.extend(v.to_be_bytes());
+        self
+    }
+
+    fn str(mut self, s: &str) -> Self {
+        self.body.extend(s.as_bytes());
+        self.body.push(0);
+        self
+    }
+
+    fn bytes(mut self, b: &[u8]) -> Self {
+        self.body.extend(b);
+        self
+    }
+}
+
+// the fields of an incoming message
+struct Reader<'a> {
+    body: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn violation() -> Error {
+        Error::new("08P01", "invalid message format")
+    }
+
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        if self.body.len() < n {
+            return Err(Self::violation());
+        }
+        let (head, tail) = self.body.split_at(n);
+        self.body = tail;
+        Ok(head)
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn str(&mut self) -> Result<String> {
+        let end = self.body.iter().position(|&b| b == 0).ok_or_else(Self::violation)?;
+        let s = String::from_utf8(self.body[..end].to_vec())
+            .map_err(|_| Error::new("22021", "invalid byte sequence for encoding \"UTF8\""))?;
+        self.body = &self.body[end + 1..];
+        Ok(s)
+    }
+
+    // the number of values that follow
+    fn count(&mut self) -> Result<usize> {
+        let n = self.i16()?;
+        if n < 0 {
+            return Err(Error::new("08P01", format!("invalid count {} in message", n)));
+        }
+        Ok(n as usize)
+    }
+
+    // a count followed by that many values
+    fn i16_list(&mut self) -> Result<Vec<i16>> {
+        let n = self.count()?;
+        (0..n).map(|_| self.i16()).collect()
+    }
+}
+
+// a statement of the extended query protocol, `None` for an empty query
+struct Prepared {
+    stmt: Option<Statement>,
+    param_types: Vec<i32>,
+}
+
+// a prepared statement with bound parameters, run on its first Execute
+struct Portal {
+    stmt: Option<Statement>,
+    result_formats: Vec<i16>,
+    result: Option<QueryResult>,
+    // how many rows of `result` were sent
+    sent: usize,
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    engine: Arc<RwLock<Engine>>,
+    statements: HashMap<String, Prepared>,
+    portals: HashMap<String, Portal>,
+    // after an error of the extended protocol messages are skipped until Sync
+    skip_until_sync: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, engine: Arc<RwLock<Engine>>) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+            engine,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            skip_until_sync: false,
+        })
+    }
+
+    // the engine to read, unless a session panicked changing it and may
+    // have left it half changed
+    fn engine(&self) -> Result<RwLockReadGuard<'_, Engine>> {
+        self.engine.read().map_err(|_| unusable())
+    }
+
+    // the engine to change
+    fn engine_mut(&self) -> Result<RwLockWriteGuard<'_, Engine>> {
+        self.engine.write().map_err(|_| unusable())
+    }
+
+    // run `stmt`, holding the engine to itself only while it changes it:
+    // queries share it, and REINDEX builds the new indexes sharing it too
+    fn execute_statement(&self, stmt: &Statement) -> Result<QueryResult> {
+        match stmt {
+            _ if Engine::reads_only(stmt) => self.engine()?.query(stmt),
+            Statement::Reindex(target) => {
+                let (table, rebuilt) = self.engine()?.reindex(target)?;
+                self.engine_mut()?.swap_indexes(&table, rebuilt)
+            }
+            _ => self.engine_mut()?.execute(stmt),
+        }
+    }
+
+    fn send(&mut self, msg: Message) -> io::Result<()> {
+        self.writer.write_all(&[msg.tag])?;
+        self.writer.write_all(&(msg.body.len() as i32 + 4).to_be_bytes())?;
+        self.writer.write_all(&msg.body)
+    }
+
+    fn send_error(&mut self, e: &Error) -> io::Result<()> {
+        let msg = Message::new(b'E')
+            .bytes(b"S")
+            .str("ERROR")
+            .bytes(b"V")
+            .str("ERROR")
+            .bytes(b"C")
+            .str(e.code)
+            .bytes(b"M")
+            .str(&e.message)
+            .bytes(&[0]);
+        self.send(msg)
+    }
+
+    fn ready(&mut self) -> io::Result<()> {
+        self.send(Message::new(b'Z').bytes(b"I"))?;
+        self.writer.flush()
+    }
+
+    fn read_exact(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        if len > MAX_MESSAGE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "message too large"));
+        }
+        let mut buf = vec![0; len];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    // read a length word followed by the rest of the message
+    fn read_body(&mut self) -> io::Result<Vec<u8>> {
+        let mut len = [0; 4];
+        self.reader.read_exact(&mut len)?;
+        let len = i32::from_be_bytes(len);
+        if len < 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid message length"));
+        }
+        self.read_exact(len as usize - 4)
+    }
+
+    // the startup phase, returns whether the client wants to go on
+    fn startup(&mut self) -> io::Result<bool> {
+        loop {
+            let body = self.read_body()?;
+            let mut r = Reader { body: &body };
+            let code = r.i32().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "short startup packet"))?;
+            match code {
+                SSL_REQUEST | GSSENC_REQUEST => {
+                    self.writer.write_all(b"N")?;
+                    self.writer.flush()?;
+                }
+                CANCEL_REQUEST => return Ok(false),
+                PROTOCOL_VERSION => break,
+                _ => {
+                    let e = Error::not_supported(format!(
+                        "unsupported frontend protocol {}.{}",
+                        code >> 16,
+                        code & 0xffff
+                    ));
+                    self.send_error(&e)?;
+                    self.writer.flush()?;
+                    return Ok(false);
+                }
+            }
+        }
+        self.send(Message::new(b'R').i32(0))?;
+        for (name, value) in [
+            ("server_version", "16.0"),
+            ("server_encoding", "UTF8"),
+            ("client_encoding", "UTF8"),
+            ("DateStyle", "ISO, MDY"),
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+        ] {
+            self.send(Message::new(b'S').str(name).str(value))?;
+        }
+        let pid = NEXT_PID.fetch_add(1, Ordering::Relaxed);
+        self.send(Message::new(b'K').i32(pid).i32(0))?;
+        self.ready()?;
+        Ok(true)
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        if !self.startup()? {
+            return Ok(());
+        }
+        loop {
+            let mut tag = [0; 1];
+            self.reader.read_exact(&mut tag)?;
+            let body = self.read_body()?;
+            let mut r = Reader { body: &body };
+            let result = match tag[0] {
+                b'Q' => {
+                    self.simple_query(&mut r)?;
+                    continue;
+                }
+                b'X' => return Ok(()),
+                b'S' => {
+                    self.skip_until_sync = false;
+                    self.ready()?;
+                    continue;
+                }
+                b'H' => {
+                    self.writer.flush()?;
+                    continue;
+                }
+                _ if self.skip_until_sync => continue,
+                b'P' => self.parse(&mut r),
+                b'B' => self.bind(&mut r),
+                b'D' => self.describe(&mut r),
+                b'E' => self.execute(&mut r),
+                b'C' => self.close(&mut r),
+                other => {
+                    let e = Error::new("08P01", format!("invalid frontend message type {}", other));
+                    self.send_error(&e)?;
+                    self.writer.flush()?;
+                    return Ok(());
+                }
+            };
+            if let Err(e) = result {
+                self.send_error(&e)?;
+                self.skip_until_sync = true;
+            }
+        }
+    }
+
+    fn simple_query(&mut self, r: &mut Reader) -> io::Result<()> {
+        let result = r.str().and_then(|sql| parse(&sql));
+        match result {
+            Ok(stmts) if stmts.is_empty() => self.send(Message::new(b'I'))?,
+            Ok(stmts) => {
+                for stmt in stmts {
+                    let result = self.execute_statement(&stmt);
+                    match result {
+                        Ok(result) => {
+                            if !result.columns.is_empty() {
+                                self.row_description(&result.columns, &[])?;
+                            }
+                            for row in &result.rows {
+                                self.data_row(row, &[])?;
+                            }
+                            self.send(Message::new(b'C').str(&result.tag))?;
+                        }
+                        Err(e) => {
+                            self.send_error(&e)?;
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => self.send_error(&e)?,
+        }
+        self.ready()
+    }
+
+    fn row_description(&mut self, columns: &[(String, DataType)], formats: &[i16]) -> io::Result<()> {
+        let mut msg = Message::new(b'T').i16(columns.len() as i16);
+        for (i, (name, ty)) in columns.iter().enumerate() {
+            msg = msg
+                .str(name)
+                .i32(0) // table oid
+                .i16(0) // column number
+                .i32(oid(*ty))
+                .i16(type_len(*ty))
+                .i32(-1) // type modifier
+                .i16(format_of(formats, i));
+        }
+        self.send(msg)
+    }
+
+    fn data_row(&mut self, row: &[Datum], formats: &[i16]) -> io::Result<()> {
+        let mut msg = Message::new(b'D').i16(row.len() as i16);
+        for (i, datum) in row.iter().enumerate() {
+            msg = match encode(datum, format_of(formats, i)) {
+                Some(bytes) => msg.i32(bytes.len() as i32).bytes(&bytes),
+                None => msg.i32(-1),
+            };
+        }
+        self.send(msg)
+    }
+
+    // Errors of the extended protocol messages are reported by `run`. A
+    // failed write turns into an error too, reporting it fails again and
+    // closes the connection.
+    fn parse(&mut self, r: &mut Reader) -> Result<()> {
+        let name = r.str()?;
+        let sql = r.str()?;
+        let n = r.count()?;
+        let mut declared = Vec::with_capacity(n);
+        for _ in 0..n {
+            declared.push(r.i32()?);
+        }
+        let mut stmts = parse(&sql)?;
+        if stmts.len() > 1 {
+            return Err(Error::syntax("cannot insert multiple commands into a prepared statement"));
+        }
+        let stmt = stmts.pop();
+        let mut param_types = declared;
+        if let Some(stmt) = &stmt {
+            let inferred = planner::param_types(&self.engine()?.catalog, stmt);
+            for (i, ty) in inferred.into_iter().enumerate() {
+                let ty = ty.map(oid).unwrap_or(TEXT_OID);
+                match param_types.get_mut(i) {
+                    Some(declared) if *declared == 0 => *declared = ty,
+                    Some(_) => (),
+                    None => param_types.push(ty),
+                }
+            }
+        }
+        if !name.is_empty() && self.statements.contains_key(&name) {
+            return Err(Error::new("42P05", format!("prepared statement \"{}\" already exists", name)));
+        }
+        self.statements.insert(name, Prepared { stmt, param_types });
+        self.send(Message::new(b'1')).map_err(io_error)
+    }
+
+    fn bind(&mut self, r: &mut Reader) -> Result<()> {
+        let portal = r.str()?;
+        let name = r.str()?;
+        let formats = r.i16_list()?;
+        let n = r.count()?;
+        let mut values = Vec::with_capacity(n);
+        for _ in 0..n {
+            let len = r.i32()?;
+            values.push(if len < 0 { None } else { Some(r.take(len as usize)?.to_vec()) });
+        }
+        let result_formats = r.i16_list()?;
+
+        let prepared = self
+            .statements
+            .get(&name)
+            .ok_or_else(|| Error::new("26000", format!("prepared statement \"{}\" does not exist", name)))?;
+        if n != prepared.param_types.len() {
+            return Err(Error::new(
+                "08P01",
+                format!(
+                    "bind message supplies {} parameters, but prepared statement \"{}\" requires {}",
+                    n,
+                    name,
+                    prepared.param_types.len()
+                ),
+            ));
+        }
+        let mut params = Vec::with_capacity(n);
+        for (i, value) in values.iter().enumerate() {
+            params.push(match value {
+                Some(bytes) => decode(bytes, format_of(&formats, i), prepared.param_types[i])?,
+                None => Datum::Null,
+            });
+        }
+        let mut stmt = prepared.stmt.clone();
+        if let Some(stmt) = &mut stmt {
+            engine::bind_params(stmt, &params)?;
+        }
+        // only the unnamed portal is replaced by a new one
+        if !portal.is_empty() && self.portals.contains_key(&portal) {
+            return Err(Error::new("42P03", format!("portal \"{}\" already exists", portal)));
+        }
+        self.portals.insert(
+            portal,
+            Portal {
+                stmt,
+                result_formats,
+                result: None,
+                sent: 0,
+            },
+        );
+        self.send(Message::new(b'2')).map_err(io_error)
+    }
+
+    fn describe(&mut self, r: &mut Reader) -> Result<()> {
+        let kind = r.u8()?;
+        let name = r.str()?;
+        let (stmt, formats) = match kind {
+            b'S' => {
+                let prepared = self
+                    .statements
+                    .get(&name)
+                    .ok_or_else(|| Error::new("26000", format!("prepared statement \"{}\" does not exist", name)))?;
+                let mut msg = Message::new(b't').i16(prepared.param_types.len() as i16);
+                for &ty in &prepared.param_types {
+                    msg = msg.i32(ty);
+                }
+                let stmt = prepared.stmt.clone();
+                self.send(msg).map_err(io_error)?;
+                (stmt, Vec::new())
+            }
+            b'P' => {
+                let portal = self
+                    .portals
+                    .get(&name)
+                    .ok_or_else(|| Error::new("34000", format!("portal \"{}\" does not exist", name)))?;
+                (portal.stmt.clone(), portal.result_formats.clone())
+            }
+            _ => return Err(Reader::violation()),
+        };
+        let columns = match &stmt {
+            Some(stmt) => self.engine()?.describe(stmt)?,
+            None => Vec::new(),
+        };
+        if columns.is_empty() {
+            self.send(Message::new(b'n')).map_err(io_error)
+        } else {
+            self.row_description(&columns, &formats).map_err(io_error)
+        }
+    }
+
+    fn execute(&mut self, r: &mut Reader) -> Result<()> {
+        let name = r.str()?;
+        let max_rows = r.i32()?;
+        let mut portal = self
+            .portals
+            .remove(&name)
+            .ok_or_else(|| Error::new("34000", format!("portal \"{}\" does not exist", name)))?;
+        let Some(stmt) = &portal.stmt else {
+            self.portals.insert(name, portal);
+            return self.send(Message::new(b'I')).map_err(io_error);
+        };
+        // a portal run to completion before has nothing left to do
+        let finished = portal.result.as_ref().is_some_and(|result| portal.sent == result.rows.len());
+        if portal.result.is_none() {
+            let result = self.execute_statement(stmt);
+            match result {
+                Ok(result) => portal.result = Some(result),
+                Err(e) => {
+                    self.portals.insert(name, portal);
+                    return Err(e);
+                }
+            }
+        }
+        let result = portal.result.as_ref().unwrap();
+        let end = if max_rows > 0 {
+            result.rows.len().min(portal.sent + max_rows as usize)
+        } else {
+            result.rows.len()
+        };
+        for row in &result.rows[portal.sent..end] {
+            self.data_row(row, &portal.result_formats).map_err(io_error)?;
+        }
+        let count = end - portal.sent;
+        let done = end == result.rows.len();
+        let tag = if result.tag.starts_with("SELECT") {
+            format!("SELECT {}", count)
+        } else if finished {
+            zero_count(&result.tag)
+        } else {
+            result.tag.clone()
+        };
+        portal.sent = end;
+        if done {
+            self.send(Message::new(b'C').str(&tag)).map_err(io_error)?;
+        } else {
+            self.send(Message::new(b's')).map_err(io_error)?;
+        }
+        // a finished portal stays around, executing it again completes with
+        // no rows and a zero count
+        self.portals.insert(name, portal);
+        Ok(())
+    }
+
+    fn close(&mut self, r: &mut Reader) -> Result<()> {
+        let kind = r.u8()?;
+        let name = r.str()?;
+        match kind {
+            b'S' => {
+                self.statements.remove(&name);
+            }
+            b'P' => {
+                self.portals.remove(&name);
+            }
+            _ => return Err(Reader::violation()),
+        }
+        self.send(Message::new(b'3')).map_err(io_error)
+    }
+}
+
+// `tag` with its row count, if it has one, set to 0: "INSERT 0 0",
+// "DELETE 0"
+fn zero_count(tag: &str) -> String {
+    match tag.rsplit_once(' ') {
+        Some((command, count)) if count.parse::<u64>().is_ok() => format!("{} 0", command),
+        _ => tag.to_string(),
+    }
+}
+
+fn io_error(e: io::Error) -> Error {
+    Error::new("08006", e.to_string())
+}
+
+fn unusable() -> Error {
+    Error::new("XX000", "the database is unusable after a failure in another session")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a tiny frontend speaking just enough of the protocol for the tests
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn connect() -> Self {
+            Self::connect_to(Arc::new(RwLock::new(Engine::new())))
+        }
+
+        fn connect_to(engine: Arc<RwLock<Engine>>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            std::thread::spawn(move || serve(listener, engine));
+            let mut client = Client {
+                stream: TcpStream::connect(addr).unwrap(),
+            };
+            // SSL is refused, then a plain startup follows
+            client.stream.write_all(&8i32.to_be_bytes()).unwrap();
+            client.stream.write_all(&SSL_REQUEST.to_be_bytes()).unwrap();
+            let mut answer = [0; 1];
+            client.stream.read_exact(&mut answer).unwrap();
+            assert_eq!(&answer, b"N");
+            let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+            body.extend(b"user\0test\0\0");
+            client.stream.write_all(&(body.len() as i32 + 4).to_be_bytes()).unwrap();
+            client.stream.write_all(&body).unwrap();
+            let tags = client.until_ready();
+            assert_eq!(tags.first().unwrap().0, b'R');
+            client
+        }
+
+        fn send(&mut self, msg: Message) {
+            self.stream.write_all(&[msg.tag]).unwrap();
+            self.stream.write_all(&(msg.body.len() as i32 + 4).to_be_bytes()).unwrap();
+            self.stream.write_all(&msg.body).unwrap();
+        }
+
+        fn recv(&mut self) -> (u8, Vec<u8>) {
+            let mut header = [0; 5];
+            self.stream.read_exact(&mut header).unwrap();
+            let len = i32::from_be_bytes(header[1..].try_into().unwrap()) as usize;
+            let mut body = vec![0; len - 4];
+            self.stream.read_exact(&mut body).unwrap();
+            (header[0], body)
+        }
+
+        fn until_ready(&mut self) -> Vec<(u8, Vec<u8>)> {
+            let mut msgs = Vec::new();
+            loop {
+                let msg = self.recv();
+                let done = msg.0 == b'Z';
+                msgs.push(msg);
+                if done {
+                    return msgs;
+                }
+            }
+        }
+
+        fn query(&mut self, sql: &str) -> Vec<(u8, Vec<u8>)> {
+            self.send(Message::new(b'Q').str(sql));
+            self.until_ready()
+        }
+    }
+
+    // the values of a DataRow as text, NULL for a NULL
+    fn row(body: &[u8]) -> Vec<String> {
+        let mut r = Reader { body };
+        let n = r.i16().unwrap();
+        (0..n)
+            .map(|_| match r.i32().unwrap() {
+                -1 => "NULL".to_string(),
+                len => String::from_utf8(r.take(len as usize).unwrap().to_vec()).unwrap(),
+            })
+            .collect()
+    }
+
+    fn field(body: &[u8], code: u8) -> String {
+        let mut r = Reader { body };
+        loop {
+            let c = r.u8().unwrap();
+            let value = r.str().unwrap();
+            if c == code {
+                return value;
+            }
+        }
+    }
+
+    fn tags(msgs: &[(u8, Vec<u8>)]) -> String {
+        msgs.iter().map(|(t, _)| *t as char).collect()
+    }
+
+    #[test]
+    fn test_simple_query() {
+        let mut client = Client::connect();
+        let msgs = client.query(
+            "create table t (a int, b text, c bool); insert into t values (1, 'x', true), (2, null, false)",
+        );
+        assert_eq!(tags(&msgs), "CCZ");
+        assert_eq!(msgs[1].1, b"INSERT 0 2\0");
+
+        let msgs = client.query("select a, b, c from t order by a");
+        assert_eq!(tags(&msgs), "TDDCZ");
+        assert_eq!(row(&msgs[1].1), ["1", "x", "t"]);
+        assert_eq!(row(&msgs[2].1), ["2", "NULL", "f"]);
+        assert_eq!(msgs[3].1, b"SELECT 2\0");
+
+        let msgs = client.query("select 1; select nope from t; select 2");
+        assert_eq!(tags(&msgs), "TDCEZ");
+        assert_eq!(field(&msgs[3].1, b'C'), "42703");
+
+        assert_eq!(tags(&client.query("")), "IZ");
+        let msgs = client.query("selec 1");
+        assert_eq!(field(&msgs[0].1, b'C'), "42601");
+    }
+
+    #[test]
+    fn test_extended_query() {
+        let mut client = Client::connect();
+        client.query("create table t (a int, b text); insert into t values (1, 'x'), (2, 'y'), (3, 'z')");
+
+        // the parameter types are inferred from the columns they are compared with
+        client.send(Message::new(b'P').str("s").str("select b from t where a >= $1 order by a").i16(0));
+        client.send(Message::new(b'D').bytes(b"S").str("s"));
+        client.send(Message::new(b'S'));
+        let msgs = client.until_ready();
+        assert_eq!(tags(&msgs), "1tTZ");
+        assert_eq!(msgs[1].1, [0, 1, 0, 0, 0, INT8_OID as u8]);
+
+        // a binary parameter, fetched two rows at a time
+        client.send(
+            Message::new(b'B')
+                .str("p")
+                .str("s")
+                .i16(1)
+                .i16(1)
+                .i16(1)
+                .i32(8)
+                .bytes(&2i64.to_be_bytes())
+                .i16(0),
+        );
+        client.send(Message::new(b'E').str("p").i32(1));
+        client.send(Message::new(b'E').str("p").i32(1));
+        client.send(Message::new(b'S'));
+        let msgs = client.until_ready();
+        assert_eq!(tags(&msgs), "2DsDCZ");
+        assert_eq!(row(&msgs[1].1), ["y"]);
+        assert_eq!(row(&msgs[3].1), ["z"]);
+        assert_eq!(msgs[4].1, b"SELECT 1\0");
+
+        // an error skips everything up to Sync
+        client.send(Message::new(b'B').str("").str("s").i16(0).i16(1).i32(3).bytes(b"abc").i16(0));
+        client.send(Message::new(b'E').str("").i32(0));
+        client.send(Message::new(b'S'));
+        let msgs = client.until_ready();
+        assert_eq!(tags(&msgs), "EZ");
+        assert_eq!(field(&msgs[0].1, b'C'), "22P02");
+
+        // the unnamed statement with a text parameter
+        client.send(Message::new(b'P').str("").str("insert into t values ($1, $2)").i16(0));
+        client.send(Message::new(b'B').str("").str("").i16(0).i16(2).i32(1).bytes(b"4").i32(-1).i16(0));
+        client.send(Message::new(b'D').bytes(b"P").str(""));
+        client.send(Message::new(b'E').str("").i32(0));
+        client.send(Message::new(b'S'));
+        let msgs = client.until_ready();
+        assert_eq!(tags(&msgs), "12nCZ");
+        assert_eq!(msgs[3].1, b"INSERT 0 1\0");
+        let msgs = client.query("select count(*), count(b) from t");
+        assert_eq!(row(&msgs[1].1), ["4", "3"]);
+
+        // a finished portal executed again inserts nothing
+        client.send(Message::new(b'E').str("").i32(0));
+        client.send(Message::new(b'S'));
+        let msgs = client.until_ready();
+        assert_eq!(tags(&msgs), "CZ");
+        assert_eq!(msgs[0].1, b"INSERT 0 0\0");
+        let msgs = client.query("select count(*) from t");
+        assert_eq!(row(&msgs[1].1), ["4"]);
+
+        // a named portal is not replaced
+        client.send(Message::new(b'B').str("p").str("s").i16(0).i16(1).i32(1).bytes(b"1").i16(0));
+        client.send(Message::new(b'S'));
+        let msgs = client.until_ready();
+        assert_eq!(tags(&msgs), "EZ");
+        assert_eq!(field(&msgs[0].1, b'C'), "42P03");
+        assert_eq!(field(&msgs[0].1, b'M'), "portal \"p\" already exists");
+
+        client.send(Message::new(b'X'));
+    }
+
+    #[test]
+    fn test_negative_count() {
+        let mut client = Client::connect();
+        client.send(Message::new(b'P').str("s").str("select $1").i16(-1));
+        client.send(Message::new(b'S'));
+        let msgs = client.until_ready();
+        assert_eq!(tags(&msgs), "EZ");
+        assert_eq!(field(&msgs[0].1, b'C'), "08P01");
+
+        client.send(Message::new(b'P').str("s").str("select 1").i16(0));
+        client.send(Message::new(b'B').str("").str("s").i16(0).i16(-2).i16(0));
+        client.send(Message::new(b'B').str("").str("s").i16(-1).i16(0).i16(0));
+        client.send(Message::new(b'S'));
+        let msgs = client.until_ready();
+        assert_eq!(tags(&msgs), "1EZ");
+        assert_eq!(field(&msgs[1].1, b'C'), "08P01");
+        assert_eq!(tags(&client.query("select 1")), "TDCZ");
+    }
+
+    #[test]
+    fn test_shared_reads() {
+        let engine = Arc::new(RwLock::new(Engine::new()));
+        let mut client = Client::connect_to(engine.clone());
+        let msgs = client.query("create table t (a int); create index t_a on t (a); insert into t values (1), (2); reindex table t");
+        assert_eq!(tags(&msgs), "CCCCZ");
+        // a query runs while another session reads
+        let _reader = engine.read().unwrap();
+        assert_eq!(tags(&client.query("select a from t where a = 2; explain select a from t")), "TDCTDCZ");
+    }
+
+    #[test]
+    fn test_poisoned_engine() {
+        let engine = Arc::new(RwLock::new(Engine::new()));
+        let mut client = Client::connect_to(engine.clone());
+        assert_eq!(tags(&client.query("create table t (a int)")), "CZ");
+        let poisoner = engine.clone();
+        std::thread::spawn(move || {
+            let _engine = poisoner.write().unwrap();
+            panic!("a session fails");
+        })
+        .join()
+        .unwrap_err();
+
+        // every statement fails, but the connection stays up
+        for _ in 0..2 {
+            let msgs = client.query("select a from t");
+            assert_eq!(tags(&msgs), "EZ");
+            assert_eq!(field(&msgs[0].1, b'C'), "XX000");
+        }
+        client.send(Message::new(b'P').str("").str("select a from t where a = $1").i16(0));
+        client.send(Message::new(b'S'));
+        let msgs = client.until_ready();
+        assert_eq!(tags(&msgs), "EZ");
+        assert_eq!(field(&msgs[0].1, b'C'), "XX000");
+    }
+}
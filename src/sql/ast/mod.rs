@@ -0,0 +1,346 @@
+#![allow(dead_code)]
+
+use std::fmt::Display;
+
+use crate::btree::key::{Datum, SortOrder};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    CreateTable(CreateTable),
+    CreateIndex(CreateIndex),
+    Insert(Insert),
+    Select(Box<Select>),
+    Delete(Delete),
+    Analyze(Option<String>),
+    Reindex(Reindex),
+    Explain(Box<Statement>),
+}
+
+impl Statement {
+    // call `f` on every expression of the statement and their sub-expressions
+    pub fn visit_exprs_mut(&mut self, f: &mut dyn FnMut(&mut Expr)) {
+        match self {
+            Statement::CreateTable(_) | Statement::CreateIndex(_) | Statement::Analyze(_) | Statement::Reindex(_) => (),
+            Statement::Insert(insert) => insert.rows.iter_mut().flatten().for_each(|e| e.visit_mut(f)),
+            Statement::Delete(delete) => {
+                if let Some(e) = &mut delete.selection {
+                    e.visit_mut(f);
+                }
+            }
+            Statement::Select(select) => {
+                for item in &mut select.items {
+                    if let SelectItem::Expr { expr, .. } = item {
+                        expr.visit_mut(f);
+                    }
+                }
+                for item in &mut select.from {
+                    item.visit_exprs_mut(f);
+                }
+                for e in select.selection.iter_mut().chain(&mut select.group_by).chain(&mut select.having) {
+                    e.visit_mut(f);
+                }
+                for item in &mut select.order_by {
+                    item.expr.visit_mut(f);
+                }
+            }
+            Statement::Explain(stmt) => stmt.visit_exprs_mut(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    Int,
+    Text,
+    Bool,
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataType::Int => write!(f, "integer"),
+            DataType::Text => write!(f, "text"),
+            DataType::Bool => write!(f, "boolean"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub ty: DataType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTable {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndex {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<(String, SortOrder)>,
+    pub unique: bool,
+    // the access method of `USING ...`, btree when omitted
+    pub using: Option<String>,
+}
+
+// `REINDEX INDEX name` or `REINDEX TABLE name`, the latter rebuilding
+// every index of the table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reindex {
+    Index(String),
+    Table(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    pub table: String,
+    pub columns: Option<Vec<String>>,
+    pub rows: Vec<Vec<Expr>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    pub table: String,
+    pub selection: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub items: Vec<SelectItem>,
+    pub from: Vec<FromItem>,
+    pub selection: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    pub order_by: Vec<OrderByItem>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    Wildcard,
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableRef {
+    pub name: String,
+    pub alias: Option<String>,
+}
+
+impl TableRef {
+    // the name columns of this table are qualified with
+    pub fn qualifier(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FromItem {
+    Table(TableRef),
+    // `left [INNER] JOIN right ON on`, or `left CROSS JOIN right`
+    Join {
+        left: Box<FromItem>,
+        right: TableRef,
+        on: Option<Expr>,
+    },
+}
+
+impl FromItem {
+    fn visit_exprs_mut(&mut self, f: &mut dyn FnMut(&mut Expr)) {
+        if let FromItem::Join { left, on, .. } = self {
+            left.visit_exprs_mut(f);
+            if let Some(on) = on {
+                on.visit_mut(f);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderByItem {
+    pub expr: Expr,
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+}
+
+impl BinaryOp {
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq
+        )
+    }
+
+    // the operator with its operands swapped, `a < b` is `b > a`
+    pub fn commute(self) -> Self {
+        match self {
+            BinaryOp::Lt => BinaryOp::Gt,
+            BinaryOp::LtEq => BinaryOp::GtEq,
+            BinaryOp::Gt => BinaryOp::Lt,
+            BinaryOp::GtEq => BinaryOp::LtEq,
+            op => op,
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "<>",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+            BinaryOp::Plus => "+",
+            BinaryOp::Minus => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Minus,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column {
+        table: Option<String>,
+        name: String,
+    },
+    Literal(Datum),
+    // `$n`, replaced by the bound value before planning
+    Param(usize),
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    // a function call, `count(*)` has no args and `star` set
+    Function {
+        name: String,
+        args: Vec<Expr>,
+        star: bool,
+    },
+}
+
+impl Expr {
+    pub fn binary(op: BinaryOp, left: Expr, right: Expr) -> Self {
+        Expr::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn is_aggregate(&self) -> bool {
+        matches!(self, Expr::Function { name, .. } if is_aggregate_name(name))
+    }
+
+    pub fn contains_aggregate(&self) -> bool {
+        match self {
+            Expr::Column { .. } | Expr::Literal(_) | Expr::Param(_) => false,
+            Expr::Binary { left, right, .. } => left.contains_aggregate() || right.contains_aggregate(),
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } => expr.contains_aggregate(),
+            Expr::Function { args, .. } => {
+                self.is_aggregate() || args.iter().any(|arg| arg.contains_aggregate())
+            }
+        }
+    }
+
+    // call `f` on every sub-expression, parents before their children
+    pub fn visit_mut(&mut self, f: &mut dyn FnMut(&mut Expr)) {
+        f(self);
+        match self {
+            Expr::Column { .. } | Expr::Literal(_) | Expr::Param(_) => (),
+            Expr::Binary { left, right, .. } => {
+                left.visit_mut(f);
+                right.visit_mut(f);
+            }
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } => expr.visit_mut(f),
+            Expr::Function { args, .. } => args.iter_mut().for_each(|arg| arg.visit_mut(f)),
+        }
+    }
+
+    // split `a AND b AND c` into `[a, b, c]`
+    pub fn conjuncts(self) -> Vec<Expr> {
+        match self {
+            Expr::Binary {
+                op: BinaryOp::And,
+                left,
+                right,
+            } => {
+                let mut out = left.conjuncts();
+                out.extend(right.conjuncts());
+                out
+            }
+            expr => vec![expr],
+        }
+    }
+}
+
+pub fn is_aggregate_name(name: &str) -> bool {
+    matches!(name, "count" | "sum" | "min" | "max")
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Column { table: Some(t), name } => write!(f, "{}.{}", t, name),
+            Expr::Column { table: None, name } => write!(f, "{}", name),
+            Expr::Literal(datum) => write!(f, "{}", datum),
+            Expr::Param(n) => write!(f, "${}", n),
+            Expr::Binary { op, left, right } => write!(f, "({} {} {})", left, op, right),
+            Expr::Unary { op: UnaryOp::Not, expr } => write!(f, "(NOT {})", expr),
+            Expr::Unary { op: UnaryOp::Minus, expr } => write!(f, "(- {})", expr),
+            Expr::IsNull { expr, negated: false } => write!(f, "({} IS NULL)", expr),
+            Expr::IsNull { expr, negated: true } => write!(f, "({} IS NOT NULL)", expr),
+            Expr::Function { name, star: true, .. } => write!(f, "{}(*)", name),
+            Expr::Function { name, args, .. } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+#![allow(dead_code)]
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    // an unquoted identifier or keyword, folded to lower case
+    Word(String),
+    // a "quoted identifier", kept as written
+    QuotedIdent(String),
+    // an unsigned integer, the parser checks its range once the sign is known
+    Number(u64),
+    String(String),
+    // a parameter placeholder `$n` of the extended query protocol
+    Param(usize),
+    LParen,
+    RParen,
+    Comma,
+    Semicolon,
+    Dot,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+pub fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        // `-- comment` until the end of line
+        if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            tokens.push(Token::Word(word.to_ascii_lowercase()));
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().collect();
+            let n = digits
+                .parse()
+                .map_err(|_| Error::new("22003", format!("value \"{}\" is out of range for type integer", digits)))?;
+            tokens.push(Token::Number(n));
+            continue;
+        }
+        if c == '$' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().collect();
+            match digits.parse() {
+                Ok(n) if n > 0 => tokens.push(Token::Param(n)),
+                _ => return Err(Error::syntax(format!("syntax error at or near \"${}\"", digits))),
+            }
+            continue;
+        }
+        if c == '\'' || c == '"' {
+            let (s, next) = quoted(&chars, i)?;
+            i = next;
+            tokens.push(if c == '\'' {
+                Token::String(s)
+            } else {
+                Token::QuotedIdent(s)
+            });
+            continue;
+        }
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match (c, next) {
+            ('<', Some('=')) => (Token::LtEq, 2),
+            ('<', Some('>')) => (Token::NotEq, 2),
+            ('!', Some('=')) => (Token::NotEq, 2),
+            ('>', Some('=')) => (Token::GtEq, 2),
+            ('<', _) => (Token::Lt, 1),
+            ('>', _) => (Token::Gt, 1),
+            ('=', _) => (Token::Eq, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            (',', _) => (Token::Comma, 1),
+            (';', _) => (Token::Semicolon, 1),
+            ('.', _) => (Token::Dot, 1),
+            ('*', _) => (Token::Star, 1),
+            ('+', _) => (Token::Plus, 1),
+            ('-', _) => (Token::Minus, 1),
+            ('/', _) => (Token::Slash, 1),
+            _ => return Err(Error::syntax(format!("syntax error at or near \"{}\"", c))),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+// read a quoted string starting at `chars[start]`, a doubled quote
+// stands for the quote itself
+fn quoted(chars: &[char], start: usize) -> Result<(String, usize)> {
+    let quote = chars[start];
+    let mut s = String::new();
+    let mut i = start + 1;
+    loop {
+        match chars.get(i) {
+            None => return Err(Error::syntax("unterminated quoted string")),
+            Some(&c) if c == quote => {
+                if chars.get(i + 1) == Some(&quote) {
+                    s.push(quote);
+                    i += 2;
+                } else {
+                    return Ok((s, i + 1));
+                }
+            }
+            Some(&c) => {
+                s.push(c);
+                i += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,678 @@
+#![allow(dead_code)]
+
+use super::ast::*;
+use super::lexer::{tokenize, Token};
+use crate::btree::key::{Datum, SortOrder};
+use crate::error::{Error, Result};
+
+// words that can not be used as a bare alias
+const RESERVED: &[&str] = &[
+    "select", "from", "where", "group", "by", "having", "order", "limit", "join", "inner", "cross", "on", "as",
+    "and", "or", "not", "is", "null", "true", "false", "insert", "into", "values", "create", "table", "index",
+    "unique", "using", "delete", "explain", "asc", "desc", "analyze", "reindex", "offset",
+];
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+// parse a string of `;` separated statements
+pub fn parse(sql: &str) -> Result<Vec<Statement>> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        pos: 0,
+    };
+    let mut statements = Vec::new();
+    loop {
+        while parser.eat(&Token::Semicolon) {}
+        if parser.peek().is_none() {
+            return Ok(statements);
+        }
+        statements.push(parser.statement()?);
+        if parser.peek().is_some() && !parser.eat(&Token::Semicolon) {
+            return Err(parser.unexpected());
+        }
+    }
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn unexpected(&self) -> Error {
+        match self.peek() {
+            Some(token) => Error::syntax(format!("syntax error at or near \"{}\"", describe(token))),
+            None => Error::syntax("syntax error at end of input"),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == keyword)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Word(w)) if !RESERVED.contains(&w.as_str()) => {
+                let w = w.clone();
+                self.pos += 1;
+                Ok(w)
+            }
+            Some(Token::QuotedIdent(w)) => {
+                let w = w.clone();
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn alias(&mut self) -> Result<Option<String>> {
+        if self.eat_keyword("as") {
+            return self.ident().map(Some);
+        }
+        match self.peek() {
+            Some(Token::Word(w)) if !RESERVED.contains(&w.as_str()) => self.ident().map(Some),
+            Some(Token::QuotedIdent(_)) => self.ident().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        if self.eat_keyword("explain") {
+            return Ok(Statement::Explain(Box::new(self.statement()?)));
+        }
+        if self.eat_keyword("select") {
+            return Ok(Statement::Select(Box::new(self.select()?)));
+        }
+        if self.eat_keyword("insert") {
+            return self.insert();
+        }
+        if self.eat_keyword("delete") {
+            return self.delete();
+        }
+        if self.eat_keyword("create") {
+            return self.create();
+        }
+        if self.eat_keyword("analyze") {
+            let table = if matches!(self.peek(), Some(Token::Word(_)) | Some(Token::QuotedIdent(_))) {
+                Some(self.ident()?)
+            } else {
+                None
+            };
+            return Ok(Statement::Analyze(table));
+        }
+        if self.eat_keyword("reindex") {
+            if self.eat_keyword("index") {
+                return Ok(Statement::Reindex(Reindex::Index(self.ident()?)));
+            }
+            self.expect_keyword("table")?;
+            return Ok(Statement::Reindex(Reindex::Table(self.ident()?)));
+        }
+        Err(self.unexpected())
+    }
+
+    fn create(&mut self) -> Result<Statement> {
+        if self.eat_keyword("table") {
+            let name = self.ident()?;
+            self.expect(&Token::LParen)?;
+            let mut columns = Vec::new();
+            loop {
+                let name = self.ident()?;
+                let ty = self.data_type()?;
+                columns.push(ColumnDef { name, ty });
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(&Token::RParen)?;
+            return Ok(Statement::CreateTable(CreateTable { name, columns }));
+        }
+        let unique = self.eat_keyword("unique");
+        self.expect_keyword("index")?;
+        let name = self.ident()?;
+        self.expect_keyword("on")?;
+        let table = self.ident()?;
+        let using = if self.eat_keyword("using") {
+            Some(self.ident()?)
+        } else {
+            None
+        };
+        self.expect(&Token::LParen)?;
+        let mut columns = Vec::new();
+        loop {
+            let column = self.ident()?;
+            columns.push((column, self.sort_order()));
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RParen)?;
+        Ok(Statement::CreateIndex(CreateIndex {
+            name,
+            table,
+            columns,
+            unique,
+            using,
+        }))
+    }
+
+    fn data_type(&mut self) -> Result<DataType> {
+        let ty = match self.peek() {
+            Some(Token::Word(w)) => match w.as_str() {
+                "int" | "integer" | "bigint" | "int4" | "int8" => DataType::Int,
+                "text" | "varchar" => DataType::Text,
+                "bool" | "boolean" => DataType::Bool,
+                _ => return Err(Error::new("42704", format!("type \"{}\" does not exist", w))),
+            },
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        Ok(ty)
+    }
+
+    fn sort_order(&mut self) -> SortOrder {
+        if self.eat_keyword("desc") {
+            SortOrder::Desc
+        } else {
+            self.eat_keyword("asc");
+            SortOrder::Asc
+        }
+    }
+
+    fn insert(&mut self) -> Result<Statement> {
+        self.expect_keyword("into")?;
+        let table = self.ident()?;
+        let columns = if self.eat(&Token::LParen) {
+            let mut columns = vec![self.ident()?];
+            while self.eat(&Token::Comma) {
+                columns.push(self.ident()?);
+            }
+            self.expect(&Token::RParen)?;
+            Some(columns)
+        } else {
+            None
+        };
+        self.expect_keyword("values")?;
+        let mut rows = Vec::new();
+        loop {
+            self.expect(&Token::LParen)?;
+            rows.push(self.expr_list()?);
+            self.expect(&Token::RParen)?;
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        Ok(Statement::Insert(Insert { table, columns, rows }))
+    }
+
+    fn delete(&mut self) -> Result<Statement> {
+        self.expect_keyword("from")?;
+        let table = self.ident()?;
+        let selection = if self.eat_keyword("where") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        Ok(Statement::Delete(Delete { table, selection }))
+    }
+
+    fn select(&mut self) -> Result<Select> {
+        let mut items = Vec::new();
+        loop {
+            if self.eat(&Token::Star) {
+                items.push(SelectItem::Wildcard);
+            } else {
+                let expr = self.expr()?;
+                let alias = self.alias()?;
+                items.push(SelectItem::Expr { expr, alias });
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        let mut from = Vec::new();
+        if self.eat_keyword("from") {
+            loop {
+                from.push(self.table_expr()?);
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let selection = if self.eat_keyword("where") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let mut group_by = Vec::new();
+        if self.eat_keyword("group") {
+            self.expect_keyword("by")?;
+            group_by = self.expr_list()?;
+        }
+        let having = if self.eat_keyword("having") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let mut order_by = Vec::new();
+        if self.eat_keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let expr = self.expr()?;
+                let order = self.sort_order();
+                order_by.push(OrderByItem { expr, order });
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let limit = if self.eat_keyword("limit") { Some(self.row_count()?) } else { None };
+        let offset = if self.eat_keyword("offset") { Some(self.row_count()?) } else { None };
+        Ok(Select {
+            items,
+            from,
+            selection,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    // the number of LIMIT or OFFSET
+    fn row_count(&mut self) -> Result<u64> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(n),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected())
+            }
+        }
+    }
+
+    fn table_ref(&mut self) -> Result<TableRef> {
+        let name = self.ident()?;
+        let alias = self.alias()?;
+        Ok(TableRef { name, alias })
+    }
+
+    fn table_expr(&mut self) -> Result<FromItem> {
+        let mut item = FromItem::Table(self.table_ref()?);
+        loop {
+            if self.eat_keyword("cross") {
+                self.expect_keyword("join")?;
+                let right = self.table_ref()?;
+                item = FromItem::Join {
+                    left: Box::new(item),
+                    right,
+                    on: None,
+                };
+            } else if self.peek_keyword("join") || self.peek_keyword("inner") {
+                self.eat_keyword("inner");
+                self.expect_keyword("join")?;
+                let right = self.table_ref()?;
+                self.expect_keyword("on")?;
+                let on = self.expr()?;
+                item = FromItem::Join {
+                    left: Box::new(item),
+                    right,
+                    on: Some(on),
+                };
+            } else {
+                return Ok(item);
+            }
+        }
+    }
+
+    fn expr_list(&mut self) -> Result<Vec<Expr>> {
+        let mut exprs = vec![self.expr()?];
+        while self.eat(&Token::Comma) {
+            exprs.push(self.expr()?);
+        }
+        Ok(exprs)
+    }
+
+    pub fn expr(&mut self) -> Result<Expr> {
+        self.or_expr()
+    }
+
+    fn or_expr(&mut self) -> Result<Expr> {
+        let mut left = self.and_expr()?;
+        while self.eat_keyword("or") {
+            left = Expr::binary(BinaryOp::Or, left, self.and_expr()?);
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr> {
+        let mut left = self.not_expr()?;
+        while self.eat_keyword("and") {
+            left = Expr::binary(BinaryOp::And, left, self.not_expr()?);
+        }
+        Ok(left)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr> {
+        if self.eat_keyword("not") {
+            let expr = self.not_expr()?;
+            return Ok(Expr::Unary {
+                op: UnaryOp::Not,
+                expr: Box::new(expr),
+            });
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+        if self.eat_keyword("is") {
+            let negated = self.eat_keyword("not");
+            self.expect_keyword("null")?;
+            return Ok(Expr::IsNull {
+                expr: Box::new(left),
+                negated,
+            });
+        }
+        let op = match self.peek() {
+            Some(Token::Eq) => BinaryOp::Eq,
+            Some(Token::NotEq) => BinaryOp::NotEq,
+            Some(Token::Lt) => BinaryOp::Lt,
+            Some(Token::LtEq) => BinaryOp::LtEq,
+            Some(Token::Gt) => BinaryOp::Gt,
+            Some(Token::GtEq) => BinaryOp::GtEq,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.additive()?;
+        Ok(Expr::binary(op, left, right))
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinaryOp::Plus,
+                Some(Token::Minus) => BinaryOp::Minus,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::binary(op, left, self.multiplicative()?);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => BinaryOp::Multiply,
+                Some(Token::Slash) => BinaryOp::Divide,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::binary(op, left, self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat(&Token::Minus) {
+            // fold `-1` into a literal so that it can be used as an index bound
+            if let Some(Token::Number(n)) = self.peek() {
+                let n = -i128::from(*n);
+                self.pos += 1;
+                return int_literal(n);
+            }
+            let expr = self.unary()?;
+            return Ok(Expr::Unary {
+                op: UnaryOp::Minus,
+                expr: Box::new(expr),
+            });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(n)) => int_literal(n.into()),
+            Some(Token::String(s)) => Ok(Expr::Literal(Datum::Text(s))),
+            Some(Token::Param(n)) => Ok(Expr::Param(n)),
+            Some(Token::LParen) => {
+                let expr = self.expr()?;
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Word(w)) if w == "null" => Ok(Expr::Literal(Datum::Null)),
+            Some(Token::Word(w)) if w == "true" => Ok(Expr::Literal(Datum::Bool(true))),
+            Some(Token::Word(w)) if w == "false" => Ok(Expr::Literal(Datum::Bool(false))),
+            Some(Token::Word(_)) | Some(Token::QuotedIdent(_)) => {
+                self.pos -= 1;
+                let name = self.ident()?;
+                if self.eat(&Token::LParen) {
+                    return self.function(name);
+                }
+                if self.eat(&Token::Dot) {
+                    let column = self.ident()?;
+                    return Ok(Expr::Column {
+                        table: Some(name),
+                        name: column,
+                    });
+                }
+                Ok(Expr::Column { table: None, name })
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected())
+            }
+        }
+    }
+
+    fn function(&mut self, name: String) -> Result<Expr> {
+        if self.eat(&Token::Star) {
+            self.expect(&Token::RParen)?;
+            return Ok(Expr::Function {
+                name,
+                args: vec![],
+                star: true,
+            });
+        }
+        let args = if self.peek() == Some(&Token::RParen) {
+            vec![]
+        } else {
+            self.expr_list()?
+        };
+        self.expect(&Token::RParen)?;
+        Ok(Expr::Function {
+            name,
+            args,
+            star: false,
+        })
+    }
+}
+
+// an integer literal, negated already if it had a minus
+fn int_literal(n: i128) -> Result<Expr> {
+    match i64::try_from(n) {
+        Ok(n) => Ok(Expr::Literal(Datum::Int(n))),
+        Err(_) => Err(Error::new("22003", format!("value \"{}\" is out of range for type integer", n))),
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(w) | Token::QuotedIdent(w) => w.clone(),
+        Token::Number(n) => n.to_string(),
+        Token::String(s) => format!("'{}'", s),
+        Token::Param(n) => format!("${}", n),
+        Token::LParen => "(".into(),
+        Token::RParen => ")".into(),
+        Token::Comma => ",".into(),
+        Token::Semicolon => ";".into(),
+        Token::Dot => ".".into(),
+        Token::Star => "*".into(),
+        Token::Plus => "+".into(),
+        Token::Minus => "-".into(),
+        Token::Slash => "/".into(),
+        Token::Eq => "=".into(),
+        Token::NotEq => "<>".into(),
+        Token::Lt => "<".into(),
+        Token::LtEq => "<=".into(),
+        Token::Gt => ">".into(),
+        Token::GtEq => ">=".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn col(name: &str) -> Expr {
+        Expr::Column {
+            table: None,
+            name: name.to_string(),
+        }
+    }
+
+    fn int(i: i64) -> Expr {
+        Expr::Literal(Datum::Int(i))
+    }
+
+    #[test]
+    fn test_parse_select() {
+        let stmts = parse("SELECT a, count(*) AS n FROM t WHERE a = 1 AND b > -5 GROUP BY a ORDER BY n DESC LIMIT 10 OFFSET 5;").unwrap();
+        assert_eq!(stmts.len(), 1);
+        let Statement::Select(select) = &stmts[0] else {
+            panic!("not a select")
+        };
+        assert_eq!(select.items.len(), 2);
+        assert_eq!(
+            select.items[1],
+            SelectItem::Expr {
+                expr: Expr::Function {
+                    name: "count".into(),
+                    args: vec![],
+                    star: true
+                },
+                alias: Some("n".into())
+            }
+        );
+        assert_eq!(
+            select.selection,
+            Some(Expr::binary(
+                BinaryOp::And,
+                Expr::binary(BinaryOp::Eq, col("a"), int(1)),
+                Expr::binary(BinaryOp::Gt, col("b"), int(-5)),
+            ))
+        );
+        assert_eq!(select.group_by, vec![col("a")]);
+        assert_eq!(select.order_by[0].order, SortOrder::Desc);
+        assert_eq!((select.limit, select.offset), (Some(10), Some(5)));
+
+        // the smallest bigint has no positive counterpart
+        let stmts = parse("select -9223372036854775808").unwrap();
+        let Statement::Select(select) = &stmts[0] else {
+            panic!("not a select")
+        };
+        assert_eq!(select.items[0], SelectItem::Expr { expr: int(i64::MIN), alias: None });
+    }
+
+    #[test]
+    fn test_parse_join() {
+        let stmts = parse("select * from a x join b on x.id = b.id, c cross join d").unwrap();
+        let Statement::Select(select) = &stmts[0] else {
+            panic!("not a select")
+        };
+        assert_eq!(select.from.len(), 2);
+        let FromItem::Join { left, right, on } = &select.from[0] else {
+            panic!("not a join")
+        };
+        assert_eq!(**left, FromItem::Table(TableRef { name: "a".into(), alias: Some("x".into()) }));
+        assert_eq!(right.name, "b");
+        assert_eq!(format!("{}", on.as_ref().unwrap()), "(x.id = b.id)");
+        assert!(matches!(&select.from[1], FromItem::Join { on: None, .. }));
+    }
+
+    #[test]
+    fn test_parse_ddl_and_dml() {
+        let stmts = parse(
+            "create table t (a int, b text, c boolean);
+             create unique index t_ab on t using btree (a, b desc);
+             insert into t (a, b) values (1, 'it''s'), (2, null);
+             delete from t where not c;
+             explain select 1;
+             analyze t;
+             reindex index t_ab;
+             reindex table t",
+        )
+        .unwrap();
+        assert_eq!(stmts.len(), 8);
+        let Statement::CreateIndex(index) = &stmts[1] else {
+            panic!("not a create index")
+        };
+        assert!(index.unique);
+        assert_eq!(index.using.as_deref(), Some("btree"));
+        assert_eq!(index.columns, vec![("a".into(), SortOrder::Asc), ("b".into(), SortOrder::Desc)]);
+        let Statement::Insert(insert) = &stmts[2] else {
+            panic!("not an insert")
+        };
+        assert_eq!(insert.rows[0][1], Expr::Literal(Datum::Text("it's".into())));
+        assert!(matches!(&stmts[4], Statement::Explain(_)));
+        assert_eq!(stmts[5], Statement::Analyze(Some("t".into())));
+        assert_eq!(stmts[6], Statement::Reindex(Reindex::Index("t_ab".into())));
+        assert_eq!(stmts[7], Statement::Reindex(Reindex::Table("t".into())));
+        assert!(parse("reindex t").is_err());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = parse("select from where").unwrap_err();
+        assert_eq!(err.code, "42601");
+        assert_eq!(err.message, "syntax error at or near \"from\"");
+        let err = parse("select (1").unwrap_err();
+        assert_eq!(err.message, "syntax error at end of input");
+        let err = parse("select 9223372036854775808").unwrap_err();
+        assert_eq!(err.code, "22003");
+        assert_eq!(err.message, "value \"9223372036854775808\" is out of range for type integer");
+        let err = parse("select -9223372036854775809").unwrap_err();
+        assert_eq!(err.message, "value \"-9223372036854775809\" is out of range for type integer");
+    }
+}
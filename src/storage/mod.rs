@@ -0,0 +1,367 @@
+#![allow(dead_code)]
+
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::btree::bytes::KeyTree;
+use crate::btree::hash::HashIndex;
+use crate::btree::key::{CompositeKey, Datum, KeyRange, KeySchema, SortOrder};
+use crate::catalog::{ColumnStats, IndexInfo, IndexMethod, IndexStats, TableStats};
+use crate::error::{Error, Result};
+
+pub type Row = Vec<Datum>;
+
+// An index over some columns of a table.
+//
+// Every btree key is the indexed column values followed by the row id,
+// like nbtree uses the heap TID as the last key column, so that keys are
+// unique even when column values repeat. The keys are held encoded in a
+// `KeyTree`, whose separators stop within the first column where two
+// leaves differ, and a key that takes more than an eighth of a page is
+// refused. A hash index is over a single column and leaves out NULLs,
+// which never equal anything.
+#[derive(Debug)]
+pub struct IndexData {
+    pub name: String,
+    pub columns: Vec<usize>,
+    pub unique: bool,
+    schema: KeySchema,
+    pub access: Access,
+}
+
+#[derive(Debug)]
+pub enum Access {
+    Btree(KeyTree),
+    Hash(HashIndex<HashEntry>),
+}
+
+// An entry of a hash index, hashed by its value alone so that all rows
+// with one value share a bucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashEntry {
+    value: Datum,
+    rowid: usize,
+}
+
+impl Hash for HashEntry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state)
+    }
+}
+
+impl IndexData {
+    pub fn new(info: &IndexInfo) -> Self {
+        let mut orders: Vec<SortOrder> = info.columns.iter().map(|(_, order)| *order).collect();
+        orders.push(SortOrder::Asc);
+        Self {
+            name: info.name.clone(),
+            columns: info.columns.iter().map(|(c, _)| *c).collect(),
+            unique: info.unique,
+            schema: KeySchema::new(orders.clone()),
+            access: match info.method {
+                IndexMethod::Btree => Access::Btree(KeyTree::new(KeySchema::new(orders.clone()))),
+                IndexMethod::Hash => Access::Hash(HashIndex::new()),
+            },
+        }
+    }
+
+    // the tree of a btree index
+    pub fn tree(&self) -> Option<&KeyTree> {
+        match &self.access {
+            Access::Btree(tree) => Some(tree),
+            Access::Hash(_) => None,
+        }
+    }
+
+    // an error if the key of `row` is too long for the index
+    fn check(&self, row: &Row, rowid: usize) -> Result<()> {
+        let Some(tree) = self.tree() else {
+            return Ok(());
+        };
+        tree.check(&key(&self.schema, &self.columns, row, rowid))
+            .map_err(|e| Error::new("54000", format!("{} for index \"{}\"", e, self.name)))
+    }
+
+    // `check` must have passed for `row`
+    fn insert(&mut self, row: &Row, rowid: usize) {
+        match &mut self.access {
+            Access::Btree(tree) => {
+                tree.insert(&key(&self.schema, &self.columns, row, rowid)).unwrap();
+            }
+            Access::Hash(table) => {
+                let value = row[self.columns[0]].clone();
+                if value != Datum::Null {
+                    table.insert(HashEntry { value, rowid });
+                }
+            }
+        }
+    }
+
+    fn delete(&mut self, row: &Row, rowid: usize) {
+        match &mut self.access {
+            Access::Btree(tree) => {
+                tree.delete(&key(&self.schema, &self.columns, row, rowid));
+            }
+            Access::Hash(table) => {
+                let value = row[self.columns[0]].clone();
+                table.delete(&HashEntry { value, rowid });
+            }
+        }
+    }
+
+    // the row ids of all keys in `range`, in index order. A hash index
+    // only takes an equality on its column and returns the rows in the
+    // order of their ids.
+    pub fn scan(&self, range: &KeyRange) -> Vec<usize> {
+        match &self.access {
+            Access::Btree(tree) => tree.scan(range).iter().map(rowid).collect(),
+            Access::Hash(table) => {
+                let [value] = range.prefix_values() else {
+                    panic!("a hash index scan is for one value");
+                };
+                let mut rowids: Vec<usize> =
+                    table.lookup(value).filter(|e| e.value == *value).map(|e| e.rowid).collect();
+                rowids.sort();
+                rowids
+            }
+        }
+    }
+
+    // whether inserting `row` would duplicate a key of a unique index,
+    // NULLs never conflict
+    fn conflicts(&self, row: &Row) -> bool {
+        let Some(tree) = self.tree() else {
+            return false;
+        };
+        if !self.unique || self.columns.iter().any(|&c| row[c] == Datum::Null) {
+            return false;
+        }
+        let prefix = self.columns.iter().map(|&c| row[c].clone()).collect();
+        !tree.scan(&KeyRange::prefix(prefix)).is_empty()
+    }
+
+    // the index rebuilt densely packed, built from this one while it can
+    // still be read
+    fn compacted(&self) -> Access {
+        match &self.access {
+            Access::Btree(tree) => Access::Btree(tree.compacted()),
+            Access::Hash(table) => Access::Hash(table.compacted()),
+        }
+    }
+
+    // gather the statistics of ANALYZE
+    pub fn analyze(&self) -> IndexStats {
+        match &self.access {
+            Access::Btree(tree) => {
+                let stats = tree.stats();
+                IndexStats {
+                    height: stats.height as f64,
+                    leaf_pages: stats.leaf_nodes as f64,
+                    keys: stats.keys as f64,
+                }
+            }
+            Access::Hash(table) => IndexStats {
+                height: 1.0,
+                leaf_pages: table.pages() as f64,
+                keys: table.len() as f64,
+            },
+        }
+    }
+}
+
+// the btree key of `row`
+fn key(schema: &KeySchema, columns: &[usize], row: &Row, rowid: usize) -> CompositeKey {
+    let mut datums: Vec<Datum> = columns.iter().map(|&c| row[c].clone()).collect();
+    datums.push(Datum::Int(rowid as i64));
+    schema.key(datums)
+}
+
+fn rowid(key: &CompositeKey) -> usize {
+    match key.datums().last() {
+        Some(Datum::Int(id)) => *id as usize,
+        _ => unreachable!(),
+    }
+}
+
+// Indexes rebuilt by REINDEX while their table could still be read, to be
+// swapped in with `TableData::swap`.
+#[derive(Debug)]
+pub struct Rebuilt {
+    // the table's version they were built at
+    version: u64,
+    // the index rebuilt, all of the table's if None
+    index: Option<String>,
+    accesses: Vec<(String, Access)>,
+}
+
+// The rows of one table. A row id is the position in `rows`, deleted
+// rows leave a `None` behind so that ids stay stable.
+#[derive(Debug, Default)]
+pub struct TableData {
+    rows: Vec<Option<Row>>,
+    pub indexes: Vec<IndexData>,
+    live: usize,
+    // counts the changes to the rows
+    version: u64,
+}
+
+impl TableData {
+    pub fn get(&self, rowid: usize) -> Option<&Row> {
+        self.rows.get(rowid).and_then(|r| r.as_ref())
+    }
+
+    // one past the largest row id ever handed out
+    pub fn end(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    // live rows with their ids
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Row)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(id, row)| row.as_ref().map(|row| (id, row)))
+    }
+
+    pub fn insert(&mut self, row: Row) -> Result<usize> {
+        let id = self.rows.len();
+        for index in &self.indexes {
+            if index.conflicts(&row) {
+                return Err(Error::new(
+                    "23505",
+                    format!("duplicate key value violates unique constraint \"{}\"", index.name),
+                ));
+            }
+            index.check(&row, id)?;
+        }
+        for index in &mut self.indexes {
+            index.insert(&row, id);
+        }
+        self.rows.push(Some(row));
+        self.live += 1;
+        self.version += 1;
+        Ok(id)
+    }
+
+    // insert all of `rows` or, when one fails, none of them. The rows in
+    // already are taken out again and their ids handed out anew.
+    pub fn insert_all(&mut self, rows: Vec<Row>) -> Result<()> {
+        let start = self.rows.len();
+        for row in rows {
+            if let Err(e) = self.insert(row) {
+                for id in start..self.rows.len() {
+                    self.delete(id);
+                }
+                self.rows.truncate(start);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn delete(&mut self, rowid: usize) -> Option<Row> {
+        let row = self.rows.get_mut(rowid)?.take()?;
+        for index in &mut self.indexes {
+            index.delete(&row, rowid);
+        }
+        self.live -= 1;
+        self.version += 1;
+        Some(row)
+    }
+
+    // the indexes densely packed, `index` alone or all of them. Built from
+    // the indexes there are, this takes the table only to read.
+    pub fn rebuild(&self, index: Option<&str>) -> Rebuilt {
+        Rebuilt {
+            version: self.version,
+            index: index.map(str::to_string),
+            accesses: self
+                .indexes
+                .iter()
+                .filter(|i| index.is_none_or(|name| i.name == name))
+                .map(|i| (i.name.clone(), i.compacted()))
+                .collect(),
+        }
+    }
+
+    // swap in the indexes of `rebuild`. If rows changed since they were
+    // built they miss the changes and are built anew.
+    pub fn swap(&mut self, rebuilt: Rebuilt) {
+        let rebuilt = match rebuilt.version == self.version {
+            true => rebuilt,
+            false => self.rebuild(rebuilt.index.as_deref()),
+        };
+        for (name, access) in rebuilt.accesses {
+            if let Some(index) = self.indexes.iter_mut().find(|i| i.name == name) {
+                index.access = access;
+            }
+        }
+    }
+
+    pub fn create_index(&mut self, info: &IndexInfo) -> Result<()> {
+        let mut index = IndexData::new(info);
+        for (id, row) in self.iter() {
+            if index.conflicts(row) {
+                return Err(Error::new(
+                    "23505",
+                    format!("could not create unique index \"{}\"", info.name),
+                ));
+            }
+            index.check(row, id)?;
+            index.insert(row, id);
+        }
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    // gather the statistics of ANALYZE
+    pub fn analyze(&self, ncolumns: usize) -> TableStats {
+        let mut distinct: Vec<HashSet<&Datum>> = vec![HashSet::new(); ncolumns];
+        let mut nulls = vec![0usize; ncolumns];
+        for (_, row) in self.iter() {
+            for (c, datum) in row.iter().enumerate() {
+                if *datum == Datum::Null {
+                    nulls[c] += 1;
+                } else {
+                    distinct[c].insert(datum);
+                }
+            }
+        }
+        let rows = self.live as f64;
+        let columns = (0..ncolumns)
+            .map(|c| ColumnStats {
+                // at least 1 so that an analyzed column is told apart
+                ndistinct: (distinct[c].len() as f64).max(1.0),
+                null_frac: if self.live == 0 { 0.0 } else { nulls[c] as f64 / rows },
+            })
+            .collect();
+        TableStats { rows, columns }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Storage {
+    tables: HashMap<String, TableData>,
+}
+
+impl Storage {
+    pub fn create_table(&mut self, name: &str) {
+        self.tables.insert(name.to_string(), TableData::default());
+    }
+
+    pub fn table(&self, name: &str) -> Result<&TableData> {
+        self.tables.get(name).ok_or_else(|| Error::undefined_table(name))
+    }
+
+    pub fn table_mut(&mut self, name: &str) -> Result<&mut TableData> {
+        self.tables.get_mut(name).ok_or_else(|| Error::undefined_table(name))
+    }
+}